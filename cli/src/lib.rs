@@ -0,0 +1,6 @@
+//! The YUV4MPEG2 reading and writing of `mpeg2stinx-cli`,
+//! a library of its own so that the tests can use it.
+
+#![warn(clippy::all)]
+
+pub mod y4m;
@@ -0,0 +1,322 @@
+#![warn(clippy::all)]
+
+use mpeg2stinx_cli::y4m::{write_frame, RawFrame, Reader};
+use mpeg2stinx_core::{mpeg2stinx, BobMode, Combine, Frame, Mask, Params, Pixel, Plane, Shape};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "\
+Usage: mpeg2stinx-cli [OPTIONS] [INPUT]
+
+Applies Mpeg2Stinx to a YUV4MPEG2 stream read from INPUT, or stdin if INPUT
+is omitted or `-`, and writes the result as YUV4MPEG2 to stdout.
+
+Options:
+  -o, --output <FILE>   Write to FILE instead of stdout
+      --mode <INT>      0: PointResize, 1: Spline36Resize [default: 1]
+      --sw <INT>        Width of the min/max clipping rectangle [default: 1]
+      --sh <INT>        Height of the min/max clipping rectangle [default: 1]
+      --shape <INT>     0: rectangle, 1: diamond, 2: ellipse, 3: plus,
+                        4: column within the field, 5: --coordinates [default: 0]
+      --coordinates <LIST>
+                        Mask as comma-separated dx,dy pairs, implies --shape 5
+      --contra <INT>    Whether to use contrasharpening [default: 1]
+      --blurv <FLOAT>   Vertical blur strength [default: 0.9 if contra, else 0.0]
+      --sstr <FLOAT>    Contrasharpening strength [default: 2.0]
+      --scl <FLOAT>     Contrasharpening scale [default: 0.25]
+      --diffscl <FLOAT> Enables temporal limiting with this scale [default: off]
+      --passes <INT>    Number of repair passes [default: 2]
+      --combine <INT>   0: average, 1: weighted average, 2: median, 3: last pass
+                        [default: 0]
+      --interlaced <INT>
+                        Whether to process the fields of interlaced frames on
+                        their own and keep the output interlaced [default: 0]
+      --tff <INT>       Field order of interlaced frames
+                        [default: bottom field first if the stream says so]
+      --sw-c <INT>      sw for chroma [default: sw scaled to the subsampling]
+      --sh-c <INT>      sh for chroma [default: sh scaled to the subsampling]
+      --blurv-c <FLOAT> blurv for chroma [default: blurv]
+      --sstr-c <FLOAT>  sstr for chroma [default: sstr]
+      --scl-c <FLOAT>   scl for chroma [default: scl]
+  -h, --help            Print this message
+
+The arguments mean the same as for the VapourSynth plugin, see its README.
+The nnedi3 based modes and yadifmod (`order`) are only available in the plugin.";
+
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    params: Params,
+    /// `None` to take the field order from the stream header.
+    tff: Option<bool>,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut params = Params::default();
+    let mut blurv = None;
+    let mut shape = None;
+    let mut coordinates = None;
+    let mut tff = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-o" | "--output" => output = Some(parse_value::<String>(&arg, args.next())?),
+            "--mode" => {
+                params.mode = match parse_value::<i64>(&arg, args.next())? {
+                    0 => BobMode::Point,
+                    1 => BobMode::Spline36,
+                    2 | 3 => return Err("modes 2 and 3 need nnedi3, use the plugin".to_string()),
+                    _ => return Err("mode must be 0, 1, 2, or 3".to_string()),
+                }
+            }
+            "--sw" | "--sh" => {
+                let value = parse_value::<i64>(&arg, args.next())?;
+                if value < 0 {
+                    return Err("sw and sh must both be non-negative integers".to_string());
+                }
+                if arg == "--sw" {
+                    params.sw = value as u32;
+                } else {
+                    params.sh = value as u32;
+                }
+            }
+            "--sw-c" | "--sh-c" => {
+                let value = parse_value::<i64>(&arg, args.next())?;
+                if value < 0 {
+                    return Err("sw-c and sh-c must both be non-negative integers".to_string());
+                }
+                if arg == "--sw-c" {
+                    params.sw_c = Some(value as u32);
+                } else {
+                    params.sh_c = Some(value as u32);
+                }
+            }
+            "--shape" => shape = Some(parse_value::<i64>(&arg, args.next())?),
+            "--coordinates" => {
+                let value = parse_value::<String>(&arg, args.next())?;
+                let values = value
+                    .split(',')
+                    .map(|c| c.trim().parse::<i32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("invalid value for {}: {}", arg, value))?;
+                if values.len() % 2 != 0 {
+                    return Err("coordinates must be dx,dy pairs".to_string());
+                }
+                let offsets: Vec<_> = values.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+                coordinates = Some(Mask::new(&offsets).ok_or_else(|| {
+                    format!("coordinates must be at most {} away", Mask::MAX_RADIUS)
+                })?);
+            }
+            "--contra" => params.contra = parse_value::<i64>(&arg, args.next())? != 0,
+            "--blurv" => blurv = Some(parse_value::<f64>(&arg, args.next())?),
+            "--sstr" => params.sstr = parse_value(&arg, args.next())?,
+            "--scl" => params.scl = parse_value(&arg, args.next())?,
+            "--blurv-c" => params.blurv_c = Some(parse_value(&arg, args.next())?),
+            "--sstr-c" => params.sstr_c = Some(parse_value(&arg, args.next())?),
+            "--scl-c" => params.scl_c = Some(parse_value(&arg, args.next())?),
+            "--diffscl" => {
+                let diffscl = parse_value::<f64>(&arg, args.next())?;
+                if diffscl < 0.0 {
+                    return Err("diffscl must be a non-negative number".to_string());
+                }
+                params.diffscl = Some(diffscl);
+            }
+            "--passes" => {
+                let passes = parse_value::<u32>(&arg, args.next())?;
+                if passes == 0 {
+                    return Err("passes must be a positive integer".to_string());
+                }
+                params.passes = passes;
+            }
+            "--combine" => {
+                params.combine = match parse_value::<i64>(&arg, args.next())? {
+                    0 => Combine::Average,
+                    1 => Combine::Weighted,
+                    2 => Combine::Median,
+                    3 => Combine::Last,
+                    _ => return Err("combine must be 0, 1, 2 or 3".to_string()),
+                }
+            }
+            "--interlaced" => params.interlaced = parse_value::<i64>(&arg, args.next())? != 0,
+            "--tff" => tff = Some(parse_value::<i64>(&arg, args.next())? != 0),
+            "-" => input = Some(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    params.blurv = blurv.unwrap_or(if params.contra { 0.9 } else { 0.0 });
+    let shape = shape.unwrap_or(if coordinates.is_some() { 5 } else { 0 });
+    params.shape = match (shape, coordinates) {
+        (0, None) => Shape::Square,
+        (1, None) => Shape::Diamond,
+        (2, None) => Shape::Ellipse,
+        (3, None) => Shape::Plus,
+        (4, None) => Shape::Field,
+        (5, Some(mask)) => Shape::Mask(mask),
+        (5, None) => return Err("shape 5 requires --coordinates".to_string()),
+        (_, Some(_)) => return Err("--coordinates requires shape 5".to_string()),
+        _ => return Err("shape must be 0, 1, 2, 3, 4 or 5".to_string()),
+    };
+
+    Ok(Options {
+        input: input.filter(|input| input != "-"),
+        output,
+        params,
+        tff,
+    })
+}
+
+/// Conversion between samples and their little endian representation in the stream.
+trait Sample: Pixel {
+    fn decode(bytes: &[u8]) -> Vec<Self>;
+    fn encode(samples: &[Self], bytes: &mut Vec<u8>);
+}
+
+impl Sample for u8 {
+    fn decode(bytes: &[u8]) -> Vec<Self> {
+        bytes.to_vec()
+    }
+
+    fn encode(samples: &[Self], bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(samples);
+    }
+}
+
+impl Sample for u16 {
+    fn decode(bytes: &[u8]) -> Vec<Self> {
+        bytes
+            .chunks_exact(2)
+            .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    fn encode(samples: &[Self], bytes: &mut Vec<u8>) {
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+}
+
+fn decode_frame<T: Sample>(
+    raw: &RawFrame,
+    sizes: &[(usize, usize); 3],
+    bit_depth: u32,
+) -> Frame<T> {
+    Frame::new(
+        raw.planes
+            .iter()
+            .zip(sizes.iter())
+            .map(|(bytes, &(width, height))| Plane::from_vec(T::decode(bytes), width, height))
+            .collect(),
+        bit_depth,
+    )
+}
+
+fn encode_frame<T: Sample>(frame: &Frame<T>, params: Vec<u8>) -> RawFrame {
+    let mut planes: [Vec<u8>; 3] = Default::default();
+    for (bytes, plane) in planes.iter_mut().zip(frame.planes.iter()) {
+        T::encode(plane.data(), bytes);
+    }
+    RawFrame { params, planes }
+}
+
+fn process<T: Sample, R: BufRead, W: Write>(
+    reader: &mut Reader<R>,
+    writer: &mut W,
+    params: &Params,
+) -> io::Result<()> {
+    let sizes = reader.header.plane_sizes();
+    let bit_depth = reader.header.bit_depth;
+    let read = |reader: &mut Reader<R>| -> io::Result<Option<(Frame<T>, Vec<u8>)>> {
+        Ok(reader
+            .read_frame()?
+            .map(|raw| (decode_frame(&raw, &sizes, bit_depth), raw.params)))
+    };
+
+    // Neighbouring frames are clamped to the clip, like frame requests in VapourSynth
+    let mut prev: Option<Frame<T>> = None;
+    let mut cur = read(reader)?;
+    while let Some((frame, frame_params)) = cur {
+        let next = read(reader)?;
+        let output = mpeg2stinx(
+            prev.as_ref().unwrap_or(&frame),
+            &frame,
+            next.as_ref().map(|(next, _)| next).unwrap_or(&frame),
+            params,
+        );
+        write_frame(writer, &encode_frame(&output, frame_params))?;
+        prev = Some(frame);
+        cur = next;
+    }
+    writer.flush()
+}
+
+fn run(mut options: Options) -> io::Result<()> {
+    let input: Box<dyn BufRead> = match options.input {
+        Some(ref path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let mut output: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    let mut reader = Reader::new(input)?;
+    // Every plane gets separated into fields
+    if reader
+        .header
+        .plane_sizes()
+        .iter()
+        .any(|&(_, height)| height % 2 != 0)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the height of every plane must be even",
+        ));
+    }
+    options.params.tff = options
+        .tff
+        .unwrap_or_else(|| !reader.header.is_bottom_field_first());
+    let mut header = reader.header.clone();
+    if options.params.interlaced {
+        header.set_interlaced(options.params.tff);
+    } else {
+        header.set_progressive();
+    }
+    header.write(&mut output)?;
+
+    if reader.header.bytes_per_sample() == 1 {
+        process::<u8, _, _>(&mut reader, &mut output, &options.params)
+    } else {
+        process::<u16, _, _>(&mut reader, &mut output, &options.params)
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("mpeg2stinx-cli: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(options) {
+        eprintln!("mpeg2stinx-cli: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, BufRead, Write};
+
+const FILE_MAGIC: &[u8] = b"YUV4MPEG2";
+const FRAME_MAGIC: &[u8] = b"FRAME";
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// The stream parameters of a YUV4MPEG2 file.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    pub ss_w: usize,
+    pub ss_h: usize,
+    pub bit_depth: u32,
+    // Written back verbatim, to keep the chroma siting of e.g. `C420mpeg2`
+    colorspace: String,
+    // Everything other than the size and colorspace, passed through verbatim
+    params: Vec<String>,
+}
+
+impl Header {
+    fn parse(line: &str) -> io::Result<Self> {
+        let mut width = None;
+        let mut height = None;
+        let mut colorspace = "420".to_string();
+        let mut params = Vec::new();
+        for token in line.split_whitespace() {
+            // Each parameter starts with a one letter tag
+            if !token.as_bytes()[0].is_ascii_alphabetic() {
+                return Err(invalid_data(format!("invalid header parameter {}", token)));
+            }
+            let (key, value) = token.split_at(1);
+            match key {
+                "W" => width = Some(value.parse().map_err(invalid_data)?),
+                "H" => height = Some(value.parse().map_err(invalid_data)?),
+                "C" => colorspace = value.to_string(),
+                _ => params.push(token.to_string()),
+            }
+        }
+
+        // Only a trailing `p` and digits give the bit depth, as in `420p10`,
+        // the `p` of `420jpeg`, `420mpeg2` or `420paldv` belongs to the chroma siting
+        let (subsampling, bit_depth) = match colorspace.rfind('p') {
+            Some(pos)
+                if pos + 1 < colorspace.len()
+                    && colorspace[pos + 1..].bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                (
+                    &colorspace[..pos],
+                    colorspace[pos + 1..].parse().map_err(invalid_data)?,
+                )
+            }
+            _ => (colorspace.as_str(), 8),
+        };
+        let (ss_w, ss_h) = match subsampling {
+            "420" | "420jpeg" | "420mpeg2" | "420paldv" => (1, 1),
+            "422" => (1, 0),
+            "444" => (0, 0),
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported colorspace C{}",
+                    colorspace
+                )))
+            }
+        };
+        if !(8..=16).contains(&bit_depth) {
+            return Err(invalid_data(format!("unsupported bit depth {}", bit_depth)));
+        }
+
+        Ok(Header {
+            width: width.ok_or_else(|| invalid_data("missing width"))?,
+            height: height.ok_or_else(|| invalid_data("missing height"))?,
+            ss_w,
+            ss_h,
+            bit_depth,
+            colorspace,
+            params,
+        })
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The width and height of each of the three planes.
+    pub fn plane_sizes(&self) -> [(usize, usize); 3] {
+        let chroma = (
+            (self.width + (1 << self.ss_w) - 1) >> self.ss_w,
+            (self.height + (1 << self.ss_h) - 1) >> self.ss_h,
+        );
+        [(self.width, self.height), chroma, chroma]
+    }
+
+    /// Whether the stream is flagged as interlaced bottom field first.
+    pub fn is_bottom_field_first(&self) -> bool {
+        self.params.iter().any(|param| param == "Ib")
+    }
+
+    /// Marks the stream as progressive, since that is what the filter outputs
+    /// unless it processes interlaced frames.
+    pub fn set_progressive(&mut self) {
+        self.set_interlacing("Ip");
+    }
+
+    /// Marks the stream as interlaced in the given field order.
+    pub fn set_interlaced(&mut self, tff: bool) {
+        self.set_interlacing(if tff { "It" } else { "Ib" });
+    }
+
+    fn set_interlacing(&mut self, param: &str) {
+        self.params.retain(|param| !param.starts_with('I'));
+        self.params.push(param.to_string());
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "YUV4MPEG2 W{} H{} C{}",
+            self.width, self.height, self.colorspace
+        )?;
+        for param in &self.params {
+            write!(writer, " {}", param)?;
+        }
+        writeln!(writer)
+    }
+}
+
+/// The raw, little endian plane data of one frame.
+pub struct RawFrame {
+    pub params: Vec<u8>,
+    pub planes: [Vec<u8>; 3],
+}
+
+pub struct Reader<R> {
+    inner: R,
+    pub header: Header,
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let line = read_line(&mut inner)?.ok_or_else(|| invalid_data("empty input"))?;
+        if !line.starts_with(FILE_MAGIC) {
+            return Err(invalid_data("input is not a YUV4MPEG2 stream"));
+        }
+        let header = Header::parse(&String::from_utf8_lossy(&line[FILE_MAGIC.len()..]))?;
+        Ok(Reader { inner, header })
+    }
+
+    /// Reads the next frame, or `None` at the end of the stream.
+    pub fn read_frame(&mut self) -> io::Result<Option<RawFrame>> {
+        let line = match read_line(&mut self.inner)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if !line.starts_with(FRAME_MAGIC) {
+            return Err(invalid_data("missing FRAME marker"));
+        }
+
+        let bytes = self.header.bytes_per_sample();
+        let mut planes: [Vec<u8>; 3] = Default::default();
+        for (plane, (width, height)) in planes.iter_mut().zip(self.header.plane_sizes().iter()) {
+            plane.resize(width * height * bytes, 0);
+            self.inner.read_exact(plane)?;
+        }
+        Ok(Some(RawFrame {
+            params: line[FRAME_MAGIC.len()..].to_vec(),
+            planes,
+        }))
+    }
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, frame: &RawFrame) -> io::Result<()> {
+    writer.write_all(FRAME_MAGIC)?;
+    writer.write_all(&frame.params)?;
+    writer.write_all(b"\n")?;
+    for plane in &frame.planes {
+        writer.write_all(plane)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! The tool run end to end on a Y4M stream, compared with calling
+//! `mpeg2stinx_core::mpeg2stinx` on the same frames directly.
+
+use mpeg2stinx_cli::y4m::Reader;
+use mpeg2stinx_core::{mpeg2stinx, Frame, Params, Pixel, Plane};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 16;
+const FRAMES: usize = 4;
+
+/// Combed noise: rows of alternating fields come from different seeds.
+fn noise_frame<T: Pixel>(bit_depth: u32, seed: u64) -> Frame<T> {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        T::from_i64(((state >> 32) & ((1 << bit_depth) - 1)) as i64)
+    };
+    let planes = [
+        (WIDTH, HEIGHT),
+        (WIDTH / 2, HEIGHT / 2),
+        (WIDTH / 2, HEIGHT / 2),
+    ]
+    .iter()
+    .map(|&(width, height)| Plane::from_fn(width, height, |_, _| next()))
+    .collect();
+    Frame::new(planes, bit_depth)
+}
+
+fn to_bytes<T: Pixel>(frame: &Frame<T>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for plane in &frame.planes {
+        for &sample in plane.data() {
+            let sample = sample.to_i64();
+            if frame.bit_depth > 8 {
+                bytes.extend_from_slice(&(sample as u16).to_le_bytes());
+            } else {
+                bytes.push(sample as u8);
+            }
+        }
+    }
+    bytes
+}
+
+/// Runs the tool on `input` with `args`, returning its output.
+fn run_cli(args: &[&str], input: Vec<u8>) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mpeg2stinx-cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Written from another thread, so a full stdout pipe can't block us
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&input).unwrap());
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    assert!(output.status.success(), "{:?} failed", args);
+    output.stdout
+}
+
+fn check_matches_core<T: Pixel>(bit_depth: u32, args: &[&str], params: &Params) {
+    let frames: Vec<Frame<T>> = (0..FRAMES as u64)
+        .map(|seed| noise_frame(bit_depth, seed))
+        .collect();
+    let colorspace = if bit_depth > 8 {
+        format!("420p{}", bit_depth)
+    } else {
+        "420mpeg2".to_string()
+    };
+    let mut input = format!(
+        "YUV4MPEG2 W{} H{} C{} F30000:1001 Ip\n",
+        WIDTH, HEIGHT, colorspace
+    )
+    .into_bytes();
+    for frame in &frames {
+        input.extend_from_slice(b"FRAME\n");
+        input.extend(to_bytes(frame));
+    }
+
+    let output = run_cli(args, input);
+    let mut reader = Reader::new(&output[..]).unwrap();
+    assert_eq!(reader.header.bit_depth, bit_depth);
+    for n in 0..FRAMES {
+        let raw = reader.read_frame().unwrap().expect("too few frames");
+        // Neighbours are clamped to the clip
+        let expected = mpeg2stinx(
+            &frames[n.saturating_sub(1)],
+            &frames[n],
+            &frames[(n + 1).min(FRAMES - 1)],
+            params,
+        );
+        assert!(
+            raw.planes.concat() == to_bytes(&expected),
+            "{:?} differs at frame {}",
+            args,
+            n
+        );
+    }
+    assert!(reader.read_frame().unwrap().is_none(), "too many frames");
+}
+
+#[test]
+fn matches_the_core_with_default_arguments() {
+    check_matches_core::<u8>(8, &[], &Params::default());
+    check_matches_core::<u16>(10, &[], &Params::default());
+    check_matches_core::<u16>(16, &[], &Params::default());
+}
+
+#[test]
+fn matches_the_core_with_temporal_limiting() {
+    let params = Params {
+        sw: 2,
+        diffscl: Some(1.5),
+        ..Params::default()
+    };
+    check_matches_core::<u8>(8, &["--sw", "2", "--diffscl", "1.5"], &params);
+}
+
+#[test]
+fn keeps_the_colorspace_tag() {
+    let mut input = format!("YUV4MPEG2 W{} H{} C420mpeg2 Ip\n", WIDTH, HEIGHT).into_bytes();
+    input.extend_from_slice(b"FRAME\n");
+    input.extend(to_bytes(&noise_frame::<u8>(8, 0)));
+    let output = run_cli(&["--mode", "0"], input);
+    let header =
+        String::from_utf8_lossy(&output[..output.iter().position(|&b| b == b'\n').unwrap()])
+            .into_owned();
+    assert!(header.contains(" C420mpeg2"), "{}", header);
+}
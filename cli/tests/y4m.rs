@@ -0,0 +1,134 @@
+//! YUV4MPEG2 streams must survive reading and writing them back bit exact,
+//! including the parameters the tool doesn't interpret.
+
+use mpeg2stinx_cli::y4m::{write_frame, Reader};
+use std::io;
+
+const WIDTH: usize = 22;
+const HEIGHT: usize = 6;
+
+/// Samples of `bit_depth` bits from a xorshift generator, little endian.
+fn noise(samples: usize, bit_depth: u32, seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let mut bytes = Vec::new();
+    for _ in 0..samples {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let sample = (state >> 32) & ((1 << bit_depth) - 1);
+        if bit_depth > 8 {
+            bytes.extend_from_slice(&(sample as u16).to_le_bytes());
+        } else {
+            bytes.push(sample as u8);
+        }
+    }
+    bytes
+}
+
+/// A stream of three frames, the second with a frame parameter of its own.
+fn stream(colorspace: &str, ss_w: usize, ss_h: usize, bit_depth: u32) -> Vec<u8> {
+    let mut data = format!(
+        "YUV4MPEG2 W{} H{} C{} F30000:1001 It A10:11 XYSCSS=420MPEG2\n",
+        WIDTH, HEIGHT, colorspace
+    )
+    .into_bytes();
+    let chroma = ((WIDTH + ss_w) >> ss_w) * ((HEIGHT + ss_h) >> ss_h);
+    for frame in 0..3 {
+        data.extend_from_slice(if frame == 1 {
+            b"FRAME Ib\n"
+        } else {
+            b"FRAME\n"
+        });
+        for (plane, &samples) in [WIDTH * HEIGHT, chroma, chroma].iter().enumerate() {
+            data.extend(noise(samples, bit_depth, 3 * frame + plane as u64));
+        }
+    }
+    data
+}
+
+fn check_round_trip(colorspace: &str, ss_w: usize, ss_h: usize, bit_depth: u32) {
+    let input = stream(colorspace, ss_w, ss_h, bit_depth);
+    let mut reader = Reader::new(&input[..]).unwrap();
+    let header = reader.header.clone();
+    assert_eq!(
+        (header.width, header.height, header.ss_w, header.ss_h),
+        (WIDTH, HEIGHT, ss_w, ss_h),
+        "C{}",
+        colorspace
+    );
+    assert_eq!(header.bit_depth, bit_depth, "C{}", colorspace);
+    assert_eq!(
+        header.bytes_per_sample(),
+        if bit_depth > 8 { 2 } else { 1 },
+        "C{}",
+        colorspace
+    );
+    assert!(!header.is_bottom_field_first(), "C{}", colorspace);
+
+    let mut output = Vec::new();
+    header.write(&mut output).unwrap();
+    let mut frames = 0;
+    while let Some(frame) = reader.read_frame().unwrap() {
+        write_frame(&mut output, &frame).unwrap();
+        frames += 1;
+    }
+    assert_eq!(frames, 3, "C{}", colorspace);
+    assert!(output == input, "C{} doesn't round-trip", colorspace);
+}
+
+#[test]
+fn round_trips_8_bit() {
+    check_round_trip("420", 1, 1, 8);
+    check_round_trip("422", 1, 0, 8);
+    check_round_trip("444", 0, 0, 8);
+}
+
+#[test]
+fn round_trips_10_bit() {
+    check_round_trip("420p10", 1, 1, 10);
+    check_round_trip("422p10", 1, 0, 10);
+    check_round_trip("444p10", 0, 0, 10);
+}
+
+#[test]
+fn round_trips_16_bit() {
+    check_round_trip("420p16", 1, 1, 16);
+    check_round_trip("422p16", 1, 0, 16);
+    check_round_trip("444p16", 0, 0, 16);
+}
+
+#[test]
+fn keeps_the_chroma_siting_of_420() {
+    // The `p` of these is not the start of a bit depth
+    check_round_trip("420jpeg", 1, 1, 8);
+    check_round_trip("420mpeg2", 1, 1, 8);
+    check_round_trip("420paldv", 1, 1, 8);
+}
+
+#[test]
+fn rejects_unsupported_colorspaces() {
+    for colorspace in &["mono", "411", "420p12x", "444p32"] {
+        let input = format!("YUV4MPEG2 W{} H{} C{}\n", WIDTH, HEIGHT, colorspace);
+        assert!(
+            Reader::new(input.as_bytes()).is_err(),
+            "C{} was accepted",
+            colorspace
+        );
+    }
+}
+
+#[test]
+fn rejects_header_parameters_without_a_tag() {
+    let mut inputs: Vec<Vec<u8>> = ["é64", "Ω", "64", "=W"]
+        .iter()
+        .map(|token| format!("YUV4MPEG2 W{} H{} {}\n", WIDTH, HEIGHT, token).into_bytes())
+        .collect();
+    // Bytes that aren't UTF-8 at all
+    inputs.push(b"YUV4MPEG2 W8 H8 \xff\xfe\n".to_vec());
+    for input in &inputs {
+        match Reader::new(&input[..]) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("{:?} was accepted", String::from_utf8_lossy(input)),
+        }
+    }
+}
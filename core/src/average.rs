@@ -0,0 +1,102 @@
+use crate::kernels::{clamp, max_value, median3, zip2};
+use crate::plane::{assert_same_size, Pixel, PlaneMut, PlaneRef};
+
+/// The mean of two planes, rounding halves up.
+///
+/// Equivalent to `mt_average`, or `misc.AverageFrames` with two equal weights.
+pub fn average<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, dst: PlaneMut<T>) {
+    zip2(x, y, dst, |x, y| {
+        T::from_i64((x.to_i64() + y.to_i64() + 1) >> 1)
+    })
+}
+
+/// How `weighted_average` rounds its results to integer samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// To the nearest integer, with halves rounded up, like `mt_average`.
+    #[default]
+    HalfUp,
+    /// Down, discarding the fractional part like integer division.
+    Floor,
+}
+
+impl Rounding {
+    #[inline(always)]
+    fn round(self, value: f64) -> i64 {
+        match self {
+            Rounding::HalfUp => (value + 0.5).floor() as i64,
+            Rounding::Floor => value.floor() as i64,
+        }
+    }
+}
+
+/// The sum of `src` multiplied by `weights`, divided by `scale`.
+///
+/// Weights may be negative, which turns this into a general linear
+/// combination: `[1.0, -2.0]` with a scale of 1 gives `x - 2y`, as used for
+/// temporal limiting. Results are rounded with `rounding` and clamped to the
+/// range of `bit_depth`. With two weights of 1 and a scale of 2 this is the
+/// same as `average`.
+pub fn weighted_average<T: Pixel>(
+    src: &[PlaneRef<T>],
+    weights: &[f64],
+    scale: f64,
+    rounding: Rounding,
+    bit_depth: u32,
+    mut dst: PlaneMut<T>,
+) {
+    assert_eq!(src.len(), weights.len(), "need one weight per plane");
+    let (width, height) = (dst.width(), dst.height());
+    for plane in src {
+        assert_same_size(width, height, plane);
+    }
+
+    let max_pix_val = max_value(bit_depth);
+    let mut sums = vec![0f64; width];
+    for row in 0..height {
+        sums.iter_mut().for_each(|sum| *sum = 0.0);
+        for (plane, &weight) in src.iter().zip(weights) {
+            for (sum, &px) in sums.iter_mut().zip(plane.row(row)) {
+                *sum += weight * px.to_i64() as f64;
+            }
+        }
+        for (target, &sum) in dst.row_mut(row).iter_mut().zip(&sums) {
+            let value = rounding.round(sum / scale);
+            *target = T::from_i64(clamp(value, 0, max_pix_val));
+        }
+    }
+}
+
+fn sorted_median<T: Pixel>(src: &[PlaneRef<T>], mut dst: PlaneMut<T>) {
+    let middle = src.len() / 2;
+    let mut values = Vec::with_capacity(src.len());
+    for row in 0..dst.height() {
+        for (x, target) in dst.row_mut(row).iter_mut().enumerate() {
+            values.clear();
+            values.extend(src.iter().map(|plane| plane.row(row)[x]));
+            values.sort_unstable();
+            *target = if src.len() % 2 == 1 {
+                values[middle]
+            } else {
+                T::from_i64((values[middle - 1].to_i64() + values[middle].to_i64() + 1) >> 1)
+            };
+        }
+    }
+}
+
+/// The median of `src` at each pixel. With an even number of planes, the
+/// two middle values are averaged like `average` does.
+///
+/// Equivalent to `median3` for three planes.
+pub fn median<T: Pixel>(src: &[PlaneRef<T>], mut dst: PlaneMut<T>) {
+    assert!(!src.is_empty(), "need at least one plane");
+    for plane in src {
+        assert_same_size(dst.width(), dst.height(), plane);
+    }
+    match src.len() {
+        1 => dst.copy_from(src[0]),
+        2 => average(src[0], src[1], dst),
+        3 => median3(src[0], src[1], src[2], dst),
+        _ => sorted_median(src, dst),
+    }
+}
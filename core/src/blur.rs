@@ -0,0 +1,59 @@
+use crate::kernels::{clamp, max_value};
+use crate::plane::{Pixel, Plane, PlaneRef};
+
+pub fn build_blurv_kernel(strength: f64) -> [f64; 3] {
+    // Vapoursynth's Convolution kernel will round our numbers to integers,
+    // so scale up as far as possible for the most accuracy.
+    const MAX: f64 = 1023.0;
+    let inner_factor = 1.0 / 2f64.powf(strength);
+    let outer_factor = (1.0 - 1.0 / 2f64.powf(strength)) / 2.0;
+    let inner = if strength > 0.0 {
+        inner_factor * MAX
+    } else {
+        MAX / inner_factor
+    };
+    let outer = if strength > 0.0 {
+        outer_factor * MAX
+    } else {
+        MAX / outer_factor
+    };
+    [outer, inner, outer]
+}
+
+/// Convolves `src` vertically with a 3-tap kernel, mirroring at the edges.
+///
+/// Equivalent to `std.Convolution(mode="v")` on an integer clip, so the
+/// coefficients are rounded to integers and normalized by their sum.
+pub fn convolve_v<T: Pixel>(src: PlaneRef<T>, kernel: &[f64; 3], bit_depth: u32) -> Plane<T> {
+    let max_pix_val = max_value(bit_depth);
+    let coeffs = [
+        kernel[0].round() as i64,
+        kernel[1].round() as i64,
+        kernel[2].round() as i64,
+    ];
+    let div = coeffs.iter().sum::<i64>();
+    let rdiv = if div == 0 { 1.0 } else { 1.0 / div as f64 };
+
+    let mut dst = Plane::new(src.width(), src.height());
+    let mut out = dst.as_mut();
+    for y in 0..src.height() {
+        let above = src.row_mirrored(y as isize - 1);
+        let cur = src.row(y);
+        let below = src.row_mirrored(y as isize + 1);
+        for (x, target) in out.row_mut(y).iter_mut().enumerate() {
+            let sum = above[x].to_i64() * coeffs[0]
+                + cur[x].to_i64() * coeffs[1]
+                + below[x].to_i64() * coeffs[2];
+            *target = T::from_i64(clamp(
+                (sum as f64 * rdiv + 0.5).floor() as i64,
+                0,
+                max_pix_val,
+            ));
+        }
+    }
+    dst
+}
+
+pub fn blur_v<T: Pixel>(src: PlaneRef<T>, strength: f64, bit_depth: u32) -> Plane<T> {
+    convolve_v(src, &build_blurv_kernel(strength), bit_depth)
+}
@@ -0,0 +1,60 @@
+use crate::fields::separate_fields;
+use crate::plane::{Pixel, Plane, PlaneRef};
+use crate::resize::{resize_v, ResizeKernel};
+
+/// Resizer used for interpolating fields to full size.
+///
+/// The nnedi3 based modes of the plugin need external filters,
+/// so only the resizer based modes are available here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BobMode {
+    Point,
+    #[default]
+    Spline36,
+}
+
+/// Interpolates each field of `src` to full height, returning the bobbed top
+/// field followed by the bobbed bottom field.
+pub fn bob<T: Pixel>(src: PlaneRef<T>, mode: BobMode, bit_depth: u32) -> (Plane<T>, Plane<T>) {
+    assert!(
+        src.height().is_multiple_of(2),
+        "bobbing requires an even height"
+    );
+    let (top, bottom) = separate_fields(src, true);
+    match mode {
+        BobMode::Point => (
+            resize_v(
+                top.as_ref(),
+                src.height(),
+                0.0,
+                ResizeKernel::Point,
+                bit_depth,
+            ),
+            resize_v(
+                bottom.as_ref(),
+                src.height(),
+                0.0,
+                ResizeKernel::Point,
+                bit_depth,
+            ),
+        ),
+        // Shift each field by a quarter of a field line, so that its lines
+        // end up where they were in the original frame.
+        BobMode::Spline36 => (
+            resize_v(
+                top.as_ref(),
+                src.height(),
+                0.25,
+                ResizeKernel::Spline36,
+                bit_depth,
+            ),
+            resize_v(
+                bottom.as_ref(),
+                src.height(),
+                -0.25,
+                ResizeKernel::Spline36,
+                bit_depth,
+            ),
+        ),
+    }
+}
@@ -0,0 +1,72 @@
+//! Repair of the interlaced chroma upsampling error (CUE), where progressive
+//! frames were chroma subsampled as if they were interlaced.
+
+use crate::average::average;
+use crate::bob::{bob, BobMode};
+use crate::kernels::{clamp, max_value};
+use crate::plane::{Pixel, Plane, PlaneRef};
+
+/// How strongly a chroma sample alternates with its vertical neighbours,
+/// i.e. the smaller of its differences to the rows above and below if both
+/// go in the same direction, and 0 otherwise.
+#[inline(always)]
+fn stripe(above: i64, cur: i64, below: i64) -> i64 {
+    let (up, down) = (cur - above, cur - below);
+    if up.signum() * down.signum() > 0 {
+        up.abs().min(down.abs())
+    } else {
+        0
+    }
+}
+
+/// Rebuilds a vertically subsampled chroma plane that was subsampled
+/// field by field.
+///
+/// With 4:2:0 interlaced siting, the even chroma rows only carry the top
+/// field and the odd rows only the bottom one, but both are sited where
+/// progressive chroma rows would be. So each field is bobbed to full height
+/// with `mode` and both are averaged, which gives every row its share of
+/// both fields. The result is only blended in where the rows alternate like
+/// stripes: not at all below a stripe strength of `threshold` (on an 8-bit
+/// scale), fully from twice the threshold on, so that clean chroma is left
+/// untouched.
+///
+/// Requires an even height, like `bob`.
+pub fn cue_repair<T: Pixel>(
+    src: PlaneRef<T>,
+    mode: BobMode,
+    threshold: f64,
+    bit_depth: u32,
+) -> Plane<T> {
+    let (top, bottom) = bob(src, mode, bit_depth);
+    let mut rebuilt = Plane::new(src.width(), src.height());
+    average(top.as_ref(), bottom.as_ref(), rebuilt.as_mut());
+
+    let threshold = threshold * 2f64.powi(bit_depth as i32 - 8);
+    let max_pix_val = max_value(bit_depth);
+    let mut dst = Plane::new(src.width(), src.height());
+    let mut out = dst.as_mut();
+    for y in 0..src.height() {
+        let above = src.row(y.saturating_sub(1));
+        let cur = src.row(y);
+        let below = src.row((y + 1).min(src.height() - 1));
+        let rebuilt = rebuilt.as_ref().row(y);
+        for (x, target) in out.row_mut(y).iter_mut().enumerate() {
+            // The outermost rows lack a neighbour to tell stripes from edges
+            let strength = if y == 0 || y + 1 == src.height() {
+                0.0
+            } else {
+                stripe(above[x].to_i64(), cur[x].to_i64(), below[x].to_i64()) as f64
+            };
+            let weight = if threshold > 0.0 {
+                ((strength - threshold) / threshold).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            let value =
+                cur[x].to_i64() as f64 + weight * (rebuilt[x].to_i64() - cur[x].to_i64()) as f64;
+            *target = T::from_i64(clamp((value + 0.5).floor() as i64, 0, max_pix_val));
+        }
+    }
+    dst
+}
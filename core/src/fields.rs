@@ -0,0 +1,47 @@
+use crate::plane::{Pixel, Plane, PlaneRef};
+
+/// Splits a frame into its two fields, returned in temporal order.
+///
+/// Equivalent to `SeparateFields`, followed by treating the fields as progressive frames.
+pub fn separate_fields<T: Pixel>(src: PlaneRef<T>, tff: bool) -> (Plane<T>, Plane<T>) {
+    let top = field(src, 0);
+    let bottom = field(src, 1);
+    if tff {
+        (top, bottom)
+    } else {
+        (bottom, top)
+    }
+}
+
+fn field<T: Pixel>(src: PlaneRef<T>, parity: usize) -> Plane<T> {
+    let height = (src.height() + 1 - parity) / 2;
+    let mut data = Vec::with_capacity(src.width() * height);
+    for y in (parity..src.height()).step_by(2) {
+        data.extend_from_slice(src.row(y));
+    }
+    Plane::from_vec(data, src.width(), height)
+}
+
+/// Interleaves the rows of two fields, given in temporal order, into a frame.
+///
+/// Equivalent to `DoubleWeave`, keeping only the frame made from this pair of fields.
+pub fn weave_fields<T: Pixel>(first: PlaneRef<T>, second: PlaneRef<T>, tff: bool) -> Plane<T> {
+    let (top, bottom) = if tff {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    assert_eq!(top.width(), bottom.width(), "fields differ in width");
+    assert!(
+        top.height() == bottom.height() || top.height() == bottom.height() + 1,
+        "fields differ in height"
+    );
+
+    let height = top.height() + bottom.height();
+    let mut data = Vec::with_capacity(top.width() * height);
+    for y in 0..height {
+        let field = if y % 2 == 0 { top } else { bottom };
+        data.extend_from_slice(field.row(y / 2));
+    }
+    Plane::from_vec(data, top.width(), height)
+}
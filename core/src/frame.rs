@@ -0,0 +1,42 @@
+use crate::plane::{Pixel, Plane, PlaneRef};
+
+/// A video frame as a list of planes, luma first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<T> {
+    pub planes: Vec<Plane<T>>,
+    pub bit_depth: u32,
+}
+
+impl<T: Pixel> Frame<T> {
+    pub fn new(planes: Vec<Plane<T>>, bit_depth: u32) -> Self {
+        Frame { planes, bit_depth }
+    }
+
+    pub fn from_refs(planes: &[PlaneRef<T>], bit_depth: u32) -> Self {
+        Frame {
+            planes: planes.iter().map(|&plane| Plane::from_ref(plane)).collect(),
+            bit_depth,
+        }
+    }
+
+    pub fn plane(&self, plane: usize) -> PlaneRef<'_, T> {
+        self.planes[plane].as_ref()
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// Builds a new frame by applying `f` to each plane of this one.
+    pub fn map_planes<F: FnMut(usize, PlaneRef<T>) -> Plane<T>>(&self, mut f: F) -> Self {
+        Frame {
+            planes: self
+                .planes
+                .iter()
+                .enumerate()
+                .map(|(i, plane)| f(i, plane.as_ref()))
+                .collect(),
+            bit_depth: self.bit_depth,
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Per-pixel operations combining two or three planes of the same size,
+//! equivalent to the `mt_lutxy` and `mt_logic` calls of the original script.
+
+use crate::plane::{assert_same_size, Pixel, PlaneMut, PlaneRef};
+use crate::simd::Isa;
+
+#[inline(always)]
+pub(crate) fn max_value(bit_depth: u32) -> i64 {
+    (1i64 << bit_depth) - 1
+}
+
+/// The neutral value of a difference clip, 128 for 8-bit like in the original script.
+#[inline(always)]
+pub(crate) fn half_value(bit_depth: u32) -> i64 {
+    1i64 << (bit_depth - 1)
+}
+
+#[inline(always)]
+pub(crate) fn clamp(input: i64, min: i64, max: i64) -> i64 {
+    if input < min {
+        min
+    } else if input > max {
+        max
+    } else {
+        input
+    }
+}
+
+pub(crate) fn zip2<T: Pixel, F: Fn(T, T) -> T>(
+    x: PlaneRef<T>,
+    y: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    f: F,
+) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    for row in 0..x.height() {
+        for ((&x, &y), target) in x
+            .row(row)
+            .iter()
+            .zip(y.row(row).iter())
+            .zip(dst.row_mut(row).iter_mut())
+        {
+            *target = f(x, y);
+        }
+    }
+}
+
+pub(crate) fn zip3<T: Pixel, F: Fn(T, T, T) -> T>(
+    x: PlaneRef<T>,
+    y: PlaneRef<T>,
+    z: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    f: F,
+) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &z);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    for row in 0..x.height() {
+        for (((&x, &y), &z), target) in x
+            .row(row)
+            .iter()
+            .zip(y.row(row).iter())
+            .zip(z.row(row).iter())
+            .zip(dst.row_mut(row).iter_mut())
+        {
+            *target = f(x, y, z);
+        }
+    }
+}
+
+pub fn min<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, mut dst: PlaneMut<T>) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::min_row(isa, x.row(row), y.row(row), dst.row_mut(row));
+    }
+}
+
+pub fn max<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, mut dst: PlaneMut<T>) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::max_row(isa, x.row(row), y.row(row), dst.row_mut(row));
+    }
+}
+
+#[inline(always)]
+pub(crate) fn median3_px<T: Pixel>(x: T, y: T, z: T) -> T {
+    if x > y {
+        if y > z {
+            y
+        } else if x > z {
+            z
+        } else {
+            x
+        }
+    } else if x > z {
+        x
+    } else if y > z {
+        z
+    } else {
+        y
+    }
+}
+
+pub fn median3<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, z: PlaneRef<T>, mut dst: PlaneMut<T>) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &z);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::median3_row(isa, x.row(row), y.row(row), z.row(row), dst.row_mut(row));
+    }
+}
+
+#[inline(always)]
+pub(crate) fn sharp_px(x: i64, y: i64, strength: f32, max_pix_val: i64) -> i64 {
+    let xf = x as f32;
+    let yf = y as f32;
+    // Saturating, as the cast saturates for extreme strengths
+    clamp(
+        x.saturating_add(((xf - yf) * strength) as i64),
+        0,
+        max_pix_val,
+    )
+}
+
+#[inline(always)]
+pub(crate) fn sharpd_px(x: i64, y: i64, strength: f32, half_val: i64, max_pix_val: i64) -> i64 {
+    let x = x as f32;
+    let y = y as f32;
+    clamp(
+        half_val.saturating_add(((x - y) * strength) as i64),
+        0,
+        max_pix_val,
+    )
+}
+
+#[inline(always)]
+pub(crate) fn limd_px(x: i64, y: i64, scale: f32, half_val: i64, max_pix_val: i64) -> i64 {
+    let closest = if (x - half_val).abs() < (y - half_val).abs() {
+        x
+    } else {
+        y
+    };
+    let factor = if (x - half_val) * (y - half_val) < 0 {
+        scale
+    } else {
+        1.0
+    };
+    clamp(
+        half_val.saturating_add(((closest - half_val) as f32 * factor) as i64),
+        0,
+        max_pix_val,
+    )
+}
+
+#[inline(always)]
+pub(crate) fn make_diff_px(x: i64, y: i64, half_val: i64, max_pix_val: i64) -> i64 {
+    clamp(x - y + half_val, 0, max_pix_val)
+}
+
+#[inline(always)]
+pub(crate) fn add_diff_px(x: i64, y: i64, half_val: i64, max_pix_val: i64) -> i64 {
+    clamp(x + y - half_val, 0, max_pix_val)
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x x y - "+string(sstr)+" * +",y=3,u=3,v=3)`
+pub fn sharp<T: Pixel>(
+    x: PlaneRef<T>,
+    y: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    strength: f32,
+    bit_depth: u32,
+) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::sharp_row(
+            isa,
+            x.row(row),
+            y.row(row),
+            dst.row_mut(row),
+            strength,
+            max_pix_val,
+        );
+    }
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x y - "+string(sstr)+" * 128 +",y=3,u=3,v=3)`
+// and also fixed to work with high bit depth
+pub fn sharpd<T: Pixel>(
+    x: PlaneRef<T>,
+    y: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    strength: f32,
+    bit_depth: u32,
+) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let half_val = half_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::sharpd_row(
+            isa,
+            x.row(row),
+            y.row(row),
+            dst.row_mut(row),
+            strength,
+            half_val,
+            max_pix_val,
+        );
+    }
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x 128 - y 128 - * 0 < "+string(scl)+" 1 ? x 128 - abs y 128 - abs < x y ? 128 - * 128 +",y=3,u=3,v=3)`
+// and also fixed to work with high bit depth
+pub fn limd<T: Pixel>(
+    x: PlaneRef<T>,
+    y: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    scale: f32,
+    bit_depth: u32,
+) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let half_val = half_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::limd_row(
+            isa,
+            x.row(row),
+            y.row(row),
+            dst.row_mut(row),
+            scale,
+            half_val,
+            max_pix_val,
+        );
+    }
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x y - abs",y=3,u=3,v=3)`
+pub fn diff<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, mut dst: PlaneMut<T>, bit_depth: u32) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = T::from_i64(max_value(bit_depth));
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::diff_row(isa, x.row(row), y.row(row), dst.row_mut(row), max_pix_val);
+    }
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x y - 128 +",y=3,u=3,v=3)`
+pub fn make_diff<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, mut dst: PlaneMut<T>, bit_depth: u32) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let half_val = half_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::make_diff_row(
+            isa,
+            x.row(row),
+            y.row(row),
+            dst.row_mut(row),
+            half_val,
+            max_pix_val,
+        );
+    }
+}
+
+// Equivalent AVS:
+// `mt_lutxy(x,y,expr="x y + 128 -",y=3,u=3,v=3)`
+pub fn add_diff<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, mut dst: PlaneMut<T>, bit_depth: u32) {
+    assert_same_size(x.width(), x.height(), &y);
+    assert_same_size(x.width(), x.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let half_val = half_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..x.height() {
+        T::add_diff_row(
+            isa,
+            x.row(row),
+            y.row(row),
+            dst.row_mut(row),
+            half_val,
+            max_pix_val,
+        );
+    }
+}
+
+/// Sharpens `nuked` against its blurred copy, limiting the result by how much
+/// `nuked` differs from `src`. This combines the sharp/median3 or
+/// sharpd/make_diff/limd/add_diff chains into a single pass.
+pub fn contrasharpen<T: Pixel>(
+    src: PlaneRef<T>,
+    nuked: PlaneRef<T>,
+    nuked_blurred: PlaneRef<T>,
+    mut dst: PlaneMut<T>,
+    sstr: f32,
+    scl: f32,
+    bit_depth: u32,
+) {
+    assert_same_size(src.width(), src.height(), &nuked);
+    assert_same_size(src.width(), src.height(), &nuked_blurred);
+    assert_same_size(src.width(), src.height(), &dst.as_ref());
+    let max_pix_val = max_value(bit_depth);
+    let half_val = half_value(bit_depth);
+    let isa = Isa::detect();
+    for row in 0..src.height() {
+        T::contrasharpen_row(
+            isa,
+            src.row(row),
+            nuked.row(row),
+            nuked_blurred.row(row),
+            dst.row_mut(row),
+            sstr,
+            scl,
+            half_val,
+            max_pix_val,
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! The Mpeg2Stinx algorithm on plain plane buffers, without any dependency on VapourSynth.
+//!
+//! All kernels operate on integer samples of any bit depth up to 32 bits,
+//! with planes described by a slice, a width, a height and a stride in pixels.
+
+#![warn(clippy::all)]
+
+mod average;
+mod blur;
+mod bob;
+mod cue;
+mod fields;
+mod frame;
+mod kernels;
+mod mask;
+mod morphology;
+mod pipeline;
+mod plane;
+mod repair;
+mod resize;
+mod simd;
+mod temporal;
+mod tiles;
+
+pub use self::average::*;
+pub use self::blur::*;
+pub use self::bob::*;
+pub use self::cue::*;
+pub use self::fields::*;
+pub use self::frame::*;
+pub use self::kernels::{
+    add_diff, contrasharpen, diff, limd, make_diff, max, median3, min, sharp, sharpd,
+};
+pub use self::mask::*;
+pub use self::morphology::*;
+pub use self::pipeline::*;
+pub use self::plane::*;
+pub use self::repair::*;
+pub use self::resize::*;
+pub use self::simd::*;
+pub use self::temporal::*;
+pub use self::tiles::*;
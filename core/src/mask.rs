@@ -0,0 +1,72 @@
+use crate::kernels::max_value;
+use crate::plane::{assert_same_size, Pixel, Plane, PlaneMut, PlaneRef};
+
+/// How `mask_merge` treats the values of a protection mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaskMode {
+    /// Every non-zero mask sample takes the source sample as it is.
+    #[default]
+    Replace,
+    /// The source is blended in proportionally to the mask value,
+    /// from none at 0 to all of it at the maximum of the mask's bit depth.
+    Blend,
+}
+
+/// Scales a full resolution `mask` down to a plane subsampled by
+/// `1 << sub_w` horizontally and `1 << sub_h` vertically.
+///
+/// Each sample is the mean of the mask samples it covers, rounded up,
+/// so that a subsampled sample is protected if any sample it covers is.
+pub fn subsample_mask<M: Pixel>(mask: PlaneRef<M>, sub_w: u32, sub_h: u32) -> Plane<M> {
+    let (block_w, block_h) = (1 << sub_w, 1 << sub_h);
+    let width = (mask.width() + block_w - 1) >> sub_w;
+    let height = (mask.height() + block_h - 1) >> sub_h;
+    Plane::from_fn(width, height, |x, y| {
+        let cols = x << sub_w..((x + 1) << sub_w).min(mask.width());
+        let rows = y << sub_h..((y + 1) << sub_h).min(mask.height());
+        let count = (cols.len() * rows.len()) as i64;
+        let sum: i64 = rows
+            .flat_map(|row| mask.row(row)[cols.clone()].iter())
+            .map(|px| px.to_i64())
+            .sum();
+        M::from_i64((sum + count - 1) / count)
+    })
+}
+
+/// Merges `src` back into `processed` where `mask` is set, as chosen by `mode`.
+///
+/// `mask` has the size of the planes and may have its own sample type,
+/// with `mask_bits` giving its bit depth.
+pub fn mask_merge<T: Pixel, M: Pixel>(
+    processed: PlaneRef<T>,
+    src: PlaneRef<T>,
+    mask: PlaneRef<M>,
+    mut dst: PlaneMut<T>,
+    mask_bits: u32,
+    mode: MaskMode,
+) {
+    let (width, height) = (dst.width(), dst.height());
+    assert_same_size(width, height, &processed);
+    assert_same_size(width, height, &src);
+    assert_same_size(width, height, &mask);
+
+    let mask_max = max_value(mask_bits);
+    for row in 0..height {
+        let rows = processed
+            .row(row)
+            .iter()
+            .zip(src.row(row))
+            .zip(mask.row(row));
+        for (target, ((&p, &s), &m)) in dst.row_mut(row).iter_mut().zip(rows) {
+            let m = m.to_i64();
+            *target = match mode {
+                _ if m == 0 => p,
+                MaskMode::Replace => s,
+                MaskMode::Blend => {
+                    let (p, s) = (p.to_i64(), s.to_i64());
+                    T::from_i64((p * (mask_max - m) + s * m + mask_max / 2) / mask_max)
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Minimum and maximum over neighbourhoods of any radius, equivalent to
+//! repeated `std.Minimum`/`std.Maximum` calls but at a constant cost per
+//! pixel for rectangles.
+
+use crate::plane::{clamp_coord, Pixel, Plane, PlaneRef};
+use std::cmp;
+
+/// The shape of the neighbourhood of `expand_shape` and `inpand_shape`,
+/// reaching `rx` pixels to the left and right and `ry` pixels up and down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shape {
+    /// The whole `(2rx+1)`x`(2ry+1)` rectangle.
+    #[default]
+    Square,
+    /// The pixels within `|dx|/rx + |dy|/ry <= 1`.
+    Diamond,
+    /// The pixels within `(dx/rx)² + (dy/ry)² <= 1`.
+    Ellipse,
+    /// The centre row and column of the rectangle.
+    Plus,
+    /// The column of `ry` rows up and down within the same field, i.e. every
+    /// other row up to `2ry` rows away. `rx` is ignored.
+    Field,
+    /// The pixels of the mask, `rx` and `ry` are ignored.
+    Mask(Mask),
+}
+
+/// A neighbourhood given pixel by pixel, as offsets from the centre of at
+/// most `Mask::MAX_RADIUS` in either direction. The centre is always part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mask {
+    /// Bit `dx + MAX_RADIUS` of `rows[dy + MAX_RADIUS]` is set for each offset.
+    rows: [u16; 2 * Mask::MAX_RADIUS as usize + 1],
+}
+
+impl Mask {
+    pub const MAX_RADIUS: u32 = 7;
+
+    /// The mask of the centre and `offsets`, given as `(dx, dy)`,
+    /// or `None` if any of them is too far from the centre.
+    pub fn new(offsets: &[(i32, i32)]) -> Option<Mask> {
+        let r = Mask::MAX_RADIUS as i32;
+        let mut rows = [0; 2 * Mask::MAX_RADIUS as usize + 1];
+        rows[r as usize] = 1 << r;
+        for &(dx, dy) in offsets {
+            if dx.abs() > r || dy.abs() > r {
+                return None;
+            }
+            rows[(dy + r) as usize] |= 1 << (dx + r);
+        }
+        Some(Mask { rows })
+    }
+
+    /// Whether `(dx, dy)` is part of the mask.
+    pub fn contains(&self, dx: i32, dy: i32) -> bool {
+        let r = Mask::MAX_RADIUS as i32;
+        dx.abs() <= r && dy.abs() <= r && self.rows[(dy + r) as usize] & (1 << (dx + r)) != 0
+    }
+
+    /// The offsets of the mask as `(dx, dy)`, row by row, centre included.
+    pub fn offsets(&self) -> Vec<(i32, i32)> {
+        let r = Mask::MAX_RADIUS as i32;
+        (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| self.contains(dx, dy))
+            .collect()
+    }
+}
+
+/// A horizontal run of a neighbourhood: the pixels from `lo` to `hi`
+/// columns away on the row `dy` rows away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    dy: isize,
+    lo: isize,
+    hi: isize,
+}
+
+impl Shape {
+    /// How far a symmetric shape reaches horizontally on row `dy`, for `|dy| <= ry`.
+    fn half_width(self, rx: u32, ry: u32, dy: u32) -> u32 {
+        if ry == 0 {
+            return rx;
+        }
+        match self {
+            Shape::Diamond => rx * (ry - dy) / ry,
+            Shape::Ellipse => {
+                let dy = f64::from(dy) / f64::from(ry);
+                (f64::from(rx) * (1.0 - dy * dy).sqrt() + 1e-9).floor() as u32
+            }
+            Shape::Plus if dy > 0 => 0,
+            _ => rx,
+        }
+    }
+
+    /// The neighbourhood as one horizontal run per row, or several for masks.
+    fn runs(self, rx: u32, ry: u32) -> Vec<Run> {
+        let ry = ry as isize;
+        match self {
+            Shape::Field => (-ry..=ry)
+                .map(|k| Run {
+                    dy: 2 * k,
+                    lo: 0,
+                    hi: 0,
+                })
+                .collect(),
+            Shape::Mask(mask) => {
+                let r = Mask::MAX_RADIUS as isize;
+                let mut runs = Vec::new();
+                for dy in -r..=r {
+                    let mut dx = -r;
+                    while dx <= r {
+                        if !mask.contains(dx as i32, dy as i32) {
+                            dx += 1;
+                            continue;
+                        }
+                        let lo = dx;
+                        while dx < r && mask.contains(dx as i32 + 1, dy as i32) {
+                            dx += 1;
+                        }
+                        runs.push(Run { dy, lo, hi: dx });
+                        dx += 1;
+                    }
+                }
+                runs
+            }
+            _ => (-ry..=ry)
+                .map(|dy| {
+                    let half_width =
+                        self.half_width(rx, ry as u32, dy.unsigned_abs() as u32) as isize;
+                    Run {
+                        dy,
+                        lo: -half_width,
+                        hi: half_width,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The van Herk/Gil-Werman running extreme of `src` over the windows from
+/// `lo` to `hi` samples away, with the edges extended by repeating the
+/// outermost samples. Costs three `pick`s per sample whatever the window.
+///
+/// `prefix` and `suffix` are scratch buffers, reused across calls.
+fn running_extreme<T: Pixel>(
+    src: &[T],
+    dst: &mut [T],
+    (lo, hi): (isize, isize),
+    pick: fn(T, T) -> T,
+    prefix: &mut Vec<T>,
+    suffix: &mut Vec<T>,
+) {
+    let len = src.len();
+    if (lo, hi) == (0, 0) || len == 0 {
+        dst.copy_from_slice(src);
+        return;
+    }
+    let window = (hi - lo) as usize + 1;
+    let extended = len + window - 1;
+    let at = |i: usize| src[clamp_coord(i as isize + lo, len)];
+
+    // Extremes from the start of each block of `window` samples up to i,
+    // and from i up to the end of its block
+    prefix.clear();
+    suffix.clear();
+    suffix.resize(extended, T::default());
+    for i in 0..extended {
+        let px = at(i);
+        prefix.push(if i % window == 0 {
+            px
+        } else {
+            pick(prefix[i - 1], px)
+        });
+    }
+    for i in (0..extended).rev() {
+        let px = at(i);
+        suffix[i] = if i % window == window - 1 || i + 1 == extended {
+            px
+        } else {
+            pick(suffix[i + 1], px)
+        };
+    }
+    // Every window spans the end of one block and the start of the next
+    for (x, target) in dst.iter_mut().enumerate() {
+        *target = pick(suffix[x], prefix[x + window - 1]);
+    }
+}
+
+fn horizontal<T: Pixel>(src: PlaneRef<T>, window: (isize, isize), pick: fn(T, T) -> T) -> Plane<T> {
+    let mut dst = Plane::new(src.width(), src.height());
+    let mut out = dst.as_mut();
+    let (mut prefix, mut suffix) = (Vec::new(), Vec::new());
+    for y in 0..src.height() {
+        running_extreme(
+            src.row(y),
+            out.row_mut(y),
+            window,
+            pick,
+            &mut prefix,
+            &mut suffix,
+        );
+    }
+    dst
+}
+
+fn vertical<T: Pixel>(src: PlaneRef<T>, ry: u32, pick: fn(T, T) -> T) -> Plane<T> {
+    let (width, height) = (src.width(), src.height());
+    let mut dst = Plane::new(width, height);
+    let mut out = dst.as_mut();
+    let (mut prefix, mut suffix) = (Vec::new(), Vec::new());
+    let mut column = vec![T::default(); height];
+    let mut filtered = vec![T::default(); height];
+    let ry = ry as isize;
+    for x in 0..width {
+        for (y, px) in column.iter_mut().enumerate() {
+            *px = src.row(y)[x];
+        }
+        running_extreme(
+            &column,
+            &mut filtered,
+            (-ry, ry),
+            pick,
+            &mut prefix,
+            &mut suffix,
+        );
+        for (y, &px) in filtered.iter().enumerate() {
+            out.row_mut(y)[x] = px;
+        }
+    }
+    dst
+}
+
+fn shaped<T: Pixel>(
+    src: PlaneRef<T>,
+    rx: u32,
+    ry: u32,
+    shape: Shape,
+    pick: fn(T, T) -> T,
+) -> Plane<T> {
+    if shape == Shape::Square {
+        let rx = rx as isize;
+        return vertical(horizontal(src, (-rx, rx), pick).as_ref(), ry, pick);
+    }
+
+    // Other shapes are the union of horizontal runs on a few rows, so
+    // filter each row once per distinct run and combine the shifted rows
+    let runs = shape.runs(rx, ry);
+    let mut filtered: Vec<((isize, isize), Plane<T>)> = Vec::new();
+    for run in &runs {
+        if filtered
+            .iter()
+            .all(|&(window, _)| window != (run.lo, run.hi))
+        {
+            filtered.push(((run.lo, run.hi), horizontal(src, (run.lo, run.hi), pick)));
+        }
+    }
+    let plane = |run: &Run| {
+        filtered
+            .iter()
+            .find(|&&(window, _)| window == (run.lo, run.hi))
+            .map(|(_, plane)| plane.as_ref())
+            .unwrap()
+    };
+
+    let height = src.height();
+    let mut dst = Plane::new(src.width(), height);
+    let mut out = dst.as_mut();
+    for y in 0..height {
+        let target = out.row_mut(y);
+        for (i, run) in runs.iter().enumerate() {
+            let row = plane(run).row(clamp_coord(y as isize + run.dy, height));
+            if i == 0 {
+                target.copy_from_slice(row);
+            } else {
+                for (t, &px) in target.iter_mut().zip(row) {
+                    *t = pick(*t, px);
+                }
+            }
+        }
+    }
+    dst
+}
+
+/// The maximum over a `(2rx+1)`x`(2ry+1)` rectangle around each pixel.
+///
+/// Equivalent to `rx` and `ry` repeated calls of `std.Maximum`.
+pub fn expand<T: Pixel>(src: PlaneRef<T>, rx: u32, ry: u32) -> Plane<T> {
+    shaped(src, rx, ry, Shape::Square, cmp::max)
+}
+
+/// The minimum over a `(2rx+1)`x`(2ry+1)` rectangle around each pixel.
+///
+/// Equivalent to `rx` and `ry` repeated calls of `std.Minimum`.
+pub fn inpand<T: Pixel>(src: PlaneRef<T>, rx: u32, ry: u32) -> Plane<T> {
+    shaped(src, rx, ry, Shape::Square, cmp::min)
+}
+
+/// The maximum over the neighbourhood of the given `shape` around each pixel.
+///
+/// Rectangles cost the same for any radius, other shapes grow with the
+/// number of rows they span only.
+pub fn expand_shape<T: Pixel>(src: PlaneRef<T>, rx: u32, ry: u32, shape: Shape) -> Plane<T> {
+    shaped(src, rx, ry, shape, cmp::max)
+}
+
+/// The minimum over the neighbourhood of the given `shape` around each pixel.
+///
+/// Rectangles cost the same for any radius, other shapes grow with the
+/// number of rows they span only.
+pub fn inpand_shape<T: Pixel>(src: PlaneRef<T>, rx: u32, ry: u32, shape: Shape) -> Plane<T> {
+    shaped(src, rx, ry, shape, cmp::min)
+}
@@ -0,0 +1,300 @@
+use crate::average::{average, median, weighted_average, Rounding};
+use crate::blur::blur_v;
+use crate::bob::{bob, BobMode};
+use crate::fields::{separate_fields, weave_fields};
+use crate::frame::Frame;
+use crate::kernels::contrasharpen;
+use crate::morphology::Shape;
+use crate::plane::{Pixel, Plane};
+use crate::repair::cross_field_repair;
+use crate::temporal::temp_limit;
+
+/// How the results of the repair passes are combined into one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Combine {
+    /// The mean of all passes.
+    #[default]
+    Average,
+    /// The mean of all passes weighted by their number, so that later,
+    /// more thoroughly repaired passes count more.
+    Weighted,
+    /// The median of all passes.
+    Median,
+    /// Only the last pass.
+    Last,
+}
+
+impl Combine {
+    /// The weights of `passes` passes for `Average` and `Weighted`.
+    pub fn weights(self, passes: usize) -> Vec<f64> {
+        match self {
+            Combine::Weighted => (1..=passes).map(|pass| pass as f64).collect(),
+            _ => vec![1.0; passes],
+        }
+    }
+}
+
+/// Parameters of the full Mpeg2Stinx filter, see the plugin's README for their meaning.
+///
+/// The `_c` fields override the settings of all planes but the first, see `Params::chroma`.
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    pub mode: BobMode,
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+    pub contra: bool,
+    pub blurv: f64,
+    pub sstr: f32,
+    pub scl: f32,
+    pub diffscl: Option<f64>,
+    /// The number of repair passes, each repairing the previous one. At least 1.
+    pub passes: u32,
+    pub combine: Combine,
+    /// Whether the frames are interlaced video, whose fields are bobbed to
+    /// frames of their own and processed at double rate, see `mpeg2stinx`.
+    pub interlaced: bool,
+    /// The field order of interlaced frames.
+    pub tff: bool,
+    pub sw_c: Option<u32>,
+    pub sh_c: Option<u32>,
+    pub blurv_c: Option<f64>,
+    pub sstr_c: Option<f32>,
+    pub scl_c: Option<f32>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            mode: BobMode::default(),
+            sw: 1,
+            sh: 1,
+            shape: Shape::Square,
+            contra: true,
+            blurv: 0.9,
+            sstr: 2.0,
+            scl: 0.25,
+            diffscl: None,
+            passes: 2,
+            combine: Combine::Average,
+            interlaced: false,
+            tff: true,
+            sw_c: None,
+            sh_c: None,
+            blurv_c: None,
+            sstr_c: None,
+            scl_c: None,
+        }
+    }
+}
+
+/// The settings that may differ between luma and chroma.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneParams {
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+    pub blurv: f64,
+    pub sstr: f32,
+    pub scl: f32,
+}
+
+/// Scales a luma radius to a plane subsampled by `2^subsampling`,
+/// rounding up so that any non-zero radius stays non-zero.
+pub fn subsampled_radius(radius: u32, subsampling: u32) -> u32 {
+    (radius + (1 << subsampling) - 1) >> subsampling
+}
+
+/// The log2 subsampling of a plane of `size` samples relative to luma of `luma_size`.
+pub(crate) fn subsampling(luma_size: usize, size: usize) -> u32 {
+    let mut subsampling = 0;
+    while subsampling < 4 && (luma_size + (1 << subsampling) - 1) >> subsampling > size {
+        subsampling += 1;
+    }
+    subsampling
+}
+
+impl Params {
+    /// The settings for luma, i.e. the first plane.
+    pub fn luma(&self) -> PlaneParams {
+        PlaneParams {
+            sw: self.sw,
+            sh: self.sh,
+            shape: self.shape,
+            blurv: self.blurv,
+            sstr: self.sstr,
+            scl: self.scl,
+        }
+    }
+
+    /// The settings for chroma subsampled by `2^ss_w` and `2^ss_h`.
+    ///
+    /// Unless overridden, the radii are scaled to the subsampling so that
+    /// they cover the same area of the picture as for luma, and everything
+    /// else is taken from luma.
+    pub fn chroma(&self, ss_w: u32, ss_h: u32) -> PlaneParams {
+        PlaneParams {
+            sw: self
+                .sw_c
+                .unwrap_or_else(|| subsampled_radius(self.sw, ss_w)),
+            sh: self
+                .sh_c
+                .unwrap_or_else(|| subsampled_radius(self.sh, ss_h)),
+            shape: self.shape,
+            blurv: self.blurv_c.unwrap_or(self.blurv),
+            sstr: self.sstr_c.unwrap_or(self.sstr),
+            scl: self.scl_c.unwrap_or(self.scl),
+        }
+    }
+
+    /// The settings for plane `plane` of `frame`, with the subsampling of
+    /// chroma derived from the plane sizes.
+    fn plane<T: Pixel>(&self, frame: &Frame<T>, plane: usize) -> PlaneParams {
+        if plane == 0 {
+            return self.luma();
+        }
+        let (luma, chroma) = (frame.plane(0), frame.plane(plane));
+        self.chroma(
+            subsampling(luma.width(), chroma.width()),
+            subsampling(luma.height(), chroma.height()),
+        )
+    }
+}
+
+fn repair_pass<T: Pixel>(src: &Frame<T>, params: &Params) -> Frame<T> {
+    src.map_planes(|i, plane| {
+        let (bob_top, bob_bottom) = bob(plane, params.mode, src.bit_depth);
+        let settings = params.plane(src, i);
+        cross_field_repair(
+            plane,
+            bob_top.as_ref(),
+            bob_bottom.as_ref(),
+            settings.sw,
+            settings.sh,
+            settings.shape,
+        )
+    })
+}
+
+/// The fields of `frame` bobbed to full frames, in temporal order.
+fn bob_frame<T: Pixel>(frame: &Frame<T>, params: &Params) -> (Frame<T>, Frame<T>) {
+    let (top, bottom): (Vec<_>, Vec<_>) = frame
+        .planes
+        .iter()
+        .map(|plane| bob(plane.as_ref(), params.mode, frame.bit_depth))
+        .unzip();
+    let top = Frame::new(top, frame.bit_depth);
+    let bottom = Frame::new(bottom, frame.bit_depth);
+    if params.tff {
+        (top, bottom)
+    } else {
+        (bottom, top)
+    }
+}
+
+fn mpeg2stinx_interlaced<T: Pixel>(
+    prev: &Frame<T>,
+    cur: &Frame<T>,
+    next: &Frame<T>,
+    params: &Params,
+) -> Frame<T> {
+    let progressive = Params {
+        interlaced: false,
+        ..*params
+    };
+    let (first, second) = bob_frame(cur, params);
+    // The neighbours of a field are the fields right before and after it
+    let (first, second) = if params.diffscl.is_some() {
+        let (_, prev_second) = bob_frame(prev, params);
+        let (next_first, _) = bob_frame(next, params);
+        (
+            mpeg2stinx(&prev_second, &first, &second, &progressive),
+            mpeg2stinx(&first, &second, &next_first, &progressive),
+        )
+    } else {
+        (
+            mpeg2stinx(&first, &first, &first, &progressive),
+            mpeg2stinx(&second, &second, &second, &progressive),
+        )
+    };
+    first.map_planes(|i, plane| {
+        let (first_field, _) = separate_fields(plane, params.tff);
+        let (_, second_field) = separate_fields(second.plane(i), params.tff);
+        weave_fields(first_field.as_ref(), second_field.as_ref(), params.tff)
+    })
+}
+
+/// Runs the whole filter on `cur`. `prev` and `next` are only used for
+/// temporal limiting, so callers may pass `cur` for them at the clip's edges.
+///
+/// With `params.interlaced`, each field of `cur` is bobbed to a frame,
+/// processed with the fields right before and after it as neighbours, and
+/// woven back from the rows of its own field, so that the output keeps the
+/// motion between the fields.
+pub fn mpeg2stinx<T: Pixel>(
+    prev: &Frame<T>,
+    cur: &Frame<T>,
+    next: &Frame<T>,
+    params: &Params,
+) -> Frame<T> {
+    if params.interlaced {
+        return mpeg2stinx_interlaced(prev, cur, next, params);
+    }
+    let bits = cur.bit_depth;
+
+    let mut passes: Vec<Frame<T>> = Vec::with_capacity(params.passes as usize);
+    for _ in 0..params.passes.max(1) {
+        let src = passes.last().unwrap_or(cur);
+        let repaired = repair_pass(src, params);
+        let repaired = match params.diffscl {
+            Some(diffscl) => temp_limit(src, &repaired, prev, next, diffscl),
+            None => repaired,
+        };
+        passes.push(repaired);
+    }
+
+    let weights = params.combine.weights(passes.len());
+    let nuked = cur.map_planes(|i, plane| {
+        let mut dst = Plane::new(plane.width(), plane.height());
+        let planes: Vec<_> = passes.iter().map(|pass| pass.plane(i)).collect();
+        match params.combine {
+            // The same as the general case, only faster
+            Combine::Average if planes.len() == 2 => average(planes[0], planes[1], dst.as_mut()),
+            Combine::Average | Combine::Weighted => weighted_average(
+                &planes,
+                &weights,
+                weights.iter().sum(),
+                Rounding::HalfUp,
+                bits,
+                dst.as_mut(),
+            ),
+            Combine::Median => median(&planes, dst.as_mut()),
+            Combine::Last => dst.as_mut().copy_from(planes[planes.len() - 1]),
+        }
+        let blurv = params.plane(cur, i).blurv;
+        if blurv > 0.0 {
+            blur_v(dst.as_ref(), blurv, bits)
+        } else {
+            dst
+        }
+    });
+    if !params.contra {
+        return nuked;
+    }
+
+    nuked.map_planes(|i, plane| {
+        let blurred = blur_v(blur_v(plane, 1.0, bits).as_ref(), 1.0, bits);
+        let mut dst = Plane::new(plane.width(), plane.height());
+        let settings = params.plane(cur, i);
+        contrasharpen(
+            cur.plane(i),
+            plane,
+            blurred.as_ref(),
+            dst.as_mut(),
+            settings.sstr,
+            settings.scl,
+            bits,
+        );
+        dst
+    })
+}
@@ -0,0 +1,261 @@
+use crate::simd::{SimdLut, SimdPixel};
+use std::fmt::Debug;
+
+/// An integer sample type that the kernels can operate on.
+pub trait Pixel:
+    Copy + Default + Ord + Debug + Send + Sync + SimdPixel + SimdLut + 'static
+{
+    fn to_i64(self) -> i64;
+
+    /// Converts back from a value that the caller has already clamped to the
+    /// valid range for the clip's bit depth.
+    fn from_i64(value: i64) -> Self;
+}
+
+macro_rules! impl_pixel {
+    ($pix_ty:ty) => {
+        impl Pixel for $pix_ty {
+            #[inline(always)]
+            fn to_i64(self) -> i64 {
+                self as i64
+            }
+
+            #[inline(always)]
+            fn from_i64(value: i64) -> Self {
+                value as $pix_ty
+            }
+        }
+    };
+}
+impl_pixel!(u8);
+impl_pixel!(u16);
+impl_pixel!(u32);
+
+/// A borrowed plane of pixels. `stride` is measured in pixels, not bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneRef<'a, T> {
+    data: &'a [T],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl<'a, T: Pixel> PlaneRef<'a, T> {
+    pub fn new(data: &'a [T], width: usize, height: usize, stride: usize) -> Self {
+        assert!(stride >= width, "stride must be at least the plane width");
+        assert!(
+            height == 0 || data.len() >= stride * (height - 1) + width,
+            "plane data is too short for its dimensions"
+        );
+        PlaneRef {
+            data,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    #[inline(always)]
+    pub fn row(&self, y: usize) -> &'a [T] {
+        let start = y * self.stride;
+        &self.data[start..start + self.width]
+    }
+
+    /// Returns row `y`, mirroring rows outside of the plane back inside it.
+    #[inline(always)]
+    pub(crate) fn row_mirrored(&self, y: isize) -> &'a [T] {
+        self.row(mirror(y, self.height))
+    }
+
+    #[inline(always)]
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.data[y * self.stride + x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + 'a {
+        let this = *self;
+        (0..self.height).map(move |y| this.row(y))
+    }
+}
+
+/// A mutably borrowed plane of pixels. `stride` is measured in pixels, not bytes.
+#[derive(Debug)]
+pub struct PlaneMut<'a, T> {
+    data: &'a mut [T],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl<'a, T: Pixel> PlaneMut<'a, T> {
+    pub fn new(data: &'a mut [T], width: usize, height: usize, stride: usize) -> Self {
+        assert!(stride >= width, "stride must be at least the plane width");
+        assert!(
+            height == 0 || data.len() >= stride * (height - 1) + width,
+            "plane data is too short for its dimensions"
+        );
+        PlaneMut {
+            data,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline(always)]
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        let start = y * self.stride;
+        &mut self.data[start..start + self.width]
+    }
+
+    pub fn as_ref(&self) -> PlaneRef<'_, T> {
+        PlaneRef::new(self.data, self.width, self.height, self.stride)
+    }
+
+    pub fn copy_from(&mut self, src: PlaneRef<T>) {
+        assert_same_size(self.width, self.height, &src);
+        for y in 0..self.height {
+            self.row_mut(y).copy_from_slice(src.row(y));
+        }
+    }
+}
+
+/// An owned, tightly packed plane of pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plane<T> {
+    data: Vec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T: Pixel> Plane<T> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Plane {
+            data: vec![T::default(); width * height],
+            width,
+            height,
+        }
+    }
+
+    pub fn from_vec(data: Vec<T>, width: usize, height: usize) -> Self {
+        assert_eq!(
+            data.len(),
+            width * height,
+            "plane data does not match its dimensions"
+        );
+        Plane {
+            data,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_fn<F: FnMut(usize, usize) -> T>(width: usize, height: usize, mut f: F) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+        Plane::from_vec(data, width, height)
+    }
+
+    pub fn from_ref(src: PlaneRef<T>) -> Self {
+        let mut data = Vec::with_capacity(src.width() * src.height());
+        for row in src.rows() {
+            data.extend_from_slice(row);
+        }
+        Plane::from_vec(data, src.width(), src.height())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    #[inline(always)]
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.data[y * self.width + x]
+    }
+
+    pub fn as_ref(&self) -> PlaneRef<'_, T> {
+        PlaneRef::new(&self.data, self.width, self.height, self.width)
+    }
+
+    pub fn as_mut(&mut self) -> PlaneMut<'_, T> {
+        PlaneMut::new(&mut self.data, self.width, self.height, self.width)
+    }
+}
+
+/// Mirrors an out of bounds coordinate back inside `0..len`,
+/// without repeating the edge sample.
+#[inline(always)]
+pub(crate) fn mirror(pos: isize, len: usize) -> usize {
+    let len = len as isize;
+    if len == 1 {
+        return 0;
+    }
+    let mut pos = pos;
+    loop {
+        if pos < 0 {
+            pos = -pos;
+        } else if pos >= len {
+            pos = 2 * (len - 1) - pos;
+        } else {
+            return pos as usize;
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn clamp_coord(pos: isize, len: usize) -> usize {
+    if pos < 0 {
+        0
+    } else if pos as usize >= len {
+        len - 1
+    } else {
+        pos as usize
+    }
+}
+
+pub(crate) fn assert_same_size<T: Pixel>(width: usize, height: usize, other: &PlaneRef<T>) {
+    assert!(
+        other.width() == width && other.height() == height,
+        "plane dimensions differ: expected {}x{}, got {}x{}",
+        width,
+        height,
+        other.width(),
+        other.height()
+    );
+}
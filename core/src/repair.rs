@@ -0,0 +1,77 @@
+use crate::fields::{separate_fields, weave_fields};
+use crate::kernels::{median3, median3_px};
+use crate::morphology::{expand_shape, inpand_shape, Shape};
+use crate::plane::{assert_same_size, Pixel, Plane, PlaneMut, PlaneRef};
+use std::cmp;
+
+/// Clamps each pixel of `src` to the range of the 3x3 neighbourhood of the
+/// same pixel in `repair_clip`.
+///
+/// Equivalent to `rgvs.Repair(mode=1)`, which also leaves the outermost pixels untouched.
+pub fn repair<T: Pixel>(src: PlaneRef<T>, repair_clip: PlaneRef<T>, mut dst: PlaneMut<T>) {
+    assert_same_size(src.width(), src.height(), &repair_clip);
+    assert_same_size(src.width(), src.height(), &dst.as_ref());
+    let (width, height) = (src.width(), src.height());
+    for y in 0..height {
+        let target = dst.row_mut(y);
+        target.copy_from_slice(src.row(y));
+        if y == 0 || y + 1 == height || width < 3 {
+            continue;
+        }
+        let rows = [
+            repair_clip.row(y - 1),
+            repair_clip.row(y),
+            repair_clip.row(y + 1),
+        ];
+        for x in 1..width - 1 {
+            let mut lo = rows[0][x];
+            let mut hi = lo;
+            for row in &rows {
+                for &px in &row[x - 1..=x + 1] {
+                    lo = cmp::min(lo, px);
+                    hi = cmp::max(hi, px);
+                }
+            }
+            target[x] = median3_px(target[x], lo, hi);
+        }
+    }
+}
+
+fn repair_against<T: Pixel>(
+    src: PlaneRef<T>,
+    bobbed: PlaneRef<T>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+) -> Plane<T> {
+    let mut dst = Plane::new(src.width(), src.height());
+    if sw == 1 && sh == 1 && shape == Shape::Square {
+        repair(src, bobbed, dst.as_mut());
+    } else {
+        let bobbed_ex = expand_shape(bobbed, sw, sh, shape);
+        let bobbed_in = inpand_shape(bobbed, sw, sh, shape);
+        median3(src, bobbed_ex.as_ref(), bobbed_in.as_ref(), dst.as_mut());
+    }
+    dst
+}
+
+/// Clamps each field of `src` to the local range of the other field, as
+/// interpolated to full height by `bob_top` and `bob_bottom`.
+///
+/// The local range is taken over the neighbourhood of the given `shape`
+/// reaching `sw` pixels to the sides and `sh` rows up and down.
+pub fn cross_field_repair<T: Pixel>(
+    src: PlaneRef<T>,
+    bob_top: PlaneRef<T>,
+    bob_bottom: PlaneRef<T>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+) -> Plane<T> {
+    let re = repair_against(src, bob_top, sw, sh, shape);
+    let ro = repair_against(src, bob_bottom, sw, sh, shape);
+    // The top field is repaired using the bottom field and vice versa
+    let (top, _) = separate_fields(ro.as_ref(), true);
+    let (_, bottom) = separate_fields(re.as_ref(), true);
+    weave_fields(top.as_ref(), bottom.as_ref(), true)
+}
@@ -0,0 +1,169 @@
+use crate::kernels::{clamp, max_value};
+use crate::plane::{clamp_coord, Pixel, Plane, PlaneRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeKernel {
+    Point,
+    Bilinear,
+    Spline36,
+}
+
+impl ResizeKernel {
+    fn support(self) -> f64 {
+        match self {
+            ResizeKernel::Point => 0.5,
+            ResizeKernel::Bilinear => 1.0,
+            ResizeKernel::Spline36 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeKernel::Point => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ResizeKernel::Bilinear => (1.0 - x).max(0.0),
+            ResizeKernel::Spline36 => {
+                if x < 1.0 {
+                    ((13.0 / 11.0 * x - 453.0 / 209.0) * x - 3.0 / 209.0) * x + 1.0
+                } else if x < 2.0 {
+                    let x = x - 1.0;
+                    ((-6.0 / 11.0 * x + 270.0 / 209.0) * x - 156.0 / 209.0) * x
+                } else if x < 3.0 {
+                    let x = x - 2.0;
+                    ((1.0 / 11.0 * x - 45.0 / 209.0) * x + 26.0 / 209.0) * x
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// The source samples, and their weights, contributing to one output sample.
+struct Taps {
+    start: isize,
+    weights: Vec<f64>,
+}
+
+// Output sample `i` is centered on source position `(i + 0.5) / scale - 0.5 + shift`,
+// matching the coordinate system of the VapourSynth resizers.
+fn build_taps(src_len: usize, dst_len: usize, shift: f64, kernel: ResizeKernel) -> Vec<Taps> {
+    let scale = dst_len as f64 / src_len as f64;
+    if kernel == ResizeKernel::Point {
+        return (0..dst_len)
+            .map(|i| {
+                let pos = (i as f64 + 0.5) / scale - 0.5 + shift;
+                Taps {
+                    start: (pos + 0.5).floor() as isize,
+                    weights: vec![1.0],
+                }
+            })
+            .collect();
+    }
+
+    // Widen the filter when downscaling, so that it doesn't alias
+    let filter_scale = scale.min(1.0);
+    let support = kernel.support() / filter_scale;
+    (0..dst_len)
+        .map(|i| {
+            let pos = (i as f64 + 0.5) / scale - 0.5 + shift;
+            let start = (pos - support).ceil() as isize;
+            let end = (pos + support).floor() as isize;
+            let mut weights: Vec<f64> = (start..=end)
+                .map(|j| kernel.weight((j as f64 - pos) * filter_scale))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            if sum.abs() > f64::EPSILON {
+                for weight in &mut weights {
+                    *weight /= sum;
+                }
+            }
+            Taps { start, weights }
+        })
+        .collect()
+}
+
+#[inline(always)]
+fn round_px<T: Pixel>(value: f64, max_pix_val: i64) -> T {
+    T::from_i64(clamp((value + 0.5).floor() as i64, 0, max_pix_val))
+}
+
+/// Resamples `src` vertically to `dst_height` rows. `shift` moves the source
+/// window down by that many source rows, like `src_top` in VapourSynth's resizers.
+pub fn resize_v<T: Pixel>(
+    src: PlaneRef<T>,
+    dst_height: usize,
+    shift: f64,
+    kernel: ResizeKernel,
+    bit_depth: u32,
+) -> Plane<T> {
+    let max_pix_val = max_value(bit_depth);
+    let taps = build_taps(src.height(), dst_height, shift, kernel);
+    let mut dst = Plane::new(src.width(), dst_height);
+    let mut acc = vec![0f64; src.width()];
+    let mut out = dst.as_mut();
+    for (y, taps) in taps.iter().enumerate() {
+        acc.iter_mut().for_each(|a| *a = 0.0);
+        for (k, &weight) in taps.weights.iter().enumerate() {
+            let row = src.row(clamp_coord(taps.start + k as isize, src.height()));
+            for (a, &px) in acc.iter_mut().zip(row.iter()) {
+                *a += px.to_i64() as f64 * weight;
+            }
+        }
+        for (target, &a) in out.row_mut(y).iter_mut().zip(acc.iter()) {
+            *target = round_px(a, max_pix_val);
+        }
+    }
+    dst
+}
+
+/// Resamples `src` horizontally to `dst_width` columns. `shift` moves the source
+/// window right by that many source columns.
+pub fn resize_h<T: Pixel>(
+    src: PlaneRef<T>,
+    dst_width: usize,
+    shift: f64,
+    kernel: ResizeKernel,
+    bit_depth: u32,
+) -> Plane<T> {
+    let max_pix_val = max_value(bit_depth);
+    let taps = build_taps(src.width(), dst_width, shift, kernel);
+    let mut dst = Plane::new(dst_width, src.height());
+    let mut out = dst.as_mut();
+    for y in 0..src.height() {
+        let row = src.row(y);
+        for (target, taps) in out.row_mut(y).iter_mut().zip(taps.iter()) {
+            let value: f64 = taps
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(k, &weight)| {
+                    row[clamp_coord(taps.start + k as isize, src.width())].to_i64() as f64 * weight
+                })
+                .sum();
+            *target = round_px(value, max_pix_val);
+        }
+    }
+    dst
+}
+
+/// Resizes `src` to `width`x`height`, keeping the centers of both images aligned.
+pub fn resize<T: Pixel>(
+    src: PlaneRef<T>,
+    width: usize,
+    height: usize,
+    kernel: ResizeKernel,
+    bit_depth: u32,
+) -> Plane<T> {
+    if src.width() == width && src.height() == height {
+        return Plane::from_ref(src);
+    }
+    let horizontal = resize_h(src, width, 0.0, kernel, bit_depth);
+    resize_v(horizontal.as_ref(), height, 0.0, kernel, bit_depth)
+}
@@ -0,0 +1,314 @@
+//! The `mt_lutxy` style kernels of `crate::kernels` on 32-bit lanes,
+//! written once for every instruction set implementing `Lanes`.
+//!
+//! Samples are widened to 32-bit integers, and the products are computed
+//! in f32 and truncated just like the scalar `*_px` functions do, so the
+//! results are bit exact. Only 8 and 16-bit samples are vectorized,
+//! larger ones don't convert to f32 exactly in a 32-bit lane.
+
+use super::scalar::Scalar;
+use super::LutOps;
+
+/// Products are limited to this, which keeps the lane arithmetic from
+/// overflowing without changing any result, since everything they are added
+/// to is at most 16 bits and clamped to at most 16 bits afterwards.
+pub(super) const MUL_LIMIT: f32 = 16_777_216.0;
+
+/// The 32-bit lane operations the kernels are built from.
+///
+/// The methods are unsafe for the same reason as those of `RowOps`.
+pub(super) trait Lanes {
+    const LANES: usize;
+    type V: Copy;
+    type F: Copy;
+
+    unsafe fn splat(value: i32) -> Self::V;
+    unsafe fn splat_f(value: f32) -> Self::F;
+    unsafe fn load_u8(ptr: *const u8) -> Self::V;
+    unsafe fn load_u16(ptr: *const u16) -> Self::V;
+    /// Stores lanes that are within the range of `u8`.
+    unsafe fn store_u8(ptr: *mut u8, value: Self::V);
+    /// Stores lanes that are within the range of `u16`.
+    unsafe fn store_u16(ptr: *mut u16, value: Self::V);
+    unsafe fn add(a: Self::V, b: Self::V) -> Self::V;
+    unsafe fn sub(a: Self::V, b: Self::V) -> Self::V;
+    unsafe fn min(a: Self::V, b: Self::V) -> Self::V;
+    unsafe fn max(a: Self::V, b: Self::V) -> Self::V;
+    unsafe fn abs(a: Self::V) -> Self::V;
+    unsafe fn and(a: Self::V, b: Self::V) -> Self::V;
+    unsafe fn or(a: Self::V, b: Self::V) -> Self::V;
+    /// All bits set in the lanes where `a < b`, none elsewhere.
+    unsafe fn lt(a: Self::V, b: Self::V) -> Self::V;
+    /// `a` in the lanes where `mask` is set, `b` elsewhere.
+    unsafe fn select(mask: Self::V, a: Self::V, b: Self::V) -> Self::V;
+    /// `(a as f32 * b) as i32`, with NaN giving 0 like `as` does,
+    /// and limited to `±MUL_LIMIT`.
+    unsafe fn mul_trunc(a: Self::V, b: Self::F) -> Self::V;
+}
+
+/// The sample types that are loaded into and stored from 32-bit lanes.
+pub(super) trait Widen: Copy {
+    unsafe fn load<L: Lanes>(ptr: *const Self) -> L::V;
+    unsafe fn store<L: Lanes>(ptr: *mut Self, value: L::V);
+}
+
+impl Widen for u8 {
+    #[inline(always)]
+    unsafe fn load<L: Lanes>(ptr: *const Self) -> L::V {
+        L::load_u8(ptr)
+    }
+
+    #[inline(always)]
+    unsafe fn store<L: Lanes>(ptr: *mut Self, value: L::V) {
+        L::store_u8(ptr, value)
+    }
+}
+
+impl Widen for u16 {
+    #[inline(always)]
+    unsafe fn load<L: Lanes>(ptr: *const Self) -> L::V {
+        L::load_u16(ptr)
+    }
+
+    #[inline(always)]
+    unsafe fn store<L: Lanes>(ptr: *mut Self, value: L::V) {
+        L::store_u16(ptr, value)
+    }
+}
+
+/// The constants of one call, splatted once.
+struct Consts<L: Lanes> {
+    zero: L::V,
+    half: L::V,
+    max: L::V,
+}
+
+// Derived, these would require `L: Copy`
+impl<L: Lanes> Clone for Consts<L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L: Lanes> Copy for Consts<L> {}
+
+impl<L: Lanes> Consts<L> {
+    #[inline(always)]
+    unsafe fn new(half: i64, max: i64) -> Self {
+        Consts {
+            zero: L::splat(0),
+            half: L::splat(half as i32),
+            max: L::splat(max as i32),
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn clamp(self, value: L::V) -> L::V {
+        L::min(L::max(value, self.zero), self.max)
+    }
+}
+
+// The lane versions of the `*_px` functions of `crate::kernels`
+
+#[inline(always)]
+unsafe fn sharp_lanes<L: Lanes>(c: Consts<L>, x: L::V, y: L::V, strength: L::F) -> L::V {
+    c.clamp(L::add(x, L::mul_trunc(L::sub(x, y), strength)))
+}
+
+#[inline(always)]
+unsafe fn sharpd_lanes<L: Lanes>(c: Consts<L>, x: L::V, y: L::V, strength: L::F) -> L::V {
+    c.clamp(L::add(c.half, L::mul_trunc(L::sub(x, y), strength)))
+}
+
+#[inline(always)]
+unsafe fn limd_lanes<L: Lanes>(c: Consts<L>, x: L::V, y: L::V, scale: L::F) -> L::V {
+    let (x, y) = (L::sub(x, c.half), L::sub(y, c.half));
+    let closest = L::select(L::lt(L::abs(x), L::abs(y)), x, y);
+    // `x * y < 0` without the multiplication, which SSE2 lacks for 32 bits
+    let opposite = L::or(
+        L::and(L::lt(x, c.zero), L::lt(c.zero, y)),
+        L::and(L::lt(c.zero, x), L::lt(y, c.zero)),
+    );
+    // Scaling by 1 leaves the integer as it is
+    let scaled = L::select(opposite, L::mul_trunc(closest, scale), closest);
+    c.clamp(L::add(c.half, scaled))
+}
+
+#[inline(always)]
+unsafe fn make_diff_lanes<L: Lanes>(c: Consts<L>, x: L::V, y: L::V) -> L::V {
+    c.clamp(L::add(L::sub(x, y), c.half))
+}
+
+#[inline(always)]
+unsafe fn add_diff_lanes<L: Lanes>(c: Consts<L>, x: L::V, y: L::V) -> L::V {
+    c.clamp(L::sub(L::add(x, y), c.half))
+}
+
+#[inline(always)]
+unsafe fn median3_lanes<L: Lanes>(x: L::V, y: L::V, z: L::V) -> L::V {
+    L::max(L::min(x, y), L::min(L::max(x, y), z))
+}
+
+/// Runs `op` on the whole vectors of the rows, returning how many samples
+/// that covered, so that the caller can finish the rows with `Scalar`.
+#[inline(always)]
+unsafe fn rows2<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    op: impl Fn(L::V, L::V) -> L::V,
+) -> usize {
+    let body = dst.len() / L::LANES * L::LANES;
+    for i in (0..body).step_by(L::LANES) {
+        let x = T::load::<L>(x.as_ptr().add(i));
+        let y = T::load::<L>(y.as_ptr().add(i));
+        T::store::<L>(dst.as_mut_ptr().add(i), op(x, y));
+    }
+    body
+}
+
+#[inline(always)]
+unsafe fn rows3<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    z: &[T],
+    dst: &mut [T],
+    op: impl Fn(L::V, L::V, L::V) -> L::V,
+) -> usize {
+    let body = dst.len() / L::LANES * L::LANES;
+    for i in (0..body).step_by(L::LANES) {
+        let x = T::load::<L>(x.as_ptr().add(i));
+        let y = T::load::<L>(y.as_ptr().add(i));
+        let z = T::load::<L>(z.as_ptr().add(i));
+        T::store::<L>(dst.as_mut_ptr().add(i), op(x, y, z));
+    }
+    body
+}
+
+// The row kernels, inlined into the `#[target_feature]` functions of `impl_lut_ops`
+
+#[inline(always)]
+pub(super) unsafe fn make_diff<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    half: i64,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(half, max);
+    let body = rows2::<L, T>(x, y, dst, |x, y| make_diff_lanes(c, x, y));
+    <Scalar as LutOps<T>>::make_diff(&x[body..], &y[body..], &mut dst[body..], half, max);
+}
+
+#[inline(always)]
+pub(super) unsafe fn add_diff<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    half: i64,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(half, max);
+    let body = rows2::<L, T>(x, y, dst, |x, y| add_diff_lanes(c, x, y));
+    <Scalar as LutOps<T>>::add_diff(&x[body..], &y[body..], &mut dst[body..], half, max);
+}
+
+#[inline(always)]
+pub(super) unsafe fn sharp<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    strength: f32,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(0, max);
+    let s = L::splat_f(strength);
+    let body = rows2::<L, T>(x, y, dst, |x, y| sharp_lanes(c, x, y, s));
+    <Scalar as LutOps<T>>::sharp(&x[body..], &y[body..], &mut dst[body..], strength, max);
+}
+
+#[inline(always)]
+pub(super) unsafe fn sharpd<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    strength: f32,
+    half: i64,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(half, max);
+    let s = L::splat_f(strength);
+    let body = rows2::<L, T>(x, y, dst, |x, y| sharpd_lanes(c, x, y, s));
+    <Scalar as LutOps<T>>::sharpd(
+        &x[body..],
+        &y[body..],
+        &mut dst[body..],
+        strength,
+        half,
+        max,
+    );
+}
+
+#[inline(always)]
+pub(super) unsafe fn limd<L: Lanes, T: Widen>(
+    x: &[T],
+    y: &[T],
+    dst: &mut [T],
+    scale: f32,
+    half: i64,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(half, max);
+    let s = L::splat_f(scale);
+    let body = rows2::<L, T>(x, y, dst, |x, y| limd_lanes(c, x, y, s));
+    <Scalar as LutOps<T>>::limd(&x[body..], &y[body..], &mut dst[body..], scale, half, max);
+}
+
+#[inline(always)]
+#[allow(clippy::too_many_arguments)]
+pub(super) unsafe fn contrasharpen<L: Lanes, T: Widen>(
+    src: &[T],
+    nuked: &[T],
+    blurred: &[T],
+    dst: &mut [T],
+    sstr: f32,
+    scl: f32,
+    half: i64,
+    max: i64,
+) where
+    Scalar: LutOps<T>,
+{
+    let c = Consts::<L>::new(half, max);
+    let (sstr_v, scl_v) = (L::splat_f(sstr), L::splat_f(scl));
+    let body = if scl == 0.0 {
+        rows3::<L, T>(src, nuked, blurred, dst, |s, n, b| {
+            median3_lanes::<L>(n, sharp_lanes(c, n, b, sstr_v), s)
+        })
+    } else {
+        rows3::<L, T>(src, nuked, blurred, dst, |s, n, b| {
+            let nukedd = make_diff_lanes(c, s, n);
+            let sharpd = sharpd_lanes(c, n, b, sstr_v);
+            add_diff_lanes(c, n, limd_lanes(c, sharpd, nukedd, scl_v))
+        })
+    };
+    <Scalar as LutOps<T>>::contrasharpen(
+        &src[body..],
+        &nuked[body..],
+        &blurred[body..],
+        &mut dst[body..],
+        sstr,
+        scl,
+        half,
+        max,
+    );
+}
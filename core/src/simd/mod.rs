@@ -0,0 +1,530 @@
+//! Row kernels for the per-pixel minimum, maximum, median and absolute
+//! difference, and for the `mt_lutxy` style kernels of `crate::kernels`,
+//! vectorized for the instruction sets the CPU supports.
+//!
+//! Every instruction set must produce exactly the same output as
+//! [`Isa::Scalar`], which the `simd` integration tests check.
+
+/// Implements `RowOps` with the vector type `$vec` and the given intrinsics,
+/// handling the part of each row that does not fill a whole vector with `Scalar`.
+///
+/// `$load` and `$store` take pointers to samples rather than to vectors.
+/// Defined before the modules below so that they can use it.
+macro_rules! impl_row_ops {
+    (
+        $isa:ty, $feature:literal, $pix_ty:ty, $vec:ty,
+        load: $load:path, store: $store:path,
+        min: $min:path, max: $max:path, sub: $sub:path,
+        splat: |$value:ident| $splat:expr
+    ) => {
+        impl RowOps<$pix_ty> for $isa {
+            #[target_feature(enable = $feature)]
+            unsafe fn min(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty]) {
+                const LANES: usize = std::mem::size_of::<$vec>() / std::mem::size_of::<$pix_ty>();
+                let body = dst.len() / LANES * LANES;
+                for i in (0..body).step_by(LANES) {
+                    let x = $load(x.as_ptr().add(i));
+                    let y = $load(y.as_ptr().add(i));
+                    $store(dst.as_mut_ptr().add(i), $min(x, y));
+                }
+                <crate::simd::scalar::Scalar as RowOps<$pix_ty>>::min(
+                    &x[body..],
+                    &y[body..],
+                    &mut dst[body..],
+                );
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn max(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty]) {
+                const LANES: usize = std::mem::size_of::<$vec>() / std::mem::size_of::<$pix_ty>();
+                let body = dst.len() / LANES * LANES;
+                for i in (0..body).step_by(LANES) {
+                    let x = $load(x.as_ptr().add(i));
+                    let y = $load(y.as_ptr().add(i));
+                    $store(dst.as_mut_ptr().add(i), $max(x, y));
+                }
+                <crate::simd::scalar::Scalar as RowOps<$pix_ty>>::max(
+                    &x[body..],
+                    &y[body..],
+                    &mut dst[body..],
+                );
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn median3(x: &[$pix_ty], y: &[$pix_ty], z: &[$pix_ty], dst: &mut [$pix_ty]) {
+                const LANES: usize = std::mem::size_of::<$vec>() / std::mem::size_of::<$pix_ty>();
+                let body = dst.len() / LANES * LANES;
+                for i in (0..body).step_by(LANES) {
+                    let x = $load(x.as_ptr().add(i));
+                    let y = $load(y.as_ptr().add(i));
+                    let z = $load(z.as_ptr().add(i));
+                    let median = $max($min(x, y), $min($max(x, y), z));
+                    $store(dst.as_mut_ptr().add(i), median);
+                }
+                <crate::simd::scalar::Scalar as RowOps<$pix_ty>>::median3(
+                    &x[body..],
+                    &y[body..],
+                    &z[body..],
+                    &mut dst[body..],
+                );
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn diff(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty], max: $pix_ty) {
+                const LANES: usize = std::mem::size_of::<$vec>() / std::mem::size_of::<$pix_ty>();
+                let body = dst.len() / LANES * LANES;
+                let $value = max;
+                let limit = $splat;
+                for i in (0..body).step_by(LANES) {
+                    let x = $load(x.as_ptr().add(i));
+                    let y = $load(y.as_ptr().add(i));
+                    let diff = $sub($max(x, y), $min(x, y));
+                    $store(dst.as_mut_ptr().add(i), $min(diff, limit));
+                }
+                <crate::simd::scalar::Scalar as RowOps<$pix_ty>>::diff(
+                    &x[body..],
+                    &y[body..],
+                    &mut dst[body..],
+                    max,
+                );
+            }
+        }
+    };
+}
+
+/// Implements `LutOps` for `$pix_ty` with the kernels of the `lut` module,
+/// or, given `scalar`, by handing the rows to `Scalar`.
+macro_rules! impl_lut_ops {
+    ($isa:ty, $feature:literal, $pix_ty:ty) => {
+        impl LutOps<$pix_ty> for $isa {
+            #[target_feature(enable = $feature)]
+            unsafe fn make_diff(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                half: i64,
+                max: i64,
+            ) {
+                crate::simd::lut::make_diff::<$isa, $pix_ty>(x, y, dst, half, max)
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn add_diff(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                half: i64,
+                max: i64,
+            ) {
+                crate::simd::lut::add_diff::<$isa, $pix_ty>(x, y, dst, half, max)
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn sharp(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                strength: f32,
+                max: i64,
+            ) {
+                crate::simd::lut::sharp::<$isa, $pix_ty>(x, y, dst, strength, max)
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn sharpd(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                strength: f32,
+                half: i64,
+                max: i64,
+            ) {
+                crate::simd::lut::sharpd::<$isa, $pix_ty>(x, y, dst, strength, half, max)
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn limd(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                scale: f32,
+                half: i64,
+                max: i64,
+            ) {
+                crate::simd::lut::limd::<$isa, $pix_ty>(x, y, dst, scale, half, max)
+            }
+
+            #[target_feature(enable = $feature)]
+            unsafe fn contrasharpen(
+                src: &[$pix_ty],
+                nuked: &[$pix_ty],
+                blurred: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                sstr: f32,
+                scl: f32,
+                half: i64,
+                max: i64,
+            ) {
+                crate::simd::lut::contrasharpen::<$isa, $pix_ty>(
+                    src, nuked, blurred, dst, sstr, scl, half, max,
+                )
+            }
+        }
+    };
+    ($isa:ty, $pix_ty:ty, scalar) => {
+        impl LutOps<$pix_ty> for $isa {
+            unsafe fn make_diff(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                half: i64,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::make_diff(x, y, dst, half, max)
+            }
+
+            unsafe fn add_diff(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                half: i64,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::add_diff(x, y, dst, half, max)
+            }
+
+            unsafe fn sharp(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                strength: f32,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::sharp(x, y, dst, strength, max)
+            }
+
+            unsafe fn sharpd(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                strength: f32,
+                half: i64,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::sharpd(
+                    x, y, dst, strength, half, max,
+                )
+            }
+
+            unsafe fn limd(
+                x: &[$pix_ty],
+                y: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                scale: f32,
+                half: i64,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::limd(x, y, dst, scale, half, max)
+            }
+
+            unsafe fn contrasharpen(
+                src: &[$pix_ty],
+                nuked: &[$pix_ty],
+                blurred: &[$pix_ty],
+                dst: &mut [$pix_ty],
+                sstr: f32,
+                scl: f32,
+                half: i64,
+                max: i64,
+            ) {
+                <crate::simd::scalar::Scalar as LutOps<$pix_ty>>::contrasharpen(
+                    src, nuked, blurred, dst, sstr, scl, half, max,
+                )
+            }
+        }
+    };
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod lut;
+#[cfg(target_arch = "aarch64")]
+mod neon;
+mod scalar;
+#[cfg(target_arch = "x86_64")]
+mod x86;
+
+/// An instruction set the row kernels can be run with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isa {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Sse2,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+}
+
+impl Isa {
+    /// The fastest instruction set supported by the running CPU.
+    pub fn detect() -> Self {
+        *Isa::available().last().unwrap()
+    }
+
+    /// All instruction sets supported by the running CPU, slowest first.
+    pub fn available() -> Vec<Self> {
+        let mut isas = vec![Isa::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            // SSE2 is part of the x86_64 baseline
+            isas.push(Isa::Sse2);
+            if is_x86_feature_detected!("avx2") {
+                isas.push(Isa::Avx2);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        isas.push(Isa::Neon);
+        isas
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx2 => is_x86_feature_detected!("avx2"),
+            _ => true,
+        }
+    }
+}
+
+/// The row operations of each instruction set.
+///
+/// The methods are unsafe because they may only be called when the
+/// instruction set is supported by the running CPU.
+trait RowOps<T> {
+    unsafe fn min(x: &[T], y: &[T], dst: &mut [T]);
+    unsafe fn max(x: &[T], y: &[T], dst: &mut [T]);
+    unsafe fn median3(x: &[T], y: &[T], z: &[T], dst: &mut [T]);
+    /// The absolute difference, limited to `max`.
+    unsafe fn diff(x: &[T], y: &[T], dst: &mut [T], max: T);
+}
+
+/// The `mt_lutxy` style row operations of each instruction set, taking the
+/// same arguments as the `*_px` functions of `crate::kernels`.
+///
+/// The methods are unsafe for the same reason as those of `RowOps`.
+#[allow(clippy::too_many_arguments)]
+trait LutOps<T> {
+    unsafe fn make_diff(x: &[T], y: &[T], dst: &mut [T], half: i64, max: i64);
+    unsafe fn add_diff(x: &[T], y: &[T], dst: &mut [T], half: i64, max: i64);
+    unsafe fn sharp(x: &[T], y: &[T], dst: &mut [T], strength: f32, max: i64);
+    unsafe fn sharpd(x: &[T], y: &[T], dst: &mut [T], strength: f32, half: i64, max: i64);
+    unsafe fn limd(x: &[T], y: &[T], dst: &mut [T], scale: f32, half: i64, max: i64);
+    unsafe fn contrasharpen(
+        src: &[T],
+        nuked: &[T],
+        blurred: &[T],
+        dst: &mut [T],
+        sstr: f32,
+        scl: f32,
+        half: i64,
+        max: i64,
+    );
+}
+
+/// Sample types with vectorized row kernels.
+///
+/// All rows passed to one call must be of the same length.
+pub trait SimdPixel: Sized {
+    fn min_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self]);
+    fn max_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self]);
+    fn median3_row(isa: Isa, x: &[Self], y: &[Self], z: &[Self], dst: &mut [Self]);
+    fn diff_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self], max: Self);
+}
+
+fn check_rows<T>(isa: Isa, rows: &[&[T]], dst: &[T]) {
+    assert!(isa.is_available(), "{:?} is not supported by this CPU", isa);
+    assert!(
+        rows.iter().all(|row| row.len() == dst.len()),
+        "rows differ in length"
+    );
+}
+
+/// Sample types with vectorized `mt_lutxy` style row kernels, which take
+/// the same arguments as the `*_px` functions of `crate::kernels`.
+///
+/// All rows passed to one call must be of the same length.
+pub trait SimdLut: Sized {
+    fn make_diff_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self], half: i64, max: i64);
+    fn add_diff_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self], half: i64, max: i64);
+    fn sharp_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self], strength: f32, max: i64);
+    #[allow(clippy::too_many_arguments)]
+    fn sharpd_row(
+        isa: Isa,
+        x: &[Self],
+        y: &[Self],
+        dst: &mut [Self],
+        strength: f32,
+        half: i64,
+        max: i64,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn limd_row(
+        isa: Isa,
+        x: &[Self],
+        y: &[Self],
+        dst: &mut [Self],
+        scale: f32,
+        half: i64,
+        max: i64,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn contrasharpen_row(
+        isa: Isa,
+        src: &[Self],
+        nuked: &[Self],
+        blurred: &[Self],
+        dst: &mut [Self],
+        sstr: f32,
+        scl: f32,
+        half: i64,
+        max: i64,
+    );
+}
+
+macro_rules! dispatch {
+    ($ops:ident<$ty:ty>, $isa:expr, $op:ident($($arg:expr),*)) => {
+        // SAFETY: `check_rows` has verified that the CPU supports `$isa`,
+        // and that all rows are as long as the destination
+        unsafe {
+            match $isa {
+                Isa::Scalar => <scalar::Scalar as $ops<$ty>>::$op($($arg),*),
+                #[cfg(target_arch = "x86_64")]
+                Isa::Sse2 => <x86::Sse2 as $ops<$ty>>::$op($($arg),*),
+                #[cfg(target_arch = "x86_64")]
+                Isa::Avx2 => <x86::Avx2 as $ops<$ty>>::$op($($arg),*),
+                #[cfg(target_arch = "aarch64")]
+                Isa::Neon => <neon::Neon as $ops<$ty>>::$op($($arg),*),
+            }
+        }
+    };
+}
+
+macro_rules! impl_simd_pixel {
+    ($pix_ty:ty) => {
+        impl SimdPixel for $pix_ty {
+            fn min_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self]) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(RowOps<$pix_ty>, isa, min(x, y, dst))
+            }
+
+            fn max_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self]) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(RowOps<$pix_ty>, isa, max(x, y, dst))
+            }
+
+            fn median3_row(isa: Isa, x: &[Self], y: &[Self], z: &[Self], dst: &mut [Self]) {
+                check_rows(isa, &[x, y, z], dst);
+                dispatch!(RowOps<$pix_ty>, isa, median3(x, y, z, dst))
+            }
+
+            fn diff_row(isa: Isa, x: &[Self], y: &[Self], dst: &mut [Self], max: Self) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(RowOps<$pix_ty>, isa, diff(x, y, dst, max))
+            }
+        }
+    };
+}
+impl_simd_pixel!(u8);
+impl_simd_pixel!(u16);
+impl_simd_pixel!(u32);
+impl_simd_pixel!(f32);
+
+macro_rules! impl_simd_lut {
+    ($pix_ty:ty) => {
+        impl SimdLut for $pix_ty {
+            fn make_diff_row(
+                isa: Isa,
+                x: &[Self],
+                y: &[Self],
+                dst: &mut [Self],
+                half: i64,
+                max: i64,
+            ) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(LutOps<$pix_ty>, isa, make_diff(x, y, dst, half, max))
+            }
+
+            fn add_diff_row(
+                isa: Isa,
+                x: &[Self],
+                y: &[Self],
+                dst: &mut [Self],
+                half: i64,
+                max: i64,
+            ) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(LutOps<$pix_ty>, isa, add_diff(x, y, dst, half, max))
+            }
+
+            fn sharp_row(
+                isa: Isa,
+                x: &[Self],
+                y: &[Self],
+                dst: &mut [Self],
+                strength: f32,
+                max: i64,
+            ) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(LutOps<$pix_ty>, isa, sharp(x, y, dst, strength, max))
+            }
+
+            fn sharpd_row(
+                isa: Isa,
+                x: &[Self],
+                y: &[Self],
+                dst: &mut [Self],
+                strength: f32,
+                half: i64,
+                max: i64,
+            ) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(LutOps<$pix_ty>, isa, sharpd(x, y, dst, strength, half, max))
+            }
+
+            fn limd_row(
+                isa: Isa,
+                x: &[Self],
+                y: &[Self],
+                dst: &mut [Self],
+                scale: f32,
+                half: i64,
+                max: i64,
+            ) {
+                check_rows(isa, &[x, y], dst);
+                dispatch!(LutOps<$pix_ty>, isa, limd(x, y, dst, scale, half, max))
+            }
+
+            fn contrasharpen_row(
+                isa: Isa,
+                src: &[Self],
+                nuked: &[Self],
+                blurred: &[Self],
+                dst: &mut [Self],
+                sstr: f32,
+                scl: f32,
+                half: i64,
+                max: i64,
+            ) {
+                check_rows(isa, &[src, nuked, blurred], dst);
+                dispatch!(
+                    LutOps<$pix_ty>,
+                    isa,
+                    contrasharpen(src, nuked, blurred, dst, sstr, scl, half, max)
+                )
+            }
+        }
+    };
+}
+impl_simd_lut!(u8);
+impl_simd_lut!(u16);
+impl_simd_lut!(u32);
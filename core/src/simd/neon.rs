@@ -0,0 +1,162 @@
+use super::lut::{Lanes, MUL_LIMIT};
+use super::{LutOps, RowOps};
+use std::arch::aarch64::*;
+use std::ptr;
+
+pub(super) struct Neon;
+
+impl_row_ops!(
+    Neon, "neon", u8, uint8x16_t,
+    load: vld1q_u8, store: vst1q_u8,
+    min: vminq_u8, max: vmaxq_u8, sub: vsubq_u8,
+    splat: |max| vdupq_n_u8(max)
+);
+impl_row_ops!(
+    Neon, "neon", u16, uint16x8_t,
+    load: vld1q_u16, store: vst1q_u16,
+    min: vminq_u16, max: vmaxq_u16, sub: vsubq_u16,
+    splat: |max| vdupq_n_u16(max)
+);
+impl_row_ops!(
+    Neon, "neon", u32, uint32x4_t,
+    load: vld1q_u32, store: vst1q_u32,
+    min: vminq_u32, max: vmaxq_u32, sub: vsubq_u32,
+    splat: |max| vdupq_n_u32(max)
+);
+
+// `fmin` and `fmax` order -0.0 below 0.0 and propagate NaN, unlike the
+// `minps` and `maxps` semantics the scalar kernels follow
+
+#[target_feature(enable = "neon")]
+#[inline]
+unsafe fn min_f32_neon(a: float32x4_t, b: float32x4_t) -> float32x4_t {
+    vbslq_f32(vcltq_f32(a, b), a, b)
+}
+
+#[target_feature(enable = "neon")]
+#[inline]
+unsafe fn max_f32_neon(a: float32x4_t, b: float32x4_t) -> float32x4_t {
+    vbslq_f32(vcgtq_f32(a, b), a, b)
+}
+
+impl_row_ops!(
+    Neon, "neon", f32, float32x4_t,
+    load: vld1q_f32, store: vst1q_f32,
+    min: min_f32_neon, max: max_f32_neon, sub: vsubq_f32,
+    splat: |max| vdupq_n_f32(max)
+);
+
+impl Lanes for Neon {
+    const LANES: usize = 4;
+    type V = int32x4_t;
+    type F = float32x4_t;
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn splat(value: i32) -> int32x4_t {
+        vdupq_n_s32(value)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn splat_f(value: f32) -> float32x4_t {
+        vdupq_n_f32(value)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn load_u8(ptr: *const u8) -> int32x4_t {
+        let bytes = vcreate_u8(u64::from(ptr::read_unaligned(ptr as *const u32)));
+        vreinterpretq_s32_u32(vmovl_u16(vget_low_u16(vmovl_u8(bytes))))
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn load_u16(ptr: *const u16) -> int32x4_t {
+        vreinterpretq_s32_u32(vmovl_u16(vld1_u16(ptr)))
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn store_u8(ptr: *mut u8, value: int32x4_t) {
+        let words = vmovn_u32(vreinterpretq_u32_s32(value));
+        let bytes = vmovn_u16(vcombine_u16(words, words));
+        ptr::write_unaligned(
+            ptr as *mut u32,
+            vget_lane_u32::<0>(vreinterpret_u32_u8(bytes)),
+        );
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn store_u16(ptr: *mut u16, value: int32x4_t) {
+        vst1_u16(ptr, vmovn_u32(vreinterpretq_u32_s32(value)))
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn add(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vaddq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn sub(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vsubq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn min(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vminq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn max(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vmaxq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn abs(a: int32x4_t) -> int32x4_t {
+        vabsq_s32(a)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn and(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vandq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn or(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vorrq_s32(a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn lt(a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vreinterpretq_s32_u32(vcltq_s32(a, b))
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn select(mask: int32x4_t, a: int32x4_t, b: int32x4_t) -> int32x4_t {
+        vbslq_s32(vreinterpretq_u32_s32(mask), a, b)
+    }
+
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn mul_trunc(a: int32x4_t, b: float32x4_t) -> int32x4_t {
+        // NaN passes through the limits and converts to 0
+        let product = vmulq_f32(vcvtq_f32_s32(a), b);
+        let product = vmaxq_f32(product, vdupq_n_f32(-MUL_LIMIT));
+        vcvtq_s32_f32(vminq_f32(product, vdupq_n_f32(MUL_LIMIT)))
+    }
+}
+
+impl_lut_ops!(Neon, "neon", u8);
+impl_lut_ops!(Neon, "neon", u16);
+impl_lut_ops!(Neon, u32, scalar);
@@ -0,0 +1,153 @@
+use super::{LutOps, RowOps};
+use crate::kernels::{add_diff_px, limd_px, make_diff_px, sharp_px, sharpd_px};
+use crate::plane::Pixel;
+use std::cmp;
+
+pub(super) struct Scalar;
+
+#[inline(always)]
+fn median3_px<T: Ord + Copy>(x: T, y: T, z: T) -> T {
+    cmp::max(cmp::min(x, y), cmp::min(cmp::max(x, y), z))
+}
+
+macro_rules! impl_scalar {
+    ($pix_ty:ty) => {
+        impl RowOps<$pix_ty> for Scalar {
+            unsafe fn min(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty]) {
+                for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+                    *target = cmp::min(x, y);
+                }
+            }
+
+            unsafe fn max(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty]) {
+                for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+                    *target = cmp::max(x, y);
+                }
+            }
+
+            unsafe fn median3(x: &[$pix_ty], y: &[$pix_ty], z: &[$pix_ty], dst: &mut [$pix_ty]) {
+                for (((&x, &y), &z), target) in
+                    x.iter().zip(y.iter()).zip(z.iter()).zip(dst.iter_mut())
+                {
+                    *target = median3_px(x, y, z);
+                }
+            }
+
+            unsafe fn diff(x: &[$pix_ty], y: &[$pix_ty], dst: &mut [$pix_ty], max: $pix_ty) {
+                for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+                    *target = cmp::min(cmp::max(x, y) - cmp::min(x, y), max);
+                }
+            }
+        }
+    };
+}
+impl_scalar!(u8);
+impl_scalar!(u16);
+impl_scalar!(u32);
+
+// Floats follow the `minps` and `maxps` instructions, which return their
+// second operand when the operands compare equal or either is NaN, so that
+// every instruction set gives the same output even for -0.0 and NaN
+
+#[inline(always)]
+fn min_f32(x: f32, y: f32) -> f32 {
+    if x < y {
+        x
+    } else {
+        y
+    }
+}
+
+#[inline(always)]
+fn max_f32(x: f32, y: f32) -> f32 {
+    if x > y {
+        x
+    } else {
+        y
+    }
+}
+
+impl RowOps<f32> for Scalar {
+    unsafe fn min(x: &[f32], y: &[f32], dst: &mut [f32]) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = min_f32(x, y);
+        }
+    }
+
+    unsafe fn max(x: &[f32], y: &[f32], dst: &mut [f32]) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = max_f32(x, y);
+        }
+    }
+
+    unsafe fn median3(x: &[f32], y: &[f32], z: &[f32], dst: &mut [f32]) {
+        for (((&x, &y), &z), target) in x.iter().zip(y.iter()).zip(z.iter()).zip(dst.iter_mut()) {
+            *target = max_f32(min_f32(x, y), min_f32(max_f32(x, y), z));
+        }
+    }
+
+    unsafe fn diff(x: &[f32], y: &[f32], dst: &mut [f32], max: f32) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = min_f32(max_f32(x, y) - min_f32(x, y), max);
+        }
+    }
+}
+
+impl<T: Pixel> LutOps<T> for Scalar {
+    unsafe fn make_diff(x: &[T], y: &[T], dst: &mut [T], half: i64, max: i64) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = T::from_i64(make_diff_px(x.to_i64(), y.to_i64(), half, max));
+        }
+    }
+
+    unsafe fn add_diff(x: &[T], y: &[T], dst: &mut [T], half: i64, max: i64) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = T::from_i64(add_diff_px(x.to_i64(), y.to_i64(), half, max));
+        }
+    }
+
+    unsafe fn sharp(x: &[T], y: &[T], dst: &mut [T], strength: f32, max: i64) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = T::from_i64(sharp_px(x.to_i64(), y.to_i64(), strength, max));
+        }
+    }
+
+    unsafe fn sharpd(x: &[T], y: &[T], dst: &mut [T], strength: f32, half: i64, max: i64) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = T::from_i64(sharpd_px(x.to_i64(), y.to_i64(), strength, half, max));
+        }
+    }
+
+    unsafe fn limd(x: &[T], y: &[T], dst: &mut [T], scale: f32, half: i64, max: i64) {
+        for ((&x, &y), target) in x.iter().zip(y.iter()).zip(dst.iter_mut()) {
+            *target = T::from_i64(limd_px(x.to_i64(), y.to_i64(), scale, half, max));
+        }
+    }
+
+    unsafe fn contrasharpen(
+        src: &[T],
+        nuked: &[T],
+        blurred: &[T],
+        dst: &mut [T],
+        sstr: f32,
+        scl: f32,
+        half: i64,
+        max: i64,
+    ) {
+        let rows = src.iter().zip(nuked.iter()).zip(blurred.iter());
+        if scl == 0.0 {
+            for (((&s, &n), &b), target) in rows.zip(dst.iter_mut()) {
+                let sharp = T::from_i64(sharp_px(n.to_i64(), b.to_i64(), sstr, max));
+                *target = median3_px(n, sharp, s);
+            }
+        } else {
+            for (((&s, &n), &b), target) in rows.zip(dst.iter_mut()) {
+                let (s, n, b) = (s.to_i64(), n.to_i64(), b.to_i64());
+                let nukedd = make_diff_px(s, n, half, max);
+                let sharpd = sharpd_px(n, b, sstr, half, max);
+                let limd = limd_px(sharpd, nukedd, scl, half, max);
+                *target = T::from_i64(add_diff_px(n, limd, half, max));
+            }
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use super::lut::{Lanes, MUL_LIMIT};
+use super::{LutOps, RowOps};
+use std::arch::x86_64::*;
+use std::ptr;
+
+pub(super) struct Sse2;
+pub(super) struct Avx2;
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn loadu_sse2<T>(ptr: *const T) -> __m128i {
+    _mm_loadu_si128(ptr as *const __m128i)
+}
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn storeu_sse2<T>(ptr: *mut T, value: __m128i) {
+    _mm_storeu_si128(ptr as *mut __m128i, value)
+}
+
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn loadu_avx2<T>(ptr: *const T) -> __m256i {
+    _mm256_loadu_si256(ptr as *const __m256i)
+}
+
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn storeu_avx2<T>(ptr: *mut T, value: __m256i) {
+    _mm256_storeu_si256(ptr as *mut __m256i, value)
+}
+
+// SSE2 lacks unsigned 16 and 32-bit min/max, so these are built from
+// saturating subtraction and from signed comparison of sign-flipped values
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn min_epu16_sse2(a: __m128i, b: __m128i) -> __m128i {
+    _mm_sub_epi16(a, _mm_subs_epu16(a, b))
+}
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn max_epu16_sse2(a: __m128i, b: __m128i) -> __m128i {
+    _mm_add_epi16(b, _mm_subs_epu16(a, b))
+}
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn gt_epu32_sse2(a: __m128i, b: __m128i) -> __m128i {
+    let sign = _mm_set1_epi32(i32::MIN);
+    _mm_cmpgt_epi32(_mm_xor_si128(a, sign), _mm_xor_si128(b, sign))
+}
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn min_epu32_sse2(a: __m128i, b: __m128i) -> __m128i {
+    let a_greater = gt_epu32_sse2(a, b);
+    _mm_or_si128(_mm_and_si128(a_greater, b), _mm_andnot_si128(a_greater, a))
+}
+
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn max_epu32_sse2(a: __m128i, b: __m128i) -> __m128i {
+    let a_greater = gt_epu32_sse2(a, b);
+    _mm_or_si128(_mm_and_si128(a_greater, a), _mm_andnot_si128(a_greater, b))
+}
+
+impl_row_ops!(
+    Sse2, "sse2", u8, __m128i,
+    load: loadu_sse2, store: storeu_sse2,
+    min: _mm_min_epu8, max: _mm_max_epu8, sub: _mm_sub_epi8,
+    splat: |max| _mm_set1_epi8(max as i8)
+);
+impl_row_ops!(
+    Sse2, "sse2", u16, __m128i,
+    load: loadu_sse2, store: storeu_sse2,
+    min: min_epu16_sse2, max: max_epu16_sse2, sub: _mm_sub_epi16,
+    splat: |max| _mm_set1_epi16(max as i16)
+);
+impl_row_ops!(
+    Sse2, "sse2", u32, __m128i,
+    load: loadu_sse2, store: storeu_sse2,
+    min: min_epu32_sse2, max: max_epu32_sse2, sub: _mm_sub_epi32,
+    splat: |max| _mm_set1_epi32(max as i32)
+);
+impl_row_ops!(
+    Avx2, "avx2", u8, __m256i,
+    load: loadu_avx2, store: storeu_avx2,
+    min: _mm256_min_epu8, max: _mm256_max_epu8, sub: _mm256_sub_epi8,
+    splat: |max| _mm256_set1_epi8(max as i8)
+);
+impl_row_ops!(
+    Avx2, "avx2", u16, __m256i,
+    load: loadu_avx2, store: storeu_avx2,
+    min: _mm256_min_epu16, max: _mm256_max_epu16, sub: _mm256_sub_epi16,
+    splat: |max| _mm256_set1_epi16(max as i16)
+);
+impl_row_ops!(
+    Avx2, "avx2", u32, __m256i,
+    load: loadu_avx2, store: storeu_avx2,
+    min: _mm256_min_epu32, max: _mm256_max_epu32, sub: _mm256_sub_epi32,
+    splat: |max| _mm256_set1_epi32(max as i32)
+);
+impl_row_ops!(
+    Sse2, "sse2", f32, __m128,
+    load: _mm_loadu_ps, store: _mm_storeu_ps,
+    min: _mm_min_ps, max: _mm_max_ps, sub: _mm_sub_ps,
+    splat: |max| _mm_set1_ps(max)
+);
+impl_row_ops!(
+    Avx2, "avx2", f32, __m256,
+    load: _mm256_loadu_ps, store: _mm256_storeu_ps,
+    min: _mm256_min_ps, max: _mm256_max_ps, sub: _mm256_sub_ps,
+    splat: |max| _mm256_set1_ps(max)
+);
+
+// SSE2 also lacks 32-bit min/max and the packing of 32-bit lanes to
+// unsigned 16 bits, which are built from comparisons and a bias instead
+
+impl Lanes for Sse2 {
+    const LANES: usize = 4;
+    type V = __m128i;
+    type F = __m128;
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn splat(value: i32) -> __m128i {
+        _mm_set1_epi32(value)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn splat_f(value: f32) -> __m128 {
+        _mm_set1_ps(value)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn load_u8(ptr: *const u8) -> __m128i {
+        let zero = _mm_setzero_si128();
+        let bytes = _mm_cvtsi32_si128(ptr::read_unaligned(ptr as *const i32));
+        _mm_unpacklo_epi16(_mm_unpacklo_epi8(bytes, zero), zero)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn load_u16(ptr: *const u16) -> __m128i {
+        _mm_unpacklo_epi16(_mm_loadl_epi64(ptr as *const __m128i), _mm_setzero_si128())
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn store_u8(ptr: *mut u8, value: __m128i) {
+        let words = _mm_packs_epi32(value, value);
+        let bytes = _mm_packus_epi16(words, words);
+        ptr::write_unaligned(ptr as *mut i32, _mm_cvtsi128_si32(bytes));
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn store_u16(ptr: *mut u16, value: __m128i) {
+        let biased = _mm_sub_epi32(value, _mm_set1_epi32(0x8000));
+        let words = _mm_packs_epi32(biased, biased);
+        let words = _mm_add_epi16(words, _mm_set1_epi16(i16::MIN));
+        _mm_storel_epi64(ptr as *mut __m128i, words);
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn add(a: __m128i, b: __m128i) -> __m128i {
+        _mm_add_epi32(a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn sub(a: __m128i, b: __m128i) -> __m128i {
+        _mm_sub_epi32(a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn min(a: __m128i, b: __m128i) -> __m128i {
+        Self::select(_mm_cmplt_epi32(a, b), a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn max(a: __m128i, b: __m128i) -> __m128i {
+        Self::select(_mm_cmpgt_epi32(a, b), a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn abs(a: __m128i) -> __m128i {
+        let sign = _mm_srai_epi32::<31>(a);
+        _mm_sub_epi32(_mm_xor_si128(a, sign), sign)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn and(a: __m128i, b: __m128i) -> __m128i {
+        _mm_and_si128(a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn or(a: __m128i, b: __m128i) -> __m128i {
+        _mm_or_si128(a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn lt(a: __m128i, b: __m128i) -> __m128i {
+        _mm_cmplt_epi32(a, b)
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn select(mask: __m128i, a: __m128i, b: __m128i) -> __m128i {
+        _mm_or_si128(_mm_and_si128(mask, a), _mm_andnot_si128(mask, b))
+    }
+
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn mul_trunc(a: __m128i, b: __m128) -> __m128i {
+        let product = _mm_mul_ps(_mm_cvtepi32_ps(a), b);
+        let product = _mm_and_ps(product, _mm_cmpord_ps(product, product));
+        let product = _mm_max_ps(product, _mm_set1_ps(-MUL_LIMIT));
+        _mm_cvttps_epi32(_mm_min_ps(product, _mm_set1_ps(MUL_LIMIT)))
+    }
+}
+
+impl Lanes for Avx2 {
+    const LANES: usize = 8;
+    type V = __m256i;
+    type F = __m256;
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn splat(value: i32) -> __m256i {
+        _mm256_set1_epi32(value)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn splat_f(value: f32) -> __m256 {
+        _mm256_set1_ps(value)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn load_u8(ptr: *const u8) -> __m256i {
+        _mm256_cvtepu8_epi32(_mm_loadl_epi64(ptr as *const __m128i))
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn load_u16(ptr: *const u16) -> __m256i {
+        _mm256_cvtepu16_epi32(_mm_loadu_si128(ptr as *const __m128i))
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn store_u8(ptr: *mut u8, value: __m256i) {
+        let words = pack_u16_avx2(value);
+        _mm_storel_epi64(ptr as *mut __m128i, _mm_packus_epi16(words, words));
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn store_u16(ptr: *mut u16, value: __m256i) {
+        _mm_storeu_si128(ptr as *mut __m128i, pack_u16_avx2(value));
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn add(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_add_epi32(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn sub(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_sub_epi32(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn min(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_min_epi32(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn max(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_max_epi32(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn abs(a: __m256i) -> __m256i {
+        _mm256_abs_epi32(a)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn and(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_and_si256(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn or(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_or_si256(a, b)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn lt(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_cmpgt_epi32(b, a)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn select(mask: __m256i, a: __m256i, b: __m256i) -> __m256i {
+        _mm256_blendv_epi8(b, a, mask)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn mul_trunc(a: __m256i, b: __m256) -> __m256i {
+        let product = _mm256_mul_ps(_mm256_cvtepi32_ps(a), b);
+        let product = _mm256_and_ps(product, _mm256_cmp_ps::<_CMP_ORD_Q>(product, product));
+        let product = _mm256_max_ps(product, _mm256_set1_ps(-MUL_LIMIT));
+        _mm256_cvttps_epi32(_mm256_min_ps(product, _mm256_set1_ps(MUL_LIMIT)))
+    }
+}
+
+/// Packs the lanes, which are within the range of `u16`, into the low half.
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn pack_u16_avx2(value: __m256i) -> __m128i {
+    // The packing works within each 128-bit half, so the two halves'
+    // results are gathered from the first and third 64-bit quarters
+    let words = _mm256_packus_epi32(value, value);
+    _mm256_castsi256_si128(_mm256_permute4x64_epi64::<0b1000>(words))
+}
+
+impl_lut_ops!(Sse2, "sse2", u8);
+impl_lut_ops!(Sse2, "sse2", u16);
+impl_lut_ops!(Sse2, u32, scalar);
+impl_lut_ops!(Avx2, "avx2", u8);
+impl_lut_ops!(Avx2, "avx2", u16);
+impl_lut_ops!(Avx2, u32, scalar);
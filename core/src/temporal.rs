@@ -0,0 +1,84 @@
+use crate::fields::{separate_fields, weave_fields};
+use crate::frame::Frame;
+use crate::kernels::{clamp, diff, max, max_value, median3_px, min, zip3};
+use crate::morphology::expand;
+use crate::plane::{Pixel, Plane, PlaneRef};
+use crate::resize::{resize, ResizeKernel};
+
+fn max3<T: Pixel>(x: PlaneRef<T>, y: PlaneRef<T>, z: PlaneRef<T>) -> Plane<T> {
+    let mut xy = Plane::new(x.width(), x.height());
+    max(x, y, xy.as_mut());
+    let mut dst = Plane::new(x.width(), x.height());
+    max(xy.as_ref(), z, dst.as_mut());
+    dst
+}
+
+/// The max of the Y/U/V planes, resizing if necessary.
+fn max_yuv<T: Pixel>(frame: &Frame<T>) -> Frame<T> {
+    if frame.plane_count() < 3 {
+        return frame.clone();
+    }
+    let bits = frame.bit_depth;
+    let (y, u, v) = (frame.plane(0), frame.plane(1), frame.plane(2));
+
+    let u_up = resize(u, y.width(), y.height(), ResizeKernel::Bilinear, bits);
+    let v_up = resize(v, y.width(), y.height(), ResizeKernel::Bilinear, bits);
+    let y_down = resize(y, u.width(), u.height(), ResizeKernel::Bilinear, bits);
+
+    let luma = max3(y, u_up.as_ref(), v_up.as_ref());
+    let chroma = max3(y_down.as_ref(), u, v);
+    Frame::new(vec![luma, chroma.clone(), chroma], bits)
+}
+
+/// Limits the changes `flt` makes to `clip` to `diffscl` times the difference
+/// between `clip` and the neighbouring source frames `prev` and `next`.
+pub fn temp_limit<T: Pixel>(
+    clip: &Frame<T>,
+    flt: &Frame<T>,
+    prev: &Frame<T>,
+    next: &Frame<T>,
+    diffscl: f64,
+) -> Frame<T> {
+    let bits = clip.bit_depth;
+    let max_pix_val = max_value(bits);
+
+    let diff_to = |other: &Frame<T>| {
+        max_yuv(&clip.map_planes(|i, plane| {
+            let mut dst = Plane::new(plane.width(), plane.height());
+            diff(plane, other.plane(i), dst.as_mut(), bits);
+            dst
+        }))
+    };
+    let diff_prev = diff_to(prev);
+    let diff_next = diff_to(next);
+
+    clip.map_planes(|i, plane| {
+        // Within each field, take the smaller of the two temporal differences
+        let (prev_top, prev_bottom) = separate_fields(diff_prev.plane(i), true);
+        let (next_top, next_bottom) = separate_fields(diff_next.plane(i), true);
+        let field_min = |a: &Plane<T>, b: &Plane<T>| {
+            let mut dst = Plane::new(a.width(), a.height());
+            min(a.as_ref(), b.as_ref(), dst.as_mut());
+            expand(dst.as_ref(), 2, 1)
+        };
+        let top = field_min(&prev_top, &next_top);
+        let bottom = field_min(&prev_bottom, &next_bottom);
+        let limit = weave_fields(top.as_ref(), bottom.as_ref(), true);
+
+        let mut dst = Plane::new(plane.width(), plane.height());
+        zip3(
+            plane,
+            limit.as_ref(),
+            flt.plane(i),
+            dst.as_mut(),
+            |x, d, f| {
+                let x = x.to_i64() as f64;
+                let d = d.to_i64() as f64 * diffscl;
+                let lo = clamp((x - d + 0.5).floor() as i64, 0, max_pix_val);
+                let hi = clamp((x + d + 0.5).floor() as i64, 0, max_pix_val);
+                median3_px(T::from_i64(lo), T::from_i64(hi), f)
+            },
+        );
+        dst
+    })
+}
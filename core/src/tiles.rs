@@ -0,0 +1,160 @@
+use crate::bob::BobMode;
+use crate::frame::Frame;
+use crate::pipeline::{mpeg2stinx, subsampling, Params};
+use crate::plane::{Pixel, Plane, PlaneRef};
+use std::ops::Range;
+
+/// How many rows of a plane the result of one row of `bob` depends on,
+/// up and down.
+fn bob_reach(mode: BobMode) -> usize {
+    match mode {
+        BobMode::Point => 2,
+        // Three field lines either side of the shifted center
+        BobMode::Spline36 => 8,
+    }
+}
+
+impl Params {
+    /// How many rows of a plane the output of one row depends on, up and down.
+    ///
+    /// This is an upper bound over all planes, in the rows of each plane.
+    fn vertical_reach(&self) -> usize {
+        let bob = bob_reach(self.mode);
+        let sh = self.sh.max(self.sh_c.unwrap_or(0)).max(1) as usize;
+        // The limit expands the differences by a field row, and resizes
+        // between luma and chroma with a bilinear kernel
+        let temporal = if self.diffscl.is_some() { 3 + 2 } else { 0 };
+        let interlaced = if self.interlaced { bob } else { 0 };
+        // The final blur and the two blurs of contrasharpening
+        interlaced + self.passes.max(1) as usize * (bob + sh + temporal) + 3
+    }
+}
+
+/// The rows of a plane subsampled by `2^ss` covering the luma rows `rows`
+/// of a frame of `luma_height` rows, in a plane of `height` rows.
+fn plane_rows(rows: &Range<usize>, luma_height: usize, height: usize, ss: u32) -> Range<usize> {
+    let end = if rows.end == luma_height {
+        height
+    } else {
+        rows.end >> ss
+    };
+    rows.start >> ss..end
+}
+
+/// The buffers holding the rows of one frame that a tile is computed from,
+/// reused from one tile to the next.
+struct Window<T> {
+    buffers: Vec<Vec<T>>,
+}
+
+impl<T: Pixel> Window<T> {
+    fn new(planes: usize) -> Self {
+        Window {
+            buffers: vec![Vec::new(); planes],
+        }
+    }
+
+    /// Copies the luma rows `rows` of `src`, and the chroma rows covering them.
+    fn fill(&mut self, src: &[PlaneRef<T>], rows: &Range<usize>, bit_depth: u32) -> Frame<T> {
+        let luma_height = src[0].height();
+        let planes = src
+            .iter()
+            .zip(self.buffers.iter_mut())
+            .map(|(plane, buffer)| {
+                let ss = subsampling(luma_height, plane.height());
+                let rows = plane_rows(rows, luma_height, plane.height(), ss);
+                let mut data = std::mem::take(buffer);
+                data.clear();
+                for y in rows.clone() {
+                    data.extend_from_slice(plane.row(y));
+                }
+                Plane::from_vec(data, plane.width(), rows.len())
+            })
+            .collect();
+        Frame::new(planes, bit_depth)
+    }
+
+    /// Takes back the buffers of a frame returned by `fill`.
+    fn recycle(&mut self, frame: Frame<T>) {
+        self.buffers = frame.planes.into_iter().map(Plane::into_vec).collect();
+    }
+}
+
+/// Runs `mpeg2stinx` on bands of about `tile_rows` luma rows at a time,
+/// giving the same output as running it on the whole frame.
+///
+/// Each band is computed from the rows it depends on, which `Params`
+/// bounds, so that all intermediate planes stay a fraction of the frame,
+/// and the buffers the bands are copied to are reused.
+/// The output of each band is passed to `output` plane by plane,
+/// along with the index of the plane and of its first row.
+///
+/// `prev` and `next` are only read with temporal limiting. The planes
+/// are luma first, and their heights must be a multiple of 2 like for
+/// `mpeg2stinx`.
+pub fn mpeg2stinx_tiled<T: Pixel, F: FnMut(usize, usize, PlaneRef<T>)>(
+    prev: &[PlaneRef<T>],
+    cur: &[PlaneRef<T>],
+    next: &[PlaneRef<T>],
+    bit_depth: u32,
+    params: &Params,
+    tile_rows: usize,
+    mut output: F,
+) {
+    let luma_height = cur[0].height();
+    let ss_max = cur
+        .iter()
+        .map(|plane| subsampling(luma_height, plane.height()))
+        .max()
+        .unwrap_or(0);
+    // Bands start on rows that keep the field parity of every plane
+    let align = 2 << ss_max;
+    let align_up = |rows: usize| rows.div_ceil(align) * align;
+    let halo = align_up(params.vertical_reach() << ss_max);
+    let tile_rows = align_up(tile_rows.max(1));
+
+    let temporal = params.diffscl.is_some();
+    let mut windows = [
+        Window::new(cur.len()),
+        Window::new(cur.len()),
+        Window::new(cur.len()),
+    ];
+    for start in (0..luma_height).step_by(tile_rows) {
+        let tile = start..(start + tile_rows).min(luma_height);
+        let rows = tile.start.saturating_sub(halo)..(tile.end + halo).min(luma_height);
+
+        let [prev_window, cur_window, next_window] = &mut windows;
+        let cur_frame = cur_window.fill(cur, &rows, bit_depth);
+        let result = if temporal {
+            let prev_frame = prev_window.fill(prev, &rows, bit_depth);
+            let next_frame = next_window.fill(next, &rows, bit_depth);
+            let result = mpeg2stinx(&prev_frame, &cur_frame, &next_frame, params);
+            prev_window.recycle(prev_frame);
+            next_window.recycle(next_frame);
+            result
+        } else {
+            // The neighbours are only used for temporal limiting
+            mpeg2stinx(&cur_frame, &cur_frame, &cur_frame, params)
+        };
+        cur_window.recycle(cur_frame);
+
+        for (i, plane) in cur.iter().enumerate() {
+            let ss = subsampling(luma_height, plane.height());
+            let window = plane_rows(&rows, luma_height, plane.height(), ss);
+            let tile = plane_rows(&tile, luma_height, plane.height(), ss);
+            let result = &result.planes[i];
+            let first = (tile.start - window.start) * result.width();
+            let len = tile.len() * result.width();
+            output(
+                i,
+                tile.start,
+                PlaneRef::new(
+                    &result.data()[first..first + len],
+                    result.width(),
+                    tile.len(),
+                    result.width(),
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Synthetic clips and output statistics shared by the integration tests.
+//!
+//! Nothing here needs VapourSynth or any of the plugins the VapourSynth
+//! filter depends on, so the tests run anywhere `cargo test` does.
+
+#![allow(dead_code)]
+
+use mpeg2stinx_core::{Frame, Pixel, Plane, PlaneRef};
+
+/// A deterministic pseudo random number generator, so that noise is
+/// reproducible without pulling in a dependency.
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+}
+
+/// A plane of uniform noise covering the whole range of `bit_depth`.
+pub fn noise_plane<T: Pixel>(width: usize, height: usize, bit_depth: u32, seed: u64) -> Plane<T> {
+    let mut rng = Lcg::new(seed);
+    let mask = (1u64 << bit_depth) - 1;
+    Plane::from_fn(width, height, |_, _| {
+        T::from_i64((rng.next_u64() & mask) as i64)
+    })
+}
+
+/// Copies `src` into a buffer with `padding` extra pixels at the end of
+/// each row, to exercise kernels on planes whose stride exceeds their width.
+pub fn padded<T: Pixel>(src: &Plane<T>, padding: usize) -> Vec<T> {
+    let stride = src.width() + padding;
+    let mut data = vec![T::default(); stride * src.height()];
+    for (y, row) in src.as_ref().rows().enumerate() {
+        data[y * stride..y * stride + src.width()].copy_from_slice(row);
+    }
+    data
+}
+
+/// The layout of a synthetic clip.
+#[derive(Debug, Clone, Copy)]
+pub struct Synthetic {
+    pub width: usize,
+    pub height: usize,
+    /// Log2 of the horizontal and vertical chroma subsampling.
+    pub ss_w: u32,
+    pub ss_h: u32,
+    pub bit_depth: u32,
+    /// Horizontal motion in pixels per frame, so each field is shifted by
+    /// half of this relative to the other field of the same frame.
+    pub speed: f64,
+}
+
+impl Default for Synthetic {
+    fn default() -> Self {
+        Synthetic {
+            width: 64,
+            height: 48,
+            ss_w: 1,
+            ss_h: 1,
+            bit_depth: 8,
+            speed: 8.0,
+        }
+    }
+}
+
+fn ramp(edge: f64, x: f64) -> f64 {
+    (0.5 + (x - edge) / 2.0).clamp(0.0, 1.0)
+}
+
+// A soft edged bar moving across a gradient background, in 0.0..=1.0
+fn luma_scene(x: f64, y: f64, t: f64, speed: f64) -> f64 {
+    let background = 0.2 + 0.3 * y / 48.0 + 0.1 * (x / 7.0).sin();
+    let left = 8.0 + speed * t;
+    let bar = ramp(left, x) * (1.0 - ramp(left + 16.0, x));
+    background + 0.4 * bar
+}
+
+fn chroma_scene(x: f64, y: f64, t: f64, speed: f64, plane: usize) -> f64 {
+    let left = 8.0 + speed * t;
+    let bar = ramp(left, x) * (1.0 - ramp(left + 16.0, x));
+    let tint = if plane == 1 { 0.3 } else { -0.25 };
+    0.5 + 0.05 * (y / 11.0).cos() + tint * bar
+}
+
+impl Synthetic {
+    fn scale<T: Pixel>(&self, value: f64) -> T {
+        let max = ((1i64 << self.bit_depth) - 1) as f64;
+        T::from_i64((value.clamp(0.0, 1.0) * max).round() as i64)
+    }
+
+    // Samples the scene at full resolution, with the rows of each field
+    // taken at the field's own point in time
+    fn full_res(&self, plane: usize, time_of_row: impl Fn(usize) -> f64) -> Vec<Vec<f64>> {
+        (0..self.height)
+            .map(|y| {
+                let t = time_of_row(y);
+                (0..self.width)
+                    .map(|x| {
+                        let (x, y) = (x as f64, y as f64);
+                        if plane == 0 {
+                            luma_scene(x, y, t, self.speed)
+                        } else {
+                            chroma_scene(x, y, t, self.speed, plane)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Subsamples chroma by box filtering the full resolution frame, like an
+    // encoder that treats interlaced content as progressive. For 4:2:0 this
+    // blends lines of both fields, which is the chroma bleed seen on MPEG-2.
+    fn subsample<T: Pixel>(&self, full: &[Vec<f64>]) -> Plane<T> {
+        let (bw, bh) = (1 << self.ss_w, 1 << self.ss_h);
+        Plane::from_fn(self.width >> self.ss_w, self.height >> self.ss_h, |x, y| {
+            let mut sum = 0.0;
+            for row in &full[y * bh..(y + 1) * bh] {
+                sum += row[x * bw..(x + 1) * bw].iter().sum::<f64>();
+            }
+            self.scale(sum / (bw * bh) as f64)
+        })
+    }
+
+    fn frame<T: Pixel>(&self, time_of_row: impl Fn(usize) -> f64) -> Frame<T> {
+        let planes = (0..3)
+            .map(|plane| {
+                let full = self.full_res(plane, &time_of_row);
+                if plane == 0 {
+                    Plane::from_fn(self.width, self.height, |x, y| self.scale(full[y][x]))
+                } else {
+                    self.subsample(&full)
+                }
+            })
+            .collect();
+        Frame::new(planes, self.bit_depth)
+    }
+
+    /// Frame `n` of a top field first interlaced clip: the bottom field is
+    /// captured half a frame after the top field, so moving edges comb.
+    pub fn combed<T: Pixel>(&self, n: usize) -> Frame<T> {
+        self.frame(|y| n as f64 + if y % 2 == 0 { 0.0 } else { 0.5 })
+    }
+
+    /// Frame `n` of the same scene captured progressively.
+    pub fn progressive<T: Pixel>(&self, n: usize) -> Frame<T> {
+        self.frame(|_| n as f64)
+    }
+}
+
+/// The mean absolute difference between each line and the average of the
+/// lines above and below it. Interlacing artifacts make this large.
+pub fn comb_energy<T: Pixel>(plane: PlaneRef<T>) -> f64 {
+    let mut sum = 0i64;
+    let mut count = 0i64;
+    for y in 1..plane.height() - 1 {
+        let (above, row, below) = (plane.row(y - 1), plane.row(y), plane.row(y + 1));
+        for x in 0..plane.width() {
+            let interpolated = (above[x].to_i64() + below[x].to_i64()) / 2;
+            sum += (row[x].to_i64() - interpolated).abs();
+            count += 1;
+        }
+    }
+    sum as f64 / count as f64
+}
+
+pub fn max_abs_diff<T: Pixel>(a: PlaneRef<T>, b: PlaneRef<T>) -> i64 {
+    a.rows()
+        .zip(b.rows())
+        .flat_map(|(a, b)| a.iter().zip(b.iter()))
+        .map(|(&a, &b)| (a.to_i64() - b.to_i64()).abs())
+        .max()
+        .unwrap_or(0)
+}
+
+/// The peak signal to noise ratio of `b` relative to `a`, in dB.
+pub fn psnr<T: Pixel>(a: PlaneRef<T>, b: PlaneRef<T>, bit_depth: u32) -> f64 {
+    let (sum, count) = a
+        .rows()
+        .zip(b.rows())
+        .flat_map(|(a, b)| a.iter().zip(b.iter()))
+        .fold((0.0, 0usize), |(sum, count), (&a, &b)| {
+            let error = (a.to_i64() - b.to_i64()) as f64;
+            (sum + error * error, count + 1)
+        });
+    let mse = sum / count as f64;
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    let peak = ((1i64 << bit_depth) - 1) as f64;
+    10.0 * (peak * peak / mse).log10()
+}
@@ -0,0 +1,67 @@
+//! Repair of the interlaced chroma upsampling error on chroma planes that
+//! were subsampled field by field from progressive pictures.
+
+mod common;
+
+use self::common::{comb_energy, max_abs_diff, psnr};
+use mpeg2stinx_core::{cue_repair, BobMode, Plane};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 24;
+
+/// Clean 4:2:0 chroma with soft horizontal bands, like the edges of coloured objects.
+fn clean_chroma() -> Plane<u8> {
+    Plane::from_fn(WIDTH, HEIGHT, |x, y| {
+        let y = y as f64 + x as f64 * 0.1;
+        (128.0 + 60.0 * (y * std::f64::consts::PI / 10.0).sin()).round() as u8
+    })
+}
+
+/// The same chroma with the field-alternating stripes the upsampling error
+/// leaves around detail, in the middle half of the plane.
+fn striped_chroma() -> Plane<u8> {
+    let clean = clean_chroma();
+    Plane::from_fn(WIDTH, HEIGHT, |x, y| {
+        let offset = if !(HEIGHT / 4..HEIGHT * 3 / 4).contains(&y) {
+            0
+        } else if y % 2 == 0 {
+            12
+        } else {
+            -12
+        };
+        (i64::from(clean.get(x, y)) + offset) as u8
+    })
+}
+
+#[test]
+fn removes_field_stripes() {
+    let (reference, src) = (clean_chroma(), striped_chroma());
+    for &mode in &[BobMode::Point, BobMode::Spline36] {
+        let output = cue_repair(src.as_ref(), mode, 2.0, 8);
+        // The bands comb a little by themselves, only count the stripes on top
+        let clean = comb_energy(reference.as_ref());
+        let before = comb_energy(src.as_ref()) - clean;
+        let after = comb_energy(output.as_ref()) - clean;
+        assert!(
+            after < before * 0.5,
+            "{:?}: stripe energy {} -> {}",
+            mode,
+            before,
+            after
+        );
+        assert!(
+            psnr(reference.as_ref(), output.as_ref(), 8)
+                > psnr(reference.as_ref(), src.as_ref(), 8),
+            "{:?}: moved away from the clean chroma",
+            mode
+        );
+    }
+}
+
+#[test]
+fn keeps_clean_chroma() {
+    // A vertical ramp never alternates between rows
+    let src = Plane::<u16>::from_fn(WIDTH, HEIGHT, |x, y| (100 + 20 * y + x) as u16);
+    let output = cue_repair(src.as_ref(), BobMode::Spline36, 2.0, 10);
+    assert_eq!(max_abs_diff(src.as_ref(), output.as_ref()), 0);
+}
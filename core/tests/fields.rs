@@ -0,0 +1,35 @@
+//! Separating frames into fields and weaving them back, in either field order.
+
+mod common;
+
+use self::common::noise_plane;
+use mpeg2stinx_core::{separate_fields, weave_fields};
+
+const WIDTH: usize = 23;
+const HEIGHT: usize = 18;
+
+#[test]
+fn fields_round_trip_in_both_orders() {
+    let src = noise_plane::<u8>(WIDTH, HEIGHT, 8, 1);
+    for &tff in &[true, false] {
+        let (first, second) = separate_fields(src.as_ref(), tff);
+        assert_eq!(first.height(), HEIGHT / 2);
+        // The first field is the top one, at even rows, only if `tff`
+        let parity = if tff { 0 } else { 1 };
+        for y in 0..first.height() {
+            assert_eq!(
+                first.as_ref().row(y),
+                src.as_ref().row(2 * y + parity),
+                "tff {}",
+                tff
+            );
+            assert_eq!(
+                second.as_ref().row(y),
+                src.as_ref().row(2 * y + 1 - parity),
+                "tff {}",
+                tff
+            );
+        }
+        assert_eq!(weave_fields(first.as_ref(), second.as_ref(), tff), src);
+    }
+}
@@ -0,0 +1,365 @@
+//! The per-pixel kernels behind the `Min`, `Max`, `Median3` and `LutXYDiff`
+//! filters and the `mask` argument, checked against straightforward per-pixel definitions.
+
+mod common;
+
+use self::common::{noise_plane, padded};
+use mpeg2stinx_core::{
+    add_diff, average, diff, make_diff, mask_merge, max, median, median3, min, sharpd,
+    subsample_mask, weighted_average, MaskMode, Pixel, Plane, PlaneMut, PlaneRef, Rounding,
+};
+
+const WIDTH: usize = 37;
+const HEIGHT: usize = 11;
+
+type Kernel2<T> = fn(PlaneRef<T>, PlaneRef<T>, PlaneMut<T>);
+
+fn check_kernel2<T: Pixel>(bit_depth: u32, kernel: Kernel2<T>, expected: fn(i64, i64) -> i64) {
+    let x = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 1);
+    let y = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 2);
+
+    // Run on planes with padded rows, like VapourSynth frames have
+    let stride = WIDTH + 5;
+    let (x_data, y_data) = (padded(&x, 5), padded(&y, 5));
+    let mut dst_data = vec![T::default(); stride * HEIGHT];
+    kernel(
+        PlaneRef::new(&x_data, WIDTH, HEIGHT, stride),
+        PlaneRef::new(&y_data, WIDTH, HEIGHT, stride),
+        PlaneMut::new(&mut dst_data, WIDTH, HEIGHT, stride),
+    );
+    let dst = PlaneRef::new(&dst_data, WIDTH, HEIGHT, stride);
+
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            assert_eq!(
+                dst.get(col, row).to_i64(),
+                expected(x.get(col, row).to_i64(), y.get(col, row).to_i64()),
+                "at ({}, {})",
+                col,
+                row
+            );
+        }
+        // The padding must be left alone
+        assert!(dst_data[row * stride + WIDTH..(row + 1) * stride]
+            .iter()
+            .all(|&px| px == T::default()));
+    }
+}
+
+#[test]
+fn min_matches_definition() {
+    check_kernel2::<u8>(8, min, |x, y| x.min(y));
+    check_kernel2::<u16>(10, min, |x, y| x.min(y));
+    check_kernel2::<u16>(16, min, |x, y| x.min(y));
+}
+
+#[test]
+fn max_matches_definition() {
+    check_kernel2::<u8>(8, max, |x, y| x.max(y));
+    check_kernel2::<u16>(10, max, |x, y| x.max(y));
+    check_kernel2::<u16>(16, max, |x, y| x.max(y));
+}
+
+#[test]
+fn diff_matches_definition() {
+    check_kernel2::<u8>(8, |x, y, dst| diff(x, y, dst, 8), |x, y| (x - y).abs());
+    check_kernel2::<u16>(10, |x, y, dst| diff(x, y, dst, 10), |x, y| (x - y).abs());
+    check_kernel2::<u16>(16, |x, y, dst| diff(x, y, dst, 16), |x, y| (x - y).abs());
+}
+
+fn check_median3<T: Pixel>(bit_depth: u32) {
+    let x = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 3);
+    let y = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 4);
+    let z = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 5);
+    let mut dst = Plane::new(WIDTH, HEIGHT);
+    median3(x.as_ref(), y.as_ref(), z.as_ref(), dst.as_mut());
+
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            let mut values = [x.get(col, row), y.get(col, row), z.get(col, row)];
+            values.sort();
+            assert_eq!(dst.get(col, row), values[1], "at ({}, {})", col, row);
+        }
+    }
+}
+
+#[test]
+fn median3_matches_definition() {
+    check_median3::<u8>(8);
+    check_median3::<u16>(10);
+    check_median3::<u16>(16);
+}
+
+#[test]
+fn median3_handles_ties() {
+    let a = Plane::from_vec(vec![1u8, 5, 5, 9], 4, 1);
+    let b = Plane::from_vec(vec![1u8, 5, 2, 9], 4, 1);
+    let c = Plane::from_vec(vec![7u8, 0, 5, 9], 4, 1);
+    let mut dst = Plane::new(4, 1);
+    median3(a.as_ref(), b.as_ref(), c.as_ref(), dst.as_mut());
+    assert_eq!(dst.data(), &[1, 5, 5, 9]);
+}
+
+/// Checks that the difference of a plane with itself, or a difference
+/// sharpened with no strength, is the neutral value `neutral`, and that
+/// adding it back leaves a plane untouched.
+fn check_neutral_difference<T: Pixel>(bit_depth: u32, neutral: i64) {
+    let x = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 6);
+    let y = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 7);
+    let mut difference = Plane::new(WIDTH, HEIGHT);
+    make_diff(x.as_ref(), x.as_ref(), difference.as_mut(), bit_depth);
+    assert!(
+        difference.data().iter().all(|px| px.to_i64() == neutral),
+        "{}-bit make_diff",
+        bit_depth
+    );
+    let mut sharpened = Plane::new(WIDTH, HEIGHT);
+    sharpd(x.as_ref(), y.as_ref(), sharpened.as_mut(), 0.0, bit_depth);
+    assert_eq!(sharpened, difference, "{}-bit sharpd", bit_depth);
+
+    let mut restored = Plane::new(WIDTH, HEIGHT);
+    add_diff(
+        x.as_ref(),
+        difference.as_ref(),
+        restored.as_mut(),
+        bit_depth,
+    );
+    assert_eq!(restored, x, "{}-bit add_diff", bit_depth);
+}
+
+#[test]
+fn difference_clips_are_centered_on_half_range() {
+    check_neutral_difference::<u8>(8, 128);
+    check_neutral_difference::<u16>(10, 512);
+    check_neutral_difference::<u16>(16, 32768);
+}
+
+fn check_weighted_average<T: Pixel>(bit_depth: u32) {
+    let x = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 7);
+    let y = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 8);
+
+    // Two equal weights are the plain average
+    let mut expected = Plane::new(WIDTH, HEIGHT);
+    average(x.as_ref(), y.as_ref(), expected.as_mut());
+    let mut dst = Plane::new(WIDTH, HEIGHT);
+    weighted_average(
+        &[x.as_ref(), y.as_ref()],
+        &[1.0, 1.0],
+        2.0,
+        Rounding::HalfUp,
+        bit_depth,
+        dst.as_mut(),
+    );
+    assert_eq!(dst, expected);
+
+    // Negative weights give a linear combination, clamped to the valid range
+    let max_pix_val = (1i64 << bit_depth) - 1;
+    for &diffscl in &[-2.0, -0.5, 0.5, 2.0] {
+        weighted_average(
+            &[x.as_ref(), y.as_ref()],
+            &[1.0, diffscl],
+            1.0,
+            Rounding::HalfUp,
+            bit_depth,
+            dst.as_mut(),
+        );
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let value =
+                    x.get(col, row).to_i64() as f64 + diffscl * y.get(col, row).to_i64() as f64;
+                let expected = ((value + 0.5).floor() as i64).max(0).min(max_pix_val);
+                assert_eq!(
+                    dst.get(col, row).to_i64(),
+                    expected,
+                    "at ({}, {})",
+                    col,
+                    row
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn weighted_average_matches_definition() {
+    check_weighted_average::<u8>(8);
+    check_weighted_average::<u16>(10);
+    check_weighted_average::<u16>(16);
+}
+
+#[test]
+fn weighted_average_rounding() {
+    let x = Plane::from_vec(vec![3u8, 4, 5, 10], 4, 1);
+    let y = Plane::from_vec(vec![0u8, 1, 0, 3], 4, 1);
+    let mut dst = Plane::new(4, 1);
+    // (2x - 3y) / 4 is 1.5, 1.25, 2.5 and 2.75
+    let mut run = |rounding| {
+        weighted_average(
+            &[x.as_ref(), y.as_ref()],
+            &[2.0, -3.0],
+            4.0,
+            rounding,
+            8,
+            dst.as_mut(),
+        );
+        dst.data().to_vec()
+    };
+    assert_eq!(run(Rounding::HalfUp), vec![2, 1, 3, 3]);
+    assert_eq!(run(Rounding::Floor), vec![1, 1, 2, 2]);
+}
+
+fn check_median<T: Pixel>(bit_depth: u32) {
+    let planes: Vec<Plane<T>> = (0..6)
+        .map(|seed| noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 10 + seed))
+        .collect();
+    let mut dst = Plane::new(WIDTH, HEIGHT);
+    for count in 1..=planes.len() {
+        let src: Vec<_> = planes[..count].iter().map(Plane::as_ref).collect();
+        median(&src, dst.as_mut());
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let mut values: Vec<i64> = src.iter().map(|p| p.get(col, row).to_i64()).collect();
+                values.sort_unstable();
+                let middle = count / 2;
+                let expected = if count % 2 == 1 {
+                    values[middle]
+                } else {
+                    (values[middle - 1] + values[middle] + 1) >> 1
+                };
+                assert_eq!(
+                    dst.get(col, row).to_i64(),
+                    expected,
+                    "{} planes at ({}, {})",
+                    count,
+                    col,
+                    row
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn median_matches_definition() {
+    check_median::<u8>(8);
+    check_median::<u16>(10);
+    check_median::<u16>(16);
+}
+
+fn check_mask_merge<T: Pixel, M: Pixel>(bit_depth: u32, mask_bits: u32) {
+    let processed = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 20);
+    let src = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 21);
+    // Half of the samples unmasked, to cover both paths
+    let noise = noise_plane::<M>(WIDTH, HEIGHT, mask_bits, 22);
+    let mask = Plane::from_fn(WIDTH, HEIGHT, |x, y| {
+        if (x + y) % 2 == 0 {
+            M::default()
+        } else {
+            noise.get(x, y)
+        }
+    });
+    let mask_max = (1i64 << mask_bits) - 1;
+
+    for &mode in &[MaskMode::Replace, MaskMode::Blend] {
+        let mut dst = Plane::new(WIDTH, HEIGHT);
+        mask_merge(
+            processed.as_ref(),
+            src.as_ref(),
+            mask.as_ref(),
+            dst.as_mut(),
+            mask_bits,
+            mode,
+        );
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let p = processed.get(col, row).to_i64();
+                let s = src.get(col, row).to_i64();
+                let m = mask.get(col, row).to_i64();
+                let expected = match mode {
+                    _ if m == 0 => p,
+                    MaskMode::Replace => s,
+                    MaskMode::Blend => {
+                        (p as f64 + (s - p) as f64 * m as f64 / mask_max as f64).round() as i64
+                    }
+                };
+                assert!(
+                    (dst.get(col, row).to_i64() - expected).abs() <= 1,
+                    "{:?} at ({}, {})",
+                    mode,
+                    col,
+                    row
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn mask_merge_matches_definition() {
+    check_mask_merge::<u8, u8>(8, 8);
+    check_mask_merge::<u8, u16>(8, 16);
+    check_mask_merge::<u16, u8>(10, 8);
+    check_mask_merge::<u16, u16>(16, 16);
+}
+
+#[test]
+fn mask_merge_blends_the_extremes_exactly() {
+    let processed = noise_plane::<u16>(WIDTH, HEIGHT, 16, 23);
+    let src = noise_plane::<u16>(WIDTH, HEIGHT, 16, 24);
+    let run = |value| {
+        let mask = Plane::from_fn(WIDTH, HEIGHT, |_, _| value);
+        let mut dst = Plane::new(WIDTH, HEIGHT);
+        mask_merge(
+            processed.as_ref(),
+            src.as_ref(),
+            mask.as_ref(),
+            dst.as_mut(),
+            8,
+            MaskMode::Blend,
+        );
+        dst
+    };
+    assert_eq!(run(0u8), processed);
+    assert_eq!(run(255u8), src);
+}
+
+#[test]
+fn mask_merge_blends_partial_mask_values() {
+    let processed = Plane::from_vec(vec![100u8, 0, 255, 40], 4, 1);
+    let src = Plane::from_vec(vec![200u8, 255, 0, 40], 4, 1);
+    let blend = |mask: PlaneRef<u16>, mask_bits| {
+        let mut dst = Plane::new(4, 1);
+        mask_merge(
+            processed.as_ref(),
+            src.as_ref(),
+            mask,
+            dst.as_mut(),
+            mask_bits,
+            MaskMode::Blend,
+        );
+        dst
+    };
+    // 128 of 255 is a little over half of the source, rounded to nearest
+    let mask = Plane::from_vec(vec![128u16; 4], 4, 1);
+    assert_eq!(blend(mask.as_ref(), 8).data(), &[150, 128, 127, 40]);
+    let mask = Plane::from_vec(vec![32768u16; 4], 4, 1);
+    assert_eq!(blend(mask.as_ref(), 16).data(), &[150, 128, 127, 40]);
+}
+
+#[test]
+fn subsampled_masks_protect_any_covered_sample() {
+    // 5x3, so that the last column and row only cover part of a block
+    #[rustfmt::skip]
+    let mask = Plane::from_vec(vec![
+        0, 0, 255, 0, 0,
+        0, 0, 0, 0, 4,
+        0, 0, 0, 0, 0u8,
+    ], 5, 3);
+
+    let chroma = subsample_mask(mask.as_ref(), 1, 1);
+    assert_eq!((chroma.width(), chroma.height()), (3, 2));
+    assert_eq!(chroma.data(), &[0, 64, 2, 0, 0, 0]);
+
+    assert_eq!(subsample_mask(mask.as_ref(), 0, 0), mask);
+    assert_eq!(subsample_mask(mask.as_ref(), 1, 0).data()[..3], [0, 128, 0]);
+}
@@ -0,0 +1,143 @@
+//! The running minimum and maximum behind `expand` and `inpand`, checked
+//! against a straightforward search of each neighbourhood.
+
+mod common;
+
+use self::common::{noise_plane, padded};
+use mpeg2stinx_core::{
+    expand, expand_shape, inpand, inpand_shape, Mask, Pixel, Plane, PlaneRef, Shape,
+};
+
+const WIDTH: usize = 29;
+const HEIGHT: usize = 13;
+const RADII: [(u32, u32); 8] = [
+    (0, 0),
+    (1, 1),
+    (2, 1),
+    (0, 3),
+    (4, 0),
+    (3, 3),
+    (5, 2),
+    (40, 20),
+];
+
+fn in_shape(shape: Shape, rx: u32, ry: u32, dx: i64, dy: i64) -> bool {
+    match shape {
+        Shape::Mask(mask) => return mask.contains(dx as i32, dy as i32),
+        Shape::Field => return dx == 0 && dy % 2 == 0 && dy.abs() <= 2 * i64::from(ry),
+        _ => (),
+    }
+    let (dx, dy) = (dx.abs() as f64, dy.abs() as f64);
+    let (rx, ry) = (f64::from(rx), f64::from(ry));
+    if dx > rx || dy > ry {
+        return false;
+    }
+    match shape {
+        Shape::Square => true,
+        _ if ry == 0.0 => true,
+        _ if rx == 0.0 => dx == 0.0,
+        Shape::Diamond => dx / rx + dy / ry <= 1.0 + 1e-9,
+        Shape::Ellipse => (dx / rx).powi(2) + (dy / ry).powi(2) <= 1.0 + 1e-9,
+        _ => dx == 0.0 || dy == 0.0,
+    }
+}
+
+fn definition<T: Pixel>(
+    src: &Plane<T>,
+    rx: u32,
+    ry: u32,
+    shape: Shape,
+    pick: fn(T, T) -> T,
+) -> Plane<T> {
+    let clamp = |pos: i64, len: usize| pos.max(0).min(len as i64 - 1) as usize;
+    let (reach_x, reach_y) = match shape {
+        Shape::Mask(_) => (7, 7),
+        Shape::Field => (0, 2 * i64::from(ry)),
+        _ => (i64::from(rx), i64::from(ry)),
+    };
+    Plane::from_fn(src.width(), src.height(), |x, y| {
+        let mut result = src.get(x, y);
+        for dy in -reach_y..=reach_y {
+            for dx in -reach_x..=reach_x {
+                if in_shape(shape, rx, ry, dx, dy) {
+                    let px = src.get(
+                        clamp(x as i64 + dx, src.width()),
+                        clamp(y as i64 + dy, src.height()),
+                    );
+                    result = pick(result, px);
+                }
+            }
+        }
+        result
+    })
+}
+
+fn check<T: Pixel>(bit_depth: u32) {
+    let src = noise_plane::<T>(WIDTH, HEIGHT, bit_depth, 3);
+    // Run on a plane with padded rows, like VapourSynth frames have
+    let data = padded(&src, 3);
+    let plane = PlaneRef::new(&data, WIDTH, HEIGHT, WIDTH + 3);
+    let mask = Mask::new(&[(-3, 0), (-2, 0), (0, -2), (1, 1), (7, -7), (0, 5)]).unwrap();
+    let shapes = [
+        Shape::Square,
+        Shape::Diamond,
+        Shape::Ellipse,
+        Shape::Plus,
+        Shape::Field,
+        Shape::Mask(mask),
+    ];
+    for &shape in &shapes {
+        for &(rx, ry) in &RADII {
+            let expanded = expand_shape(plane, rx, ry, shape);
+            let inpanded = inpand_shape(plane, rx, ry, shape);
+            assert_eq!(
+                expanded,
+                definition(&src, rx, ry, shape, std::cmp::max),
+                "expand {:?} {}x{}",
+                shape,
+                rx,
+                ry
+            );
+            assert_eq!(
+                inpanded,
+                definition(&src, rx, ry, shape, std::cmp::min),
+                "inpand {:?} {}x{}",
+                shape,
+                rx,
+                ry
+            );
+        }
+    }
+}
+
+#[test]
+fn shapes_match_definition() {
+    check::<u8>(8);
+    check::<u16>(12);
+    check::<u32>(32);
+}
+
+#[test]
+fn masks_keep_their_offsets() {
+    let offsets = [(0, -1), (-2, 0), (0, 0), (1, 0), (3, 7)];
+    let mask = Mask::new(&offsets[..2]).unwrap();
+    assert!(mask.contains(0, 0), "the centre is always included");
+    assert_eq!(mask.offsets(), vec![(0, -1), (-2, 0), (0, 0)]);
+    assert_eq!(Mask::new(&offsets).unwrap().offsets(), offsets.to_vec());
+    assert_eq!(Mask::new(&[(8, 0)]), None);
+}
+
+#[test]
+fn rectangles_match_repeated_3x3_passes() {
+    // sw=3 sh=2 used to be two 3x3 passes followed by a horizontal one
+    let src = noise_plane::<u8>(WIDTH, HEIGHT, 8, 4);
+    let mut chained = expand(src.as_ref(), 1, 1);
+    chained = expand(chained.as_ref(), 1, 1);
+    chained = expand(chained.as_ref(), 1, 0);
+    assert_eq!(expand(src.as_ref(), 3, 2), chained);
+
+    let mut chained = inpand(src.as_ref(), 1, 1);
+    chained = inpand(chained.as_ref(), 1, 1);
+    chained = inpand(chained.as_ref(), 1, 0);
+    assert_eq!(inpand(src.as_ref(), 3, 2), chained);
+}
@@ -0,0 +1,452 @@
+//! The whole Mpeg2Stinx filter run on synthetic combed clips, checked
+//! through statistics of its output rather than exact values.
+
+mod common;
+
+use self::common::{comb_energy, max_abs_diff, noise_plane, psnr, Synthetic};
+use mpeg2stinx_core::{
+    mpeg2stinx, mpeg2stinx_tiled, BobMode, Combine, Frame, Mask, Params, Pixel, Plane, PlaneRef,
+    Shape,
+};
+
+fn run<T: Pixel>(clip: &Synthetic, n: usize, params: &Params) -> (Frame<T>, Frame<T>) {
+    let prev = clip.combed(n.saturating_sub(1));
+    let cur = clip.combed(n);
+    let next = clip.combed(n + 1);
+    let output = mpeg2stinx(&prev, &cur, &next, params);
+    (cur, output)
+}
+
+fn assert_decombs<T: Pixel>(clip: &Synthetic, params: &Params) {
+    let (src, output) = run::<T>(clip, 2, params);
+    // Vertically subsampled chroma blends both fields instead of combing,
+    // so only require it to stay below one 8-bit step
+    let floor = f64::from(1 << (clip.bit_depth - 8));
+    for plane in 0..3 {
+        let before = comb_energy(src.plane(plane));
+        let after = comb_energy(output.plane(plane));
+        assert!(
+            after < before * 0.5 || (plane > 0 && clip.ss_h > 0 && after < floor),
+            "plane {} of {:?} with {:?}: comb energy {} -> {}",
+            plane,
+            clip,
+            params,
+            before,
+            after
+        );
+    }
+}
+
+#[test]
+fn reduces_combing() {
+    let clip = Synthetic::default();
+    for &mode in &[BobMode::Point, BobMode::Spline36] {
+        for &(sw, sh) in &[(1, 1), (2, 1), (2, 2)] {
+            for &contra in &[true, false] {
+                let params = Params {
+                    mode,
+                    sw,
+                    sh,
+                    contra,
+                    blurv: if contra { 0.9 } else { 0.0 },
+                    ..Params::default()
+                };
+                assert_decombs::<u8>(&clip, &params);
+            }
+        }
+    }
+}
+
+#[test]
+fn reduces_combing_with_all_shapes() {
+    let mask = Mask::new(&[(0, -2), (0, -1), (0, 1), (0, 2), (-1, 0), (1, 0)]).unwrap();
+    let shapes = [
+        Shape::Diamond,
+        Shape::Ellipse,
+        Shape::Plus,
+        Shape::Field,
+        Shape::Mask(mask),
+    ];
+    for &shape in &shapes {
+        let params = Params {
+            sw: 2,
+            sh: 2,
+            shape,
+            ..Params::default()
+        };
+        assert_decombs::<u8>(&Synthetic::default(), &params);
+    }
+}
+
+#[test]
+fn reduces_combing_with_any_number_of_passes() {
+    let combines = [
+        Combine::Average,
+        Combine::Weighted,
+        Combine::Median,
+        Combine::Last,
+    ];
+    for &passes in &[1, 3, 4] {
+        for &combine in &combines {
+            let params = Params {
+                passes,
+                combine,
+                ..Params::default()
+            };
+            assert_decombs::<u8>(&Synthetic::default(), &params);
+        }
+    }
+}
+
+#[test]
+fn combinations_agree_on_a_single_pass() {
+    let clip = Synthetic::default();
+    let params = Params {
+        passes: 1,
+        ..Params::default()
+    };
+    let (_, expected) = run::<u8>(&clip, 2, &params);
+    for &combine in &[Combine::Weighted, Combine::Median, Combine::Last] {
+        let (_, output) = run::<u8>(&clip, 2, &Params { combine, ..params });
+        assert_eq!(output, expected, "{:?}", combine);
+    }
+    // Two passes are averaged by default, which the median does as well
+    let (_, average) = run::<u8>(&clip, 2, &Params::default());
+    let median = Params {
+        combine: Combine::Median,
+        ..Params::default()
+    };
+    assert_eq!(run::<u8>(&clip, 2, &median).1, average);
+}
+
+#[test]
+fn reduces_combing_at_high_bit_depths() {
+    for &bit_depth in &[10, 16] {
+        let clip = Synthetic {
+            bit_depth,
+            ..Synthetic::default()
+        };
+        assert_decombs::<u16>(&clip, &Params::default());
+    }
+}
+
+#[test]
+fn reduces_combing_for_all_subsamplings() {
+    for &(ss_w, ss_h) in &[(1, 1), (1, 0), (0, 0)] {
+        let clip = Synthetic {
+            ss_w,
+            ss_h,
+            ..Synthetic::default()
+        };
+        assert_decombs::<u8>(&clip, &Params::default());
+    }
+}
+
+#[test]
+fn reduces_combing_with_temporal_limiting() {
+    let params = Params {
+        diffscl: Some(2.0),
+        ..Params::default()
+    };
+    assert_decombs::<u8>(&Synthetic::default(), &params);
+}
+
+#[test]
+fn mostly_preserves_static_content() {
+    let clip = Synthetic {
+        speed: 0.0,
+        ..Synthetic::default()
+    };
+    let src = clip.progressive::<u8>(0);
+    let output = mpeg2stinx(&src, &src, &src, &Params::default());
+    for plane in 0..3 {
+        let quality = psnr(src.plane(plane), output.plane(plane), 8);
+        assert!(quality > 30.0, "plane {}: PSNR {}", plane, quality);
+    }
+}
+
+#[test]
+fn temporal_limiting_keeps_static_content() {
+    // Without any difference to the neighbouring frames the repair passes
+    // may not change anything, and nothing else runs without contra and blurv
+    let clip = Synthetic::default();
+    let src = clip.combed::<u8>(0);
+    let params = Params {
+        contra: false,
+        blurv: 0.0,
+        diffscl: Some(1.0),
+        ..Params::default()
+    };
+    let output = mpeg2stinx(&src, &src, &src, &params);
+    for plane in 0..3 {
+        assert_eq!(max_abs_diff(src.plane(plane), output.plane(plane)), 0);
+    }
+}
+
+#[test]
+fn keeps_format_and_range() {
+    let clip = Synthetic {
+        bit_depth: 10,
+        ss_w: 1,
+        ss_h: 0,
+        ..Synthetic::default()
+    };
+    let (src, output) = run::<u16>(&clip, 1, &Params::default());
+    assert_eq!(output.bit_depth, 10);
+    assert_eq!(output.plane_count(), 3);
+    for (src, output) in src.planes.iter().zip(output.planes.iter()) {
+        assert_eq!(
+            (output.width(), output.height()),
+            (src.width(), src.height())
+        );
+        assert!(output.data().iter().all(|&px| px <= 1023));
+    }
+}
+
+#[test]
+fn is_deterministic() {
+    let clip = Synthetic::default();
+    let (_, first) = run::<u8>(&clip, 3, &Params::default());
+    let (_, second) = run::<u8>(&clip, 3, &Params::default());
+    assert_eq!(first, second);
+}
+
+fn noise_frame<T: Pixel>(width: usize, height: usize, ss_h: u32, seed: u64) -> Frame<T> {
+    let chroma = |seed| noise_plane(width / 2, height >> ss_h, 8, seed);
+    Frame::new(
+        vec![
+            noise_plane(width, height, 8, seed),
+            chroma(seed + 1),
+            chroma(seed + 2),
+        ],
+        8,
+    )
+}
+
+fn planes<T: Pixel>(frame: &Frame<T>) -> Vec<PlaneRef<'_, T>> {
+    (0..frame.plane_count()).map(|i| frame.plane(i)).collect()
+}
+
+fn tiled<T: Pixel>(
+    prev: &Frame<T>,
+    cur: &Frame<T>,
+    next: &Frame<T>,
+    params: &Params,
+    tile_rows: usize,
+) -> Frame<T> {
+    let mut output = cur.map_planes(|_, plane| Plane::new(plane.width(), plane.height()));
+    mpeg2stinx_tiled(
+        &planes(prev),
+        &planes(cur),
+        &planes(next),
+        cur.bit_depth,
+        params,
+        tile_rows,
+        |i, first_row, rows| {
+            let mut dst = output.planes[i].as_mut();
+            for (y, row) in rows.rows().enumerate() {
+                dst.row_mut(first_row + y).copy_from_slice(row);
+            }
+        },
+    );
+    output
+}
+
+#[test]
+fn tiles_match_the_whole_frame() {
+    // Noise, so that every sample depends on all the rows it can
+    for &ss_h in &[0, 1] {
+        let (prev, cur, next) = (
+            noise_frame::<u8>(16, 176, ss_h, 1),
+            noise_frame(16, 176, ss_h, 4),
+            noise_frame(16, 176, ss_h, 7),
+        );
+        for &mode in &[BobMode::Point, BobMode::Spline36] {
+            for &(sh, passes) in &[(1, 1), (2, 2)] {
+                for &diffscl in &[None, Some(1.5)] {
+                    for &(interlaced, tff) in &[(false, true), (true, false)] {
+                        let params = Params {
+                            mode,
+                            sh,
+                            passes,
+                            diffscl,
+                            interlaced,
+                            tff,
+                            ..Params::default()
+                        };
+                        let whole = mpeg2stinx(&prev, &cur, &next, &params);
+                        for &tile_rows in &[8, 40] {
+                            assert_eq!(
+                                tiled(&prev, &cur, &next, &params, tile_rows),
+                                whole,
+                                "{} rows of 4:2:{} with {:?}",
+                                tile_rows,
+                                if ss_h == 0 { 2 } else { 0 },
+                                params
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn scales_chroma_radii_to_subsampling() {
+    let params = Params {
+        sw: 3,
+        sh: 2,
+        ..Params::default()
+    };
+    let chroma = params.chroma(1, 1);
+    assert_eq!((chroma.sw, chroma.sh), (2, 1));
+    assert_eq!(params.chroma(0, 0), params.luma());
+    // Radii of 1 stay 1, so the defaults repair chroma as before
+    let chroma = Params::default().chroma(1, 1);
+    assert_eq!((chroma.sw, chroma.sh), (1, 1));
+}
+
+#[test]
+fn chroma_overrides_leave_luma_untouched() {
+    let clip = Synthetic::default();
+    let (_, default) = run::<u8>(&clip, 2, &Params::default());
+    let params = Params {
+        sw_c: Some(2),
+        sh_c: Some(2),
+        sstr_c: Some(0.5),
+        ..Params::default()
+    };
+    let (_, output) = run::<u8>(&clip, 2, &params);
+    assert_eq!(max_abs_diff(default.plane(0), output.plane(0)), 0);
+    assert!(max_abs_diff(default.plane(1), output.plane(1)) > 0);
+}
+
+/// `frame` upside down, which turns a top field first frame into a bottom
+/// field first one with the same fields.
+fn flipped<T: Pixel>(frame: &Frame<T>) -> Frame<T> {
+    frame.map_planes(|_, plane| {
+        Plane::from_fn(plane.width(), plane.height(), |x, y| {
+            plane.get(x, plane.height() - 1 - y)
+        })
+    })
+}
+
+#[test]
+fn treats_both_field_orders_alike() {
+    // Each field is repaired against the other one whichever comes first,
+    // so bottom field first input is processed as its mirror image would be
+    let clip = Synthetic::default();
+    let (prev, cur, next) = (clip.combed(1), clip.combed(2), clip.combed(3));
+    for &mode in &[BobMode::Point, BobMode::Spline36] {
+        for &diffscl in &[None, Some(2.0)] {
+            for &interlaced in &[false, true] {
+                let params = Params {
+                    mode,
+                    sw: 2,
+                    diffscl,
+                    interlaced,
+                    ..Params::default()
+                };
+                let bff_params = Params {
+                    tff: false,
+                    ..params
+                };
+                let tff = mpeg2stinx::<u8>(&prev, &cur, &next, &params);
+                let bff = mpeg2stinx(
+                    &flipped(&prev),
+                    &flipped(&cur),
+                    &flipped(&next),
+                    &bff_params,
+                );
+                assert_eq!(flipped(&tff), bff, "{:?}", params);
+            }
+        }
+    }
+}
+
+#[test]
+fn reads_a_clip_played_backwards_as_bottom_field_first() {
+    // Reversing a top field first clip reverses the order of its fields, so
+    // each field keeps its neighbours only if `tff` follows the reversal
+    for seed in 0..4 {
+        let (prev, cur, next) = (
+            noise_frame::<u8>(32, 24, 1, 3 * seed),
+            noise_frame(32, 24, 1, 3 * seed + 1),
+            noise_frame(32, 24, 1, 3 * seed + 2),
+        );
+        // Temporal limiting is where the neighbours of a field are read
+        let params = Params {
+            mode: BobMode::Spline36,
+            diffscl: Some(0.25),
+            interlaced: true,
+            ..Params::default()
+        };
+        let bff_params = Params {
+            tff: false,
+            ..params
+        };
+        let forwards = mpeg2stinx::<u8>(&prev, &cur, &next, &params);
+        assert_eq!(
+            mpeg2stinx(&next, &cur, &prev, &bff_params),
+            forwards,
+            "seed {}",
+            seed
+        );
+        assert_ne!(
+            mpeg2stinx(&next, &cur, &prev, &params),
+            forwards,
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn keeps_the_motion_of_interlaced_frames() {
+    // Combing is the motion between the fields of interlaced video, which
+    // processing each field on its own leaves in place
+    let clip = Synthetic::default();
+    for &diffscl in &[None, Some(2.0)] {
+        let progressive = Params {
+            diffscl,
+            ..Params::default()
+        };
+        let interlaced = Params {
+            interlaced: true,
+            ..progressive
+        };
+        let (src, decombed) = run::<u8>(&clip, 2, &progressive);
+        let (_, output) = run::<u8>(&clip, 2, &interlaced);
+        let before = comb_energy(src.plane(0));
+        let after = comb_energy(output.plane(0));
+        assert!(
+            after > before * 0.8 && after > comb_energy(decombed.plane(0)) * 2.0,
+            "{:?}: comb energy {} -> {}",
+            diffscl,
+            before,
+            after
+        );
+        assert!(psnr(src.plane(0), output.plane(0), 8) > psnr(src.plane(0), decombed.plane(0), 8));
+    }
+}
+
+#[test]
+fn interlaced_frames_of_static_content_stay_close() {
+    let clip = Synthetic {
+        speed: 0.0,
+        ..Synthetic::default()
+    };
+    let params = Params {
+        interlaced: true,
+        ..Params::default()
+    };
+    let (src, output) = run::<u8>(&clip, 1, &params);
+    for plane in 0..3 {
+        assert!(
+            psnr(src.plane(plane), output.plane(plane), 8) > 35.0,
+            "plane {}",
+            plane
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Checks that every instruction set supported by the running CPU gives
+//! bit exact results compared to the scalar row kernels.
+
+mod common;
+
+use self::common::Lcg;
+use mpeg2stinx_core::{Isa, SimdLut, SimdPixel};
+use std::fmt::Debug;
+
+trait Sample: SimdPixel + Copy + Default + PartialEq + Debug {
+    const MAX: Self;
+    fn random(rng: &mut Lcg) -> Self;
+    /// The bits of the sample, so that floats compare exactly.
+    fn to_bits(self) -> u64;
+}
+
+macro_rules! impl_sample {
+    ($pix_ty:ty) => {
+        impl Sample for $pix_ty {
+            const MAX: Self = <$pix_ty>::MAX;
+
+            fn random(rng: &mut Lcg) -> Self {
+                // Favour the extremes, where signedness bugs show up
+                match rng.next_u64() % 8 {
+                    0 => 0,
+                    1 => Self::MAX,
+                    2 => Self::MAX / 2,
+                    3 => Self::MAX / 2 + 1,
+                    _ => ((rng.next_u64() << 32) | rng.next_u64()) as $pix_ty,
+                }
+            }
+
+            fn to_bits(self) -> u64 {
+                u64::from(self)
+            }
+        }
+    };
+}
+impl_sample!(u8);
+impl_sample!(u16);
+impl_sample!(u32);
+
+impl Sample for f32 {
+    const MAX: Self = f32::MAX;
+
+    fn random(rng: &mut Lcg) -> Self {
+        // Signed zeros, equal values and NaN are where the min and max
+        // instructions of the instruction sets differ
+        match rng.next_u64() % 10 {
+            0 => 0.0,
+            1 => -0.0,
+            2 => 1.0,
+            3 => f32::INFINITY,
+            4 => f32::NEG_INFINITY,
+            5 => f32::NAN,
+            _ => (rng.next_u64() as f32 / (1u64 << 31) as f32 - 0.5) * 1000.0,
+        }
+    }
+
+    fn to_bits(self) -> u64 {
+        u64::from(f32::to_bits(self))
+    }
+}
+
+// Long enough for several whole AVX2 vectors of u8 plus a tail of each length
+const MAX_LEN: usize = 100;
+// Offsets from the start of the buffers, to exercise unaligned loads and stores
+const MAX_OFFSET: usize = 3;
+
+type RowOp<'a, T> = &'a dyn Fn(Isa, &mut [T]);
+type BoxedRowOp<'a, T> = Box<dyn Fn(Isa, &mut [T]) + 'a>;
+
+fn run<T: Sample>(isa: Isa, len: usize, op: RowOp<T>) -> Vec<u64> {
+    let mut dst = vec![T::MAX; len];
+    op(isa, &mut dst);
+    dst.into_iter().map(T::to_bits).collect()
+}
+
+fn rows<T: Sample>(rng: &mut Lcg) -> [Vec<T>; 3] {
+    let mut row = || (0..MAX_LEN + MAX_OFFSET).map(|_| T::random(rng)).collect();
+    [row(), row(), row()]
+}
+
+fn check<T: Sample>(seed: u64) {
+    let mut rng = Lcg::new(seed);
+    for isa in Isa::available() {
+        for len in 0..=MAX_LEN {
+            for offset in 0..=MAX_OFFSET {
+                let [x, y, z] = rows::<T>(&mut rng);
+                let range = offset..offset + len;
+                let (x, y, z) = (&x[range.clone()], &y[range.clone()], &z[range]);
+
+                let ops: [(&str, RowOp<T>); 5] = [
+                    ("min", &|isa, dst| T::min_row(isa, x, y, dst)),
+                    ("max", &|isa, dst| T::max_row(isa, x, y, dst)),
+                    ("median3", &|isa, dst| T::median3_row(isa, x, y, z, dst)),
+                    ("diff", &|isa, dst| T::diff_row(isa, x, y, dst, T::MAX)),
+                    ("limited diff", &|isa, dst| {
+                        T::diff_row(isa, x, y, dst, z.first().copied().unwrap_or_default())
+                    }),
+                ];
+                for (name, op) in ops.iter() {
+                    assert_eq!(
+                        run(isa, len, *op),
+                        run(Isa::Scalar, len, *op),
+                        "{} with {:?} on {} samples at offset {}",
+                        name,
+                        isa,
+                        len,
+                        offset
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Strengths and scales beyond any sensible argument, including ones
+// whose products overflow or are NaN, as the `as` casts define those too
+const FACTORS: [f32; 10] = [
+    0.0,
+    1.0,
+    0.25,
+    -0.5,
+    2.7,
+    1e9,
+    -1e9,
+    f32::INFINITY,
+    f32::NEG_INFINITY,
+    f32::NAN,
+];
+
+fn check_lut<T: Sample + SimdLut>(seed: u64, bit_depths: &[u32]) {
+    let mut rng = Lcg::new(seed);
+    for isa in Isa::available() {
+        for &bit_depth in bit_depths {
+            let max = (1i64 << bit_depth) - 1;
+            let half = 1i64 << (bit_depth - 1);
+            for len in 0..=MAX_LEN {
+                let offset = len % (MAX_OFFSET + 1);
+                let [x, y, z] = rows::<T>(&mut rng);
+                let range = offset..offset + len;
+                let (x, y, z) = (&x[range.clone()], &y[range.clone()], &z[range]);
+
+                let mut ops: Vec<(String, BoxedRowOp<T>)> = vec![
+                    (
+                        "make_diff".into(),
+                        Box::new(|isa, dst| T::make_diff_row(isa, x, y, dst, half, max)),
+                    ),
+                    (
+                        "add_diff".into(),
+                        Box::new(|isa, dst| T::add_diff_row(isa, x, y, dst, half, max)),
+                    ),
+                ];
+                for &factor in FACTORS.iter() {
+                    ops.push((
+                        format!("sharp by {}", factor),
+                        Box::new(move |isa, dst| T::sharp_row(isa, x, y, dst, factor, max)),
+                    ));
+                    ops.push((
+                        format!("sharpd by {}", factor),
+                        Box::new(move |isa, dst| T::sharpd_row(isa, x, y, dst, factor, half, max)),
+                    ));
+                    ops.push((
+                        format!("limd by {}", factor),
+                        Box::new(move |isa, dst| T::limd_row(isa, x, y, dst, factor, half, max)),
+                    ));
+                    for &scl in [0.0, 0.25, factor].iter() {
+                        ops.push((
+                            format!("contrasharpen by {} and {}", factor, scl),
+                            Box::new(move |isa, dst| {
+                                T::contrasharpen_row(isa, z, x, y, dst, factor, scl, half, max)
+                            }),
+                        ));
+                    }
+                }
+                for (name, op) in ops.iter() {
+                    assert_eq!(
+                        run(isa, len, &**op),
+                        run(Isa::Scalar, len, &**op),
+                        "{} with {:?} on {} {}-bit samples at offset {}",
+                        name,
+                        isa,
+                        len,
+                        bit_depth,
+                        offset
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn u8_matches_scalar() {
+    check::<u8>(1);
+}
+
+#[test]
+fn u16_matches_scalar() {
+    check::<u16>(2);
+}
+
+#[test]
+fn u32_matches_scalar() {
+    check::<u32>(3);
+}
+
+#[test]
+fn f32_matches_scalar() {
+    check::<f32>(4);
+}
+
+#[test]
+fn u8_lut_matches_scalar() {
+    check_lut::<u8>(5, &[8]);
+}
+
+#[test]
+fn u16_lut_matches_scalar() {
+    check_lut::<u16>(6, &[9, 10, 16]);
+}
+
+#[test]
+fn u32_lut_matches_scalar() {
+    check_lut::<u32>(7, &[32]);
+}
+
+#[test]
+fn detects_an_available_isa() {
+    assert!(Isa::detect().is_available());
+    assert!(Isa::available().contains(&Isa::Scalar));
+}
@@ -0,0 +1,86 @@
+use mpeg2stinx_core::{Pixel, PlaneMut, PlaneRef};
+use std::mem;
+use std::slice;
+use vapoursynth::frame::Frame;
+
+/// Borrows a plane of a VapourSynth frame for use with the core kernels.
+pub(crate) fn plane_ref<'a, T: Pixel>(frame: &'a Frame<'_>, plane: usize) -> PlaneRef<'a, T> {
+    let width = frame.width(plane);
+    let height = frame.height(plane);
+    let stride = frame.stride(plane) / mem::size_of::<T>();
+    let len = if height == 0 {
+        0
+    } else {
+        stride * (height - 1) + width
+    };
+    // SAFETY: VapourSynth guarantees that a plane holds `height` rows of
+    // `stride` bytes, suitably aligned for the sample type of the format.
+    let data = unsafe { slice::from_raw_parts(frame.data_ptr(plane) as *const T, len) };
+    PlaneRef::new(data, width, height, stride)
+}
+
+/// Mutably borrows a plane of a VapourSynth frame for use with the core kernels.
+pub(crate) fn plane_mut<'a, T: Pixel>(frame: &'a mut Frame<'_>, plane: usize) -> PlaneMut<'a, T> {
+    let width = frame.width(plane);
+    let height = frame.height(plane);
+    let stride = frame.stride(plane) / mem::size_of::<T>();
+    let len = if height == 0 {
+        0
+    } else {
+        stride * (height - 1) + width
+    };
+    // SAFETY: as above, and the exclusive borrow of `frame` ensures nothing
+    // else can access the plane while the returned `PlaneMut` is alive.
+    let data = unsafe { slice::from_raw_parts_mut(frame.data_ptr_mut(plane) as *mut T, len) };
+    PlaneMut::new(data, width, height, stride)
+}
+
+/// Runs `$kernel` for each plane index yielded by `$planes`, with the named
+/// planes of the given frames bound to the right sample type.
+macro_rules! for_each_plane {
+    (
+        $planes:expr, $bytes_per_sample:expr,
+        ref [$($name:ident = $frame:expr),*],
+        mut [$dst_name:ident = $dst:expr],
+        $kernel:expr
+    ) => {
+        for plane in $planes {
+            match $bytes_per_sample {
+                1 => {
+                    $(let $name = crate::adapter::plane_ref::<u8>(&*$frame, plane);)*
+                    let $dst_name = crate::adapter::plane_mut::<u8>(&mut *$dst, plane);
+                    $kernel
+                }
+                2 => {
+                    $(let $name = crate::adapter::plane_ref::<u16>(&*$frame, plane);)*
+                    let $dst_name = crate::adapter::plane_mut::<u16>(&mut *$dst, plane);
+                    $kernel
+                }
+                4 => {
+                    $(let $name = crate::adapter::plane_ref::<u32>(&*$frame, plane);)*
+                    let $dst_name = crate::adapter::plane_mut::<u32>(&mut *$dst, plane);
+                    $kernel
+                }
+                _ => unreachable!(),
+            }
+        }
+    };
+}
+
+/// Runs `op` on each row of the given planes of 32-bit float frames, which
+/// the core kernels, being for integer `Pixel`s, only take a row at a time.
+pub(crate) fn for_each_float_row<const N: usize>(
+    planes: impl Iterator<Item = usize>,
+    frames: [&Frame<'_>; N],
+    dst: &mut Frame<'_>,
+    op: impl Fn([&[f32]; N], &mut [f32]),
+) {
+    for plane in planes {
+        for row in 0..dst.height(plane) {
+            op(
+                frames.map(|frame| frame.plane_row::<f32>(plane, row)),
+                dst.plane_row_mut::<f32>(plane, row),
+            );
+        }
+    }
+}
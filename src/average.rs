@@ -0,0 +1,152 @@
+use crate::adapter::{plane_mut, plane_ref};
+use crate::error::Error;
+use crate::planes::Planes;
+use mpeg2stinx_core::{median, weighted_average, Pixel, Rounding};
+use vapoursynth::core::CoreRef;
+use vapoursynth::format::SampleType;
+use vapoursynth::frame::Frame;
+use vapoursynth::prelude::*;
+
+/// Parses the `rounding` argument of `AverageFrames`.
+fn rounding_from_arg(rounding: i64) -> Result<Rounding, Error> {
+    match rounding {
+        0 => Ok(Rounding::HalfUp),
+        1 => Ok(Rounding::Floor),
+        _ => Err(Error::invalid_argument("rounding", "0 or 1")),
+    }
+}
+
+/// Checks the arguments of `AverageFrames` for `clips` clips, where `weights`
+/// default to 1 for each clip and `scale` to the sum of the weights.
+pub(crate) fn average_params(
+    clips: usize,
+    weights: Option<Vec<f64>>,
+    scale: Option<f64>,
+    rounding: Option<i64>,
+) -> Result<(Vec<f64>, f64, Rounding), Error> {
+    let weights = weights.unwrap_or_else(|| vec![1.0; clips]);
+    if weights.len() != clips {
+        return Err(Error::invalid_argument(
+            "weights",
+            "as many as there are clips",
+        ));
+    }
+    let scale = scale.unwrap_or_else(|| weights.iter().sum());
+    if scale == 0.0 || !scale.is_finite() {
+        return Err(Error::invalid_argument("scale", "non-zero"));
+    }
+    let rounding = match rounding {
+        Some(rounding) => rounding_from_arg(rounding)?,
+        None => Rounding::default(),
+    };
+    Ok((weights, scale, rounding))
+}
+
+pub(crate) fn rounding_to_arg(rounding: Rounding) -> i64 {
+    match rounding {
+        Rounding::HalfUp => 0,
+        Rounding::Floor => 1,
+    }
+}
+
+fn average_plane<T: Pixel>(
+    frames: &[FrameRef],
+    weights: &[f64],
+    scale: f64,
+    rounding: Rounding,
+    dst: &mut Frame,
+    plane: usize,
+) {
+    let bit_depth = frames[0].format().bitsPerSample as u32;
+    let src: Vec<_> = frames.iter().map(|f| plane_ref::<T>(f, plane)).collect();
+    weighted_average(
+        &src,
+        weights,
+        scale,
+        rounding,
+        bit_depth,
+        plane_mut::<T>(dst, plane),
+    );
+}
+
+// Float samples are neither rounded nor clamped, so that e.g. negative
+// chroma stays negative
+fn average_plane_float(
+    frames: &[FrameRef],
+    weights: &[f64],
+    scale: f64,
+    dst: &mut Frame,
+    plane: usize,
+) {
+    for row in 0..dst.height(plane) {
+        let src: Vec<&[f32]> = frames.iter().map(|f| f.plane_row(plane, row)).collect();
+        for (x, target) in dst.plane_row_mut::<f32>(plane, row).iter_mut().enumerate() {
+            let sum: f64 = src
+                .iter()
+                .zip(weights)
+                .map(|(row, &weight)| weight * f64::from(row[x]))
+                .sum();
+            *target = (sum / scale) as f32;
+        }
+    }
+}
+
+// Equivalent to `misc.AverageFrames`, except that the scale is applied
+// exactly as given and integer results are rounded with `rounding`
+pub(crate) fn average_frames<'core>(
+    core: CoreRef<'core>,
+    frames: &[FrameRef<'core>],
+    weights: &[f64],
+    scale: f64,
+    rounding: Rounding,
+    planes: Planes,
+) -> Result<FrameRef<'core>, Error> {
+    let mut filtered = FrameRefMut::copy_of(core, &*frames[0]);
+
+    // Assume formats are equivalent, because this was checked on creation
+    let format = frames[0].format();
+    for plane in planes.filter(0..format.plane_count()) {
+        match (format.sample_type(), format.bytes_per_sample()) {
+            (SampleType::Integer, 1) => {
+                average_plane::<u8>(frames, weights, scale, rounding, &mut filtered, plane)
+            }
+            (SampleType::Integer, 2) => {
+                average_plane::<u16>(frames, weights, scale, rounding, &mut filtered, plane)
+            }
+            (SampleType::Integer, 4) => {
+                average_plane::<u32>(frames, weights, scale, rounding, &mut filtered, plane)
+            }
+            (SampleType::Float, 4) => {
+                average_plane_float(frames, weights, scale, &mut filtered, plane)
+            }
+            _ => return Err(Error::unsupported_format("half precision samples")),
+        }
+    }
+    Ok(FrameRef::from(filtered))
+}
+
+fn median_plane<T: Pixel>(frames: &[FrameRef], dst: &mut Frame, plane: usize) {
+    let src: Vec<_> = frames.iter().map(|f| plane_ref::<T>(f, plane)).collect();
+    median(&src, plane_mut::<T>(dst, plane));
+}
+
+// The median of `frames` at each sample, see `mpeg2stinx_core::median`
+pub(crate) fn median_frames<'core>(
+    core: CoreRef<'core>,
+    frames: &[FrameRef<'core>],
+    planes: Planes,
+) -> Result<FrameRef<'core>, Error> {
+    let mut filtered = FrameRefMut::copy_of(core, &*frames[0]);
+
+    // The formats were checked on creation
+    let format = frames[0].format();
+    for plane in planes.filter(0..format.plane_count()) {
+        match format.bytes_per_sample() {
+            1 => median_plane::<u8>(frames, &mut filtered, plane),
+            2 => median_plane::<u16>(frames, &mut filtered, plane),
+            4 => median_plane::<u32>(frames, &mut filtered, plane),
+            _ => unreachable!(),
+        }
+    }
+    Ok(FrameRef::from(filtered))
+}
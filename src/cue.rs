@@ -0,0 +1,60 @@
+use crate::adapter::{plane_mut, plane_ref};
+use crate::error::Error;
+use mpeg2stinx_core::{BobMode, Pixel};
+use vapoursynth::core::CoreRef;
+use vapoursynth::frame::Frame;
+use vapoursynth::prelude::*;
+
+/// Checks the optional arguments of `CUERepair`.
+pub(crate) fn cue_params(
+    mode: Option<i64>,
+    threshold: Option<f64>,
+) -> Result<(BobMode, f64), Error> {
+    let mode = match mode.unwrap_or(1) {
+        0 => BobMode::Point,
+        1 => BobMode::Spline36,
+        _ => return Err(Error::invalid_argument("mode", "0 or 1")),
+    };
+    let threshold = threshold.unwrap_or(2.0);
+    if threshold.is_nan() || threshold < 0.0 {
+        return Err(Error::invalid_argument(
+            "threshold",
+            "a non-negative number",
+        ));
+    }
+    Ok((mode, threshold))
+}
+
+fn cue_plane<T: Pixel>(
+    src: &FrameRef,
+    mode: BobMode,
+    threshold: f64,
+    dst: &mut Frame,
+    plane: usize,
+) {
+    let bit_depth = src.format().bitsPerSample as u32;
+    let repaired =
+        mpeg2stinx_core::cue_repair(plane_ref::<T>(src, plane), mode, threshold, bit_depth);
+    plane_mut::<T>(dst, plane).copy_from(repaired.as_ref());
+}
+
+// Luma is kept as it is, see `mpeg2stinx_core::cue_repair` for chroma
+pub(crate) fn cue_repair<'core>(
+    core: CoreRef<'core>,
+    src: &FrameRef<'core>,
+    mode: BobMode,
+    threshold: f64,
+) -> Result<FrameRef<'core>, Error> {
+    let mut filtered = FrameRefMut::copy_of(core, &*src);
+
+    // The format was checked on creation
+    for plane in 1..src.format().plane_count() {
+        match src.format().bytesPerSample {
+            1 => cue_plane::<u8>(src, mode, threshold, &mut filtered, plane),
+            2 => cue_plane::<u16>(src, mode, threshold, &mut filtered, plane),
+            4 => cue_plane::<u32>(src, mode, threshold, &mut filtered, plane),
+            _ => unreachable!(),
+        }
+    }
+    Ok(FrameRef::from(filtered))
+}
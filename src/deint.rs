@@ -1,6 +1,5 @@
 use super::*;
-use failure::bail;
-use failure::Error;
+use crate::error::Error;
 use std::convert::TryFrom;
 use vapoursynth::core::CoreRef;
 use vapoursynth::prelude::*;
@@ -28,7 +27,7 @@ impl TryFrom<i64> for FilterMode {
             1 => FilterMode::Spline36Bob,
             2 => FilterMode::Nnedi3,
             3 => FilterMode::Nnedi3CL,
-            _ => bail!("Mpeg2Stinx: mode must be 0, 1, 2, or 3"),
+            _ => return Err(Error::invalid_argument("mode", "0, 1, 2 or 3")),
         })
     }
 }
@@ -39,12 +38,27 @@ impl FilterMode {
         core: CoreRef<'core>,
         api: API,
         src: &Node<'core>,
+        tff: bool,
     ) -> Result<Node<'core>, Error> {
+        // Double rate nnedi3 starts with the top (3) or bottom (2) field
+        let field = if tff { 3 } else { 2 };
         match self {
-            FilterMode::PointBob => point_bob(core, api, src),
-            FilterMode::Spline36Bob => spline36_bob(core, api, src, true),
-            FilterMode::Nnedi3 => nnedi3(core, api, src, 3, false),
-            FilterMode::Nnedi3CL => nnedi3(core, api, src, 3, true),
+            FilterMode::PointBob => point_bob(core, api, src, tff),
+            FilterMode::Spline36Bob => spline36_bob(core, api, src, true, tff),
+            FilterMode::Nnedi3 => nnedi3(
+                core,
+                api,
+                &set_field_order(core, api, src, tff)?,
+                field,
+                false,
+            ),
+            FilterMode::Nnedi3CL => nnedi3(
+                core,
+                api,
+                &set_field_order(core, api, src, tff)?,
+                field,
+                true,
+            ),
         }
     }
 }
@@ -53,12 +67,13 @@ pub(crate) fn point_bob<'core>(
     core: CoreRef<'core>,
     api: API,
     src: &Node<'core>,
+    tff: bool,
 ) -> Result<Node<'core>, Error> {
-    let clip = separate_rows(core, api, src)?;
+    let clip = separate_rows(core, api, src, tff)?;
     let res = if let Constant(res) = clip.info().resolution {
         res
     } else {
-        bail!("Resolution is not constant");
+        return Err(Error::unsupported_format("the resolution is not constant"));
     };
     point_resize(core, api, &clip, res.width as i64, 2 * res.height as i64)
 }
@@ -68,18 +83,23 @@ pub(crate) fn spline36_bob<'core>(
     api: API,
     src: &Node<'core>,
     process_chroma: bool,
+    tff: bool,
 ) -> Result<Node<'core>, Error> {
     let clip = separate_rows(
         core,
         api,
         &convert(core, api, src, PresetFormat::Gray8 as i32)?,
+        tff,
     )?;
     let res = if let Constant(res) = clip.info().resolution {
         res
     } else {
-        bail!("Resolution is not constant");
+        return Err(Error::unsupported_format("the resolution is not constant"));
     };
 
+    // Shift each field by a quarter of a field line, in opposite directions
+    // for top and bottom fields, so that its lines end up where they were
+    let shift = if tff { 0.25 } else { -0.25 };
     let even = spline36_resize_crop(
         core,
         api,
@@ -87,7 +107,7 @@ pub(crate) fn spline36_bob<'core>(
         res.width as i64,
         2 * res.height as i64,
         0.0,
-        0.25,
+        shift,
         res.width as f64,
         res.height as f64,
     )?;
@@ -98,7 +118,7 @@ pub(crate) fn spline36_bob<'core>(
         res.width as i64,
         2 * res.height as i64,
         0.0,
-        -0.25,
+        -shift,
         res.width as f64,
         res.height as f64,
     )?;
@@ -107,7 +127,7 @@ pub(crate) fn spline36_bob<'core>(
     let format = if let Constant(format) = src.info().format {
         format
     } else {
-        bail!("Resolution is not constant");
+        return Err(Error::unsupported_format("the format is not constant"));
     };
     if format.id() == FormatID::from(PresetFormat::Gray8) {
         return Ok(clip);
@@ -119,8 +139,8 @@ pub(crate) fn spline36_bob<'core>(
             api,
             &[
                 &clip,
-                &spline36_bob(core, api, &u_to_y8(core, api, &src)?, false)?,
-                &spline36_bob(core, api, &v_to_y8(core, api, &src)?, false)?,
+                &spline36_bob(core, api, &u_to_y8(core, api, &src)?, false, tff)?,
+                &spline36_bob(core, api, &v_to_y8(core, api, &src)?, false, tff)?,
             ],
             &[0, 0, 0],
             ColorFamily::YUV,
@@ -0,0 +1,136 @@
+use crate::deint::FilterMode;
+use crate::error::Error;
+use crate::stage::Params;
+use crate::third_party::{NNEDI3CL_NAMESPACE, NNEDI3_NAMESPACE, YADIFMOD_NAMESPACE};
+use crate::vsfunc::RGVS_NAMESPACE;
+use crate::zones::Zone;
+use mpeg2stinx_core::Shape;
+use vapoursynth::core::CoreRef;
+
+/// A plugin invoked by the filter chain of `build_stage`.
+///
+/// The std and resize plugins are part of VapourSynth itself, so only
+/// plugins that may be missing from an installation are listed.
+struct Plugin {
+    identifier: &'static str,
+    namespace: &'static str,
+    /// The arguments that avoid the plugin.
+    avoided_by: &'static str,
+}
+
+const RGVS: Plugin = Plugin {
+    identifier: RGVS_NAMESPACE,
+    namespace: "rgvs",
+    avoided_by: "sw and sh, or sw_c and sh_c for chroma, other than 1, a shape other than 0, \
+                 or fused=1",
+};
+
+const NNEDI3: Plugin = Plugin {
+    identifier: NNEDI3_NAMESPACE,
+    namespace: "nnedi3",
+    avoided_by: "mode=0 or mode=1",
+};
+
+const NNEDI3CL: Plugin = Plugin {
+    identifier: NNEDI3CL_NAMESPACE,
+    namespace: "nnedi3cl",
+    avoided_by: "mode=0 or mode=1, or mode=2 to run nnedi3 on the CPU",
+};
+
+const YADIFMOD: Plugin = Plugin {
+    identifier: YADIFMOD_NAMESPACE,
+    namespace: "yadifmod",
+    avoided_by: "order=-1",
+};
+
+/// A plugin that is required by the given arguments but not loaded.
+#[derive(Debug)]
+pub struct MissingPlugin {
+    pub identifier: &'static str,
+    pub namespace: &'static str,
+    /// The arguments that require the plugin, e.g. `mode=2 in zone 0-1499`.
+    pub required_by: Vec<String>,
+    pub avoided_by: &'static str,
+}
+
+/// The plugins invoked for `params` on a clip with the given chroma
+/// subsampling, each with the arguments requiring it.
+fn requirements(params: &Params, (ss_w, ss_h): (u32, u32)) -> Vec<(&'static Plugin, String)> {
+    let mut requirements = Vec::new();
+    let (luma_planes, chroma_planes) = params.planes.split();
+    let chroma = params.chroma(ss_w, ss_h);
+    // Other shapes are always repaired through the native `Expand` and `Inpand`
+    let square = params.shape == Shape::Square;
+    if square && luma_planes.is_some() && params.sw == 1 && params.sh == 1 {
+        requirements.push((&RGVS, "sw=1 and sh=1".to_owned()));
+    } else if square && chroma_planes.is_some() && chroma.sw == 1 && chroma.sh == 1 {
+        let reason = if params.sw_c.is_some() && params.sh_c.is_some() {
+            "sw_c=1 and sh_c=1".to_owned()
+        } else {
+            "sw and sh scaled to 1 for subsampled chroma".to_owned()
+        };
+        requirements.push((&RGVS, reason));
+    }
+    match params.mode {
+        FilterMode::Nnedi3 => requirements.push((&NNEDI3, "mode=2".to_owned())),
+        FilterMode::Nnedi3CL => requirements.push((&NNEDI3CL, "mode=3".to_owned())),
+        FilterMode::PointBob | FilterMode::Spline36Bob => (),
+    }
+    if params.order != -1 {
+        requirements.push((&YADIFMOD, format!("order={}", params.order)));
+    }
+    requirements
+}
+
+/// Checks that every plugin needed to build the filter chains of `params`
+/// and `zones` is loaded, reporting all missing plugins at once.
+pub(crate) fn check_dependencies(
+    core: CoreRef,
+    params: &Params,
+    zones: &[Zone],
+    subsampling: (u32, u32),
+    fused: bool,
+) -> Result<(), Error> {
+    // The fused pipeline doesn't invoke other plugins
+    if fused {
+        return Ok(());
+    }
+
+    let main = requirements(params, subsampling);
+    let mut required = main.clone();
+    for zone in zones {
+        // Zones inherit the main arguments, only mention what they change
+        for (plugin, reason) in requirements(&zone.params, subsampling) {
+            if !main
+                .iter()
+                .any(|(p, r)| p.identifier == plugin.identifier && *r == reason)
+            {
+                let reason = format!("{} in zone {}-{}", reason, zone.start, zone.end);
+                required.push((plugin, reason));
+            }
+        }
+    }
+
+    let mut missing: Vec<MissingPlugin> = Vec::new();
+    for (plugin, reason) in required {
+        if let Some(entry) = missing
+            .iter_mut()
+            .find(|entry| entry.identifier == plugin.identifier)
+        {
+            entry.required_by.push(reason);
+        } else if core.get_plugin_by_id(plugin.identifier)?.is_none() {
+            missing.push(MissingPlugin {
+                identifier: plugin.identifier,
+                namespace: plugin.namespace,
+                required_by: vec![reason],
+                avoided_by: plugin.avoided_by,
+            });
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MissingPlugins(missing))
+    }
+}
@@ -0,0 +1,158 @@
+use crate::dependencies::MissingPlugin;
+use std::error::Error as StdError;
+use std::ffi::NulError;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while creating or running our filters.
+///
+/// Each variant displays as a single message prefixed with `Mpeg2Stinx: `,
+/// which is what script users see. vapoursynth-rs expects `failure::Error`
+/// from filter functions and filters, which `?` converts to at that boundary.
+#[derive(Debug)]
+pub enum Error {
+    /// A plugin needed by the filter chain is not loaded.
+    MissingPlugin {
+        identifier: &'static str,
+        namespace: &'static str,
+    },
+    /// Plugins required by the chosen arguments are not loaded.
+    MissingPlugins(Vec<MissingPlugin>),
+    /// An argument, or a parameter of a zone, is outside of its valid range.
+    InvalidArgument { name: String, expected: String },
+    /// A zones definition could not be parsed.
+    InvalidZones(String),
+    /// The zones file could not be read.
+    ZonesFile { path: String, source: io::Error },
+    /// A clip has a format the requested processing can't handle.
+    UnsupportedFormat(String),
+    /// A frame requested in `get_frame_initial` was not delivered.
+    FrameFetch {
+        filter: &'static str,
+        input: &'static str,
+        frame: usize,
+    },
+    /// A function of another plugin reported an error.
+    Invoke { function: String, message: String },
+    /// A call into VapourSynth failed, e.g. a missing key in a map.
+    VapourSynth(String),
+    /// A filter panicked in a callback of the API v4, with the panic message.
+    #[cfg(feature = "api-v4")]
+    Panic(String),
+}
+
+/// What every message starts with, see `Error`.
+const PREFIX: &str = "Mpeg2Stinx: ";
+
+impl Error {
+    /// The error of a function of another plugin, or of one of our own
+    /// functions used as an internal node, whose message already carries
+    /// our prefix and keeps only the one `Display` adds.
+    pub(crate) fn invoke(function: impl Into<String>, message: &str) -> Self {
+        Error::Invoke {
+            function: function.into(),
+            message: message.strip_prefix(PREFIX).unwrap_or(message).to_owned(),
+        }
+    }
+
+    pub(crate) fn invalid_argument(name: impl Into<String>, expected: impl Into<String>) -> Self {
+        Error::InvalidArgument {
+            name: name.into(),
+            expected: expected.into(),
+        }
+    }
+
+    pub(crate) fn unsupported_format(reason: impl Into<String>) -> Self {
+        Error::UnsupportedFormat(reason.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", PREFIX)?;
+        match self {
+            Error::MissingPlugin {
+                identifier,
+                namespace,
+            } => write!(f, "the {} plugin ({}) is not loaded", namespace, identifier),
+            Error::MissingPlugins(plugins) => {
+                write!(f, "required plugins are not loaded:")?;
+                for plugin in plugins {
+                    write!(
+                        f,
+                        "\n  {} ({}), required by {}, avoided with {}",
+                        plugin.namespace,
+                        plugin.identifier,
+                        plugin.required_by.join(", "),
+                        plugin.avoided_by
+                    )?;
+                }
+                Ok(())
+            }
+            Error::InvalidArgument { name, expected } => {
+                write!(f, "{} must be {}", name, expected)
+            }
+            Error::InvalidZones(message) => write!(f, "invalid zones: {}", message),
+            Error::ZonesFile { path, source } => {
+                write!(f, "failed to read zones file {}: {}", path, source)
+            }
+            Error::UnsupportedFormat(reason) => write!(f, "unsupported format: {}", reason),
+            Error::FrameFetch {
+                filter,
+                input,
+                frame,
+            } => write!(f, "{} couldn't get frame {} of {}", filter, frame, input),
+            Error::Invoke { function, message } => write!(f, "{} failed: {}", function, message),
+            Error::VapourSynth(message) => write!(f, "{}", message),
+            #[cfg(feature = "api-v4")]
+            Error::Panic(message) => write!(f, "internal error: {}", message),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::ZonesFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Self {
+        Error::VapourSynth(e.to_string())
+    }
+}
+
+impl From<vapoursynth::map::Error> for Error {
+    fn from(e: vapoursynth::map::Error) -> Self {
+        Error::VapourSynth(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_errors_are_prefixed_once() {
+        let inner = Error::invalid_argument("planes", "between 0 and 2");
+        let outer = Error::invoke("Min", &inner.to_string());
+        assert_eq!(
+            outer.to_string(),
+            "Mpeg2Stinx: Min failed: planes must be between 0 and 2"
+        );
+        let outermost = Error::invoke("MakeDiff", &outer.to_string());
+        assert_eq!(outermost.to_string().matches("Mpeg2Stinx").count(), 1);
+    }
+
+    #[test]
+    fn other_plugins_keep_their_messages() {
+        let error = Error::invoke("Repair", "Repair: mode must be between -1 and 24");
+        assert_eq!(
+            error.to_string(),
+            "Mpeg2Stinx: Repair failed: Repair: mode must be between -1 and 24"
+        );
+    }
+}
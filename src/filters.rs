@@ -1,19 +1,27 @@
+use crate::average::{average_frames, average_params, median_frames, rounding_to_arg};
+use crate::cue::{cue_params, cue_repair};
+use crate::error::Error;
 use crate::lutxy::lutxy_diff;
+use crate::morphology::{expand, inpand, radius_from_arg, shape_to_args};
+use crate::nodes::{create_node, InternalFilter};
+use crate::planes::Planes;
+use crate::requests::{DeclaredRequests, Parallelism, RequestPattern};
 use crate::util::{max, median3, min};
-use crate::MPEG2STINX_NAMESPACE;
-use failure::Error;
-use failure::{bail, format_err};
+use mpeg2stinx_core::{BobMode, Rounding, Shape};
 use vapoursynth::api::API;
 use vapoursynth::core::CoreRef;
+use vapoursynth::format::{ColorFamily, SampleType};
 use vapoursynth::frame::FrameRef;
 use vapoursynth::map::OwnedMap;
 use vapoursynth::node::Node;
 use vapoursynth::plugins::*;
+use vapoursynth::video_info::Property::Constant;
 use vapoursynth::video_info::VideoInfo;
 
 pub(crate) struct Min<'core> {
     pub clip1: Node<'core>,
     pub clip2: Node<'core>,
+    pub planes: Planes,
 }
 
 impl<'core> Filter<'core> for Min<'core> {
@@ -27,9 +35,8 @@ impl<'core> Filter<'core> for Min<'core> {
         _core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<Option<FrameRef<'core>>, Error> {
-        self.clip1.request_frame_filter(context, n);
-        self.clip2.request_frame_filter(context, n);
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
         Ok(None)
     }
 
@@ -39,17 +46,47 @@ impl<'core> Filter<'core> for Min<'core> {
         core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<FrameRef<'core>, Error> {
+    ) -> Result<FrameRef<'core>, failure::Error> {
         let clip1 = self
             .clip1
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Min: Couldn't get clip1 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip1",
+                frame: n,
+            })?;
         let clip2 = self
             .clip2
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Min: Couldn't get clip2 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip2",
+                frame: n,
+            })?;
 
-        min(core, &clip1, &clip2)
+        Ok(min(core, &clip1, &clip2, self.planes)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Min<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![
+            (&self.clip1, RequestPattern::StrictSpatial),
+            (&self.clip2, RequestPattern::StrictSpatial),
+        ]
+    }
+}
+
+impl<'core> InternalFilter<'core> for Min<'core> {
+    const NAME: &'static str = "Min";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip1", &self.clip1)?;
+        args.set_node("clip2", &self.clip2)?;
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
     }
 }
 
@@ -58,25 +95,23 @@ pub(crate) fn min_clip<'core>(
     api: API,
     clip1: &Node<'core>,
     clip2: &Node<'core>,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
-    let mpeg2stinx = core
-        .get_plugin_by_id(MPEG2STINX_NAMESPACE)
-        .map_err(Error::from)?
-        .unwrap();
-
-    let mut args = OwnedMap::new(api);
-    args.set_node("clip1", &*clip1)?;
-    args.set_node("clip2", &*clip2)?;
-    let result = mpeg2stinx.invoke("Min", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    create_node(
+        core,
+        api,
+        Min {
+            clip1: clip1.clone(),
+            clip2: clip2.clone(),
+            planes,
+        },
+    )
 }
 
 pub(crate) struct Max<'core> {
     pub clip1: Node<'core>,
     pub clip2: Node<'core>,
+    pub planes: Planes,
 }
 
 impl<'core> Filter<'core> for Max<'core> {
@@ -90,9 +125,8 @@ impl<'core> Filter<'core> for Max<'core> {
         _core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<Option<FrameRef<'core>>, Error> {
-        self.clip1.request_frame_filter(context, n);
-        self.clip2.request_frame_filter(context, n);
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
         Ok(None)
     }
 
@@ -102,17 +136,47 @@ impl<'core> Filter<'core> for Max<'core> {
         core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<FrameRef<'core>, Error> {
+    ) -> Result<FrameRef<'core>, failure::Error> {
         let clip1 = self
             .clip1
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Max: Couldn't get clip1 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip1",
+                frame: n,
+            })?;
         let clip2 = self
             .clip2
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Max: Couldn't get clip2 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip2",
+                frame: n,
+            })?;
+
+        Ok(max(core, &clip1, &clip2, self.planes)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Max<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![
+            (&self.clip1, RequestPattern::StrictSpatial),
+            (&self.clip2, RequestPattern::StrictSpatial),
+        ]
+    }
+}
 
-        max(core, &clip1, &clip2)
+impl<'core> InternalFilter<'core> for Max<'core> {
+    const NAME: &'static str = "Max";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip1", &self.clip1)?;
+        args.set_node("clip2", &self.clip2)?;
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
     }
 }
 
@@ -121,27 +185,24 @@ pub(crate) fn max_clip<'core>(
     api: API,
     clip1: &Node<'core>,
     clip2: &Node<'core>,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
-    let mpeg2stinx = core
-        .get_plugin_by_id(MPEG2STINX_NAMESPACE)
-        .map_err(Error::from)?
-        .unwrap();
-
-    let mut args = OwnedMap::new(api);
-    args.set_node("clip1", &*clip1)?;
-    args.set_node("clip2", &*clip2)?;
-    let result = mpeg2stinx.invoke("Max", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    create_node(
+        core,
+        api,
+        Max {
+            clip1: clip1.clone(),
+            clip2: clip2.clone(),
+            planes,
+        },
+    )
 }
 
 pub(crate) struct Median3<'core> {
     pub clip1: Node<'core>,
     pub clip2: Node<'core>,
     pub clip3: Node<'core>,
-    pub process_chroma: bool,
+    pub planes: Planes,
 }
 
 impl<'core> Filter<'core> for Median3<'core> {
@@ -155,10 +216,8 @@ impl<'core> Filter<'core> for Median3<'core> {
         _core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<Option<FrameRef<'core>>, Error> {
-        self.clip1.request_frame_filter(context, n);
-        self.clip2.request_frame_filter(context, n);
-        self.clip3.request_frame_filter(context, n);
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
         Ok(None)
     }
 
@@ -168,21 +227,57 @@ impl<'core> Filter<'core> for Median3<'core> {
         core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<FrameRef<'core>, Error> {
+    ) -> Result<FrameRef<'core>, failure::Error> {
         let clip1 = self
             .clip1
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Median3: Couldn't get clip1 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip1",
+                frame: n,
+            })?;
         let clip2 = self
             .clip2
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Median3: Couldn't get clip2 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip2",
+                frame: n,
+            })?;
         let clip3 = self
             .clip3
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Median3: Couldn't get clip3 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip3",
+                frame: n,
+            })?;
 
-        median3(core, &clip1, &clip2, &clip3, self.process_chroma)
+        Ok(median3(core, &clip1, &clip2, &clip3, self.planes)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Median3<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![
+            (&self.clip1, RequestPattern::StrictSpatial),
+            (&self.clip2, RequestPattern::StrictSpatial),
+            (&self.clip3, RequestPattern::StrictSpatial),
+        ]
+    }
+}
+
+impl<'core> InternalFilter<'core> for Median3<'core> {
+    const NAME: &'static str = "Median3";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip1", &self.clip1)?;
+        args.set_node("clip2", &self.clip2)?;
+        args.set_node("clip3", &self.clip3)?;
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
     }
 }
 
@@ -192,28 +287,24 @@ pub(crate) fn median3_clip<'core>(
     clip1: &Node<'core>,
     clip2: &Node<'core>,
     clip3: &Node<'core>,
-    process_chroma: bool,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
-    let mpeg2stinx = core
-        .get_plugin_by_id(MPEG2STINX_NAMESPACE)
-        .map_err(Error::from)?
-        .unwrap();
-
-    let mut args = OwnedMap::new(api);
-    args.set_node("clip1", &*clip1)?;
-    args.set_node("clip2", &*clip2)?;
-    args.set_node("clip3", &*clip3)?;
-    args.set_int("process_chroma", process_chroma as i64)?;
-    let result = mpeg2stinx.invoke("Median3", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    create_node(
+        core,
+        api,
+        Median3 {
+            clip1: clip1.clone(),
+            clip2: clip2.clone(),
+            clip3: clip3.clone(),
+            planes,
+        },
+    )
 }
 
 pub(crate) struct LutXYDiff<'core> {
     pub clip1: Node<'core>,
     pub clip2: Node<'core>,
+    pub planes: Planes,
 }
 
 impl<'core> Filter<'core> for LutXYDiff<'core> {
@@ -227,9 +318,8 @@ impl<'core> Filter<'core> for LutXYDiff<'core> {
         _core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<Option<FrameRef<'core>>, Error> {
-        self.clip1.request_frame_filter(context, n);
-        self.clip2.request_frame_filter(context, n);
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
         Ok(None)
     }
 
@@ -239,17 +329,47 @@ impl<'core> Filter<'core> for LutXYDiff<'core> {
         core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<FrameRef<'core>, Error> {
+    ) -> Result<FrameRef<'core>, failure::Error> {
         let clip1 = self
             .clip1
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("LutXYDiff: Couldn't get clip1 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip1",
+                frame: n,
+            })?;
         let clip2 = self
             .clip2
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("LutXYDiff: Couldn't get clip2 frame"))?;
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip2",
+                frame: n,
+            })?;
 
-        lutxy_diff(core, &clip1, &clip2)
+        Ok(lutxy_diff(core, &clip1, &clip2, self.planes)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for LutXYDiff<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![
+            (&self.clip1, RequestPattern::StrictSpatial),
+            (&self.clip2, RequestPattern::StrictSpatial),
+        ]
+    }
+}
+
+impl<'core> InternalFilter<'core> for LutXYDiff<'core> {
+    const NAME: &'static str = "LutXYDiff";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip1", &self.clip1)?;
+        args.set_node("clip2", &self.clip2)?;
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
     }
 }
 
@@ -258,18 +378,610 @@ pub(crate) fn lutxy_diff_clip<'core>(
     api: API,
     clip1: &Node<'core>,
     clip2: &Node<'core>,
+    planes: Planes,
+) -> Result<Node<'core>, Error> {
+    create_node(
+        core,
+        api,
+        LutXYDiff {
+            clip1: clip1.clone(),
+            clip2: clip2.clone(),
+            planes,
+        },
+    )
+}
+
+pub(crate) struct AverageFrames<'core> {
+    pub clips: Vec<Node<'core>>,
+    pub weights: Vec<f64>,
+    pub scale: f64,
+    pub rounding: Rounding,
+    pub planes: Planes,
+}
+
+impl<'core> AverageFrames<'core> {
+    /// Checks the arguments of the `AverageFrames` function.
+    pub(crate) fn new(
+        clips: Vec<Node<'core>>,
+        weights: Option<Vec<f64>>,
+        scale: Option<f64>,
+        rounding: Option<i64>,
+        planes: Planes,
+    ) -> Result<Self, Error> {
+        let first = match clips.first() {
+            Some(first) => first.info(),
+            None => return Err(Error::invalid_argument("clips", "at least one clip")),
+        };
+        let format = match first.format {
+            Constant(format) => format,
+            _ => return Err(Error::unsupported_format("the format is not constant")),
+        };
+        if format.sample_type() == SampleType::Float && format.bits_per_sample() != 32 {
+            return Err(Error::unsupported_format("half precision samples"));
+        }
+        for clip in &clips[1..] {
+            let info = clip.info();
+            if info.format != first.format || info.resolution != first.resolution {
+                return Err(Error::invalid_argument(
+                    "clips",
+                    "all of the same format and size",
+                ));
+            }
+        }
+
+        let (weights, scale, rounding) = average_params(clips.len(), weights, scale, rounding)?;
+        Ok(AverageFrames {
+            clips,
+            weights,
+            scale,
+            rounding,
+            planes,
+        })
+    }
+}
+
+impl<'core> Filter<'core> for AverageFrames<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.clips[0].info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, failure::Error> {
+        let frames = self
+            .clips
+            .iter()
+            .map(|clip| {
+                clip.get_frame_filter(context, n).ok_or(Error::FrameFetch {
+                    filter: Self::NAME,
+                    input: "clips",
+                    frame: n,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(average_frames(
+            core,
+            &frames,
+            &self.weights,
+            self.scale,
+            self.rounding,
+            self.planes,
+        )?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for AverageFrames<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        self.clips
+            .iter()
+            .map(|clip| (clip, RequestPattern::StrictSpatial))
+            .collect()
+    }
+}
+
+impl<'core> InternalFilter<'core> for AverageFrames<'core> {
+    const NAME: &'static str = "AverageFrames";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        for (clip, &weight) in self.clips.iter().zip(&self.weights) {
+            args.append_node("clips", clip)?;
+            args.append_float("weights", weight)?;
+        }
+        args.set_float("scale", self.scale)?;
+        args.set_int("rounding", rounding_to_arg(self.rounding))?;
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
+    }
+}
+
+/// Native replacement for `misc.AverageFrames`, see `AverageFrames::new`.
+pub(crate) fn average_frames_clip<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clips: &[&Node<'core>],
+    weights: Option<&[f64]>,
+    scale: Option<f64>,
+    planes: Planes,
+) -> Result<Node<'core>, Error> {
+    create_node(
+        core,
+        api,
+        AverageFrames::new(
+            clips.iter().map(|&clip| clip.clone()).collect(),
+            weights.map(<[f64]>::to_vec),
+            scale,
+            None,
+            planes,
+        )?,
+    )
+}
+
+pub(crate) struct MedianFrames<'core> {
+    pub clips: Vec<Node<'core>>,
+    pub planes: Planes,
+}
+
+impl<'core> MedianFrames<'core> {
+    /// Checks the arguments of the `MedianFrames` function.
+    pub(crate) fn new(clips: Vec<Node<'core>>, planes: Planes) -> Result<Self, Error> {
+        let first = match clips.first() {
+            Some(first) => first.info(),
+            None => return Err(Error::invalid_argument("clips", "at least one clip")),
+        };
+        match first.format {
+            Constant(format) if format.sample_type() == SampleType::Integer => (),
+            Constant(_) => {
+                return Err(Error::unsupported_format(
+                    "MedianFrames only supports integer formats",
+                ))
+            }
+            _ => return Err(Error::unsupported_format("the format is not constant")),
+        }
+        for clip in &clips[1..] {
+            let info = clip.info();
+            if info.format != first.format || info.resolution != first.resolution {
+                return Err(Error::invalid_argument(
+                    "clips",
+                    "all of the same format and size",
+                ));
+            }
+        }
+        Ok(MedianFrames { clips, planes })
+    }
+}
+
+impl<'core> Filter<'core> for MedianFrames<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.clips[0].info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, failure::Error> {
+        let frames = self
+            .clips
+            .iter()
+            .map(|clip| {
+                clip.get_frame_filter(context, n).ok_or(Error::FrameFetch {
+                    filter: Self::NAME,
+                    input: "clips",
+                    frame: n,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(median_frames(core, &frames, self.planes)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for MedianFrames<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        self.clips
+            .iter()
+            .map(|clip| (clip, RequestPattern::StrictSpatial))
+            .collect()
+    }
+}
+
+impl<'core> InternalFilter<'core> for MedianFrames<'core> {
+    const NAME: &'static str = "MedianFrames";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        for clip in &self.clips {
+            args.append_node("clips", clip)?;
+        }
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
+    }
+}
+
+pub(crate) fn median_frames_clip<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clips: &[&Node<'core>],
+    planes: Planes,
+) -> Result<Node<'core>, Error> {
+    create_node(
+        core,
+        api,
+        MedianFrames::new(clips.iter().map(|&clip| clip.clone()).collect(), planes)?,
+    )
+}
+
+pub(crate) struct Expand<'core> {
+    pub clip: Node<'core>,
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+    pub planes: Planes,
+}
+
+impl<'core> Expand<'core> {
+    /// Checks the arguments of the `Expand` function.
+    pub(crate) fn new(
+        clip: Node<'core>,
+        sw: Option<i64>,
+        sh: Option<i64>,
+        shape: Shape,
+        planes: Planes,
+    ) -> Result<Self, Error> {
+        Ok(Expand {
+            clip,
+            sw: radius_from_arg("sw", sw.unwrap_or(1))?,
+            sh: radius_from_arg("sh", sh.unwrap_or(1))?,
+            shape,
+            planes,
+        })
+    }
+}
+
+impl<'core> Filter<'core> for Expand<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.clip.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, failure::Error> {
+        let clip = self
+            .clip
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip",
+                frame: n,
+            })?;
+
+        Ok(expand(
+            core,
+            &clip,
+            self.sw,
+            self.sh,
+            self.shape,
+            self.planes,
+        )?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Expand<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![(&self.clip, RequestPattern::StrictSpatial)]
+    }
+}
+
+impl<'core> InternalFilter<'core> for Expand<'core> {
+    const NAME: &'static str = "Expand";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip", &self.clip)?;
+        args.set_int("sw", i64::from(self.sw))?;
+        args.set_int("sh", i64::from(self.sh))?;
+        let (shape, coordinates) = shape_to_args(self.shape);
+        args.set_int("shape", shape)?;
+        if let Some(coordinates) = coordinates {
+            args.set_int_array("coordinates", &coordinates)?;
+        }
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
+    }
+}
+
+/// `sw` and `sh` passes of `std.Maximum` in a single node, whatever the radii.
+pub(crate) fn expand_clip<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clip: &Node<'core>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+    planes: Planes,
+) -> Result<Node<'core>, Error> {
+    if sw == 0 && sh == 0 {
+        return Ok(clip.clone());
+    }
+    create_node(
+        core,
+        api,
+        Expand {
+            clip: clip.clone(),
+            sw,
+            sh,
+            shape,
+            planes,
+        },
+    )
+}
+
+pub(crate) struct Inpand<'core> {
+    pub clip: Node<'core>,
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+    pub planes: Planes,
+}
+
+impl<'core> Inpand<'core> {
+    /// Checks the arguments of the `Inpand` function.
+    pub(crate) fn new(
+        clip: Node<'core>,
+        sw: Option<i64>,
+        sh: Option<i64>,
+        shape: Shape,
+        planes: Planes,
+    ) -> Result<Self, Error> {
+        Ok(Inpand {
+            clip,
+            sw: radius_from_arg("sw", sw.unwrap_or(1))?,
+            sh: radius_from_arg("sh", sh.unwrap_or(1))?,
+            shape,
+            planes,
+        })
+    }
+}
+
+impl<'core> Filter<'core> for Inpand<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.clip.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, failure::Error> {
+        let clip = self
+            .clip
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip",
+                frame: n,
+            })?;
+
+        Ok(inpand(
+            core,
+            &clip,
+            self.sw,
+            self.sh,
+            self.shape,
+            self.planes,
+        )?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Inpand<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![(&self.clip, RequestPattern::StrictSpatial)]
+    }
+}
+
+impl<'core> InternalFilter<'core> for Inpand<'core> {
+    const NAME: &'static str = "Inpand";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip", &self.clip)?;
+        args.set_int("sw", i64::from(self.sw))?;
+        args.set_int("sh", i64::from(self.sh))?;
+        let (shape, coordinates) = shape_to_args(self.shape);
+        args.set_int("shape", shape)?;
+        if let Some(coordinates) = coordinates {
+            args.set_int_array("coordinates", &coordinates)?;
+        }
+        args.set_int_array("planes", &self.planes.to_arg())?;
+        Ok(())
+    }
+}
+
+/// `sw` and `sh` passes of `std.Minimum` in a single node, whatever the radii.
+pub(crate) fn inpand_clip<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clip: &Node<'core>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
-    let mpeg2stinx = core
-        .get_plugin_by_id(MPEG2STINX_NAMESPACE)
-        .map_err(Error::from)?
-        .unwrap();
-
-    let mut args = OwnedMap::new(api);
-    args.set_node("clip1", &*clip1)?;
-    args.set_node("clip2", &*clip2)?;
-    let result = mpeg2stinx.invoke("LutXYDiff", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    if sw == 0 && sh == 0 {
+        return Ok(clip.clone());
+    }
+    create_node(
+        core,
+        api,
+        Inpand {
+            clip: clip.clone(),
+            sw,
+            sh,
+            shape,
+            planes,
+        },
+    )
+}
+
+pub(crate) struct CueRepair<'core> {
+    pub clip: Node<'core>,
+    pub mode: BobMode,
+    pub threshold: f64,
+}
+
+impl<'core> CueRepair<'core> {
+    /// Checks the arguments of the `CUERepair` function.
+    pub(crate) fn new(
+        clip: Node<'core>,
+        mode: Option<i64>,
+        threshold: Option<f64>,
+    ) -> Result<Self, Error> {
+        let info = clip.info();
+        let format = match info.format {
+            Constant(format) => format,
+            _ => return Err(Error::unsupported_format("the format is not constant")),
+        };
+        if format.color_family() != ColorFamily::YUV || format.sub_sampling_h() != 1 {
+            return Err(Error::unsupported_format(
+                "CUERepair requires YUV with vertically subsampled chroma, like 4:2:0",
+            ));
+        }
+        if format.sample_type() != SampleType::Integer {
+            return Err(Error::unsupported_format(
+                "CUERepair only supports integer formats",
+            ));
+        }
+        match info.resolution {
+            Constant(resolution) if resolution.height % 4 == 0 => (),
+            _ => {
+                return Err(Error::unsupported_format(
+                    "CUERepair requires a constant height divisible by 4",
+                ))
+            }
+        }
+
+        let (mode, threshold) = cue_params(mode, threshold)?;
+        Ok(CueRepair {
+            clip,
+            mode,
+            threshold,
+        })
+    }
+}
+
+impl<'core> Filter<'core> for CueRepair<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.clip.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, failure::Error> {
+        let clip = self
+            .clip
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: Self::NAME,
+                input: "clip",
+                frame: n,
+            })?;
+
+        Ok(cue_repair(core, &clip, self.mode, self.threshold)?)
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for CueRepair<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, _n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        vec![(&self.clip, RequestPattern::StrictSpatial)]
+    }
+}
+
+impl<'core> InternalFilter<'core> for CueRepair<'core> {
+    const NAME: &'static str = "CUERepair";
+
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error> {
+        args.set_node("clip", &self.clip)?;
+        args.set_int("mode", self.mode as i64)?;
+        args.set_float("threshold", self.threshold)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,142 @@
+use super::*;
+use crate::adapter::{plane_mut, plane_ref};
+use crate::error::Error;
+use mpeg2stinx_core::{mpeg2stinx_tiled, BobMode, Pixel, PlaneRef};
+use vapoursynth::core::CoreRef;
+use vapoursynth::format::SampleType;
+use vapoursynth::prelude::*;
+use vapoursynth::video_info::Property::Constant;
+
+/// Converts `params` for the native pipeline of `mpeg2stinx_core`, which
+/// implements the resizer based modes but neither nnedi3, yadifmod nor dithering.
+pub(crate) fn core_params(params: &Params) -> Result<mpeg2stinx_core::Params, Error> {
+    let mode = match params.mode {
+        FilterMode::PointBob => BobMode::Point,
+        FilterMode::Spline36Bob => BobMode::Spline36,
+        FilterMode::Nnedi3 | FilterMode::Nnedi3CL => {
+            return Err(Error::invalid_argument(
+                "mode",
+                "0 or 1 with fused processing",
+            ));
+        }
+    };
+    if params.order != -1 {
+        return Err(Error::invalid_argument("order", "-1 with fused processing"));
+    }
+    if params.dither {
+        return Err(Error::invalid_argument("dither", "0 with fused processing"));
+    }
+    Ok(mpeg2stinx_core::Params {
+        mode,
+        sw: params.sw,
+        sh: params.sh,
+        shape: params.shape,
+        contra: params.contra,
+        blurv: params.blurv(),
+        sstr: params.sstr as f32,
+        scl: params.scl as f32,
+        diffscl: params.diffscl,
+        passes: params.passes,
+        combine: params.combine,
+        interlaced: params.interlaced,
+        tff: params.tff,
+        sw_c: params.sw_c,
+        sh_c: params.sh_c,
+        blurv_c: params.blurv_c,
+        sstr_c: params.sstr_c.map(|sstr| sstr as f32),
+        scl_c: params.scl_c.map(|scl| scl as f32),
+    })
+}
+
+pub(crate) fn check_fused_format(clip: &Node) -> Result<(), Error> {
+    let format = match clip.info().format {
+        Constant(format) => format,
+        _ => {
+            return Err(Error::unsupported_format(
+                "fused processing requires a constant format",
+            ))
+        }
+    };
+    if format.sample_type() != SampleType::Integer {
+        return Err(Error::unsupported_format(
+            "fused processing only supports integer formats",
+        ));
+    }
+    Ok(())
+}
+
+/// The luma rows each band of `mpeg2stinx_tiled` outputs at a time, so
+/// that its scratch planes stay small next to HD frames.
+const TILE_ROWS: usize = 256;
+
+fn plane_refs<'a, T: Pixel>(frame: &'a FrameRef) -> Vec<PlaneRef<'a, T>> {
+    (0..frame.format().plane_count())
+        .map(|plane| plane_ref::<T>(frame, plane))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_fused<'core, T: Pixel>(
+    core: CoreRef<'core>,
+    prev: &FrameRef<'core>,
+    src: &FrameRef<'core>,
+    next: &FrameRef<'core>,
+    params: &mpeg2stinx_core::Params,
+    planes: Planes,
+    sstr: f32,
+    scl: f32,
+) -> FrameRefMut<'core> {
+    let params = mpeg2stinx_core::Params {
+        sstr,
+        scl,
+        ..*params
+    };
+    let mut dst = FrameRefMut::copy_of(core, src);
+    // Each band is written straight to the output frame
+    mpeg2stinx_tiled(
+        &plane_refs::<T>(prev),
+        &plane_refs::<T>(src),
+        &plane_refs::<T>(next),
+        src.format().bitsPerSample as u32,
+        &params,
+        TILE_ROWS,
+        |plane, first_row, rows| {
+            if !planes.contains(plane) {
+                return;
+            }
+            let mut dst = plane_mut::<T>(&mut dst, plane);
+            for (y, row) in rows.rows().enumerate() {
+                dst.row_mut(first_row + y).copy_from_slice(row);
+            }
+        },
+    );
+    dst
+}
+
+/// Runs the whole filter on `src` in a single pass, without any internal nodes.
+///
+/// `prev` and `next` are only read when `params` enables temporal limiting.
+pub(crate) fn process_fused<'core>(
+    core: CoreRef<'core>,
+    prev: &FrameRef<'core>,
+    src: &FrameRef<'core>,
+    next: &FrameRef<'core>,
+    params: &mpeg2stinx_core::Params,
+    planes: Planes,
+    overrides: &FrameOverrides,
+) -> Result<FrameRef<'core>, Error> {
+    let (sstr, scl) = overrides.sharpening(params.sstr, params.scl);
+    let mut output = match src.format().bytesPerSample {
+        1 => run_fused::<u8>(core, prev, src, next, params, planes, sstr, scl),
+        2 => run_fused::<u16>(core, prev, src, next, params, planes, sstr, scl),
+        4 => run_fused::<u32>(core, prev, src, next, params, planes, sstr, scl),
+        _ => unreachable!(),
+    };
+    set_output_props(
+        &mut output,
+        true,
+        output_field_based(params.interlaced, params.tff),
+        overrides.vfm,
+    )?;
+    Ok(FrameRef::from(output))
+}
@@ -1,28 +1,52 @@
 #![warn(clippy::all)]
 
+#[macro_use]
+mod adapter;
+mod average;
+mod cue;
 mod deint;
+mod dependencies;
+mod error;
 mod filters;
+mod fused;
 mod lutxy;
+mod mask;
+mod morphology;
+mod nodes;
+mod planes;
+mod props;
 mod repair;
+mod requests;
+mod stage;
 mod third_party;
 mod util;
+#[cfg(feature = "api-v4")]
+mod v4;
 mod vsfunc;
+mod zones;
 
 use self::deint::*;
+use self::dependencies::check_dependencies;
+use self::error::Error;
 use self::filters::*;
+use self::fused::*;
 use self::lutxy::*;
+use self::mask::*;
+use self::morphology::shape_from_args;
+use self::planes::Planes;
+use self::props::*;
 use self::repair::*;
+use self::requests::*;
+use self::stage::*;
 use self::third_party::*;
 use self::util::*;
 use self::vsfunc::*;
-use failure::ensure;
-use failure::format_err;
-use failure::Error;
-use std::convert::TryFrom;
+use self::zones::*;
 use vapoursynth::core::CoreRef;
 use vapoursynth::export_vapoursynth_plugin;
 use vapoursynth::format::FormatID;
 use vapoursynth::make_filter_function;
+use vapoursynth::map::ValueIter;
 use vapoursynth::plugins::*;
 use vapoursynth::prelude::*;
 use vapoursynth::video_info::VideoInfo;
@@ -31,11 +55,127 @@ const MPEG2STINX_NAMESPACE: &str = "com.soichiro.mpeg2stinx";
 
 struct Mpeg2Stinx<'core> {
     src: Node<'core>,
-    nuked: Node<'core>,
-    nuked_blurred: Option<Node<'core>>,
-    contra: bool,
-    sstr: f32,
-    scl: f32,
+    stages: Vec<Stage<'core>>,
+    // `zones[i]` is processed by `stages[i + 1]`, everything else by `stages[0]`
+    zones: Vec<Zone>,
+    /// The mic threshold of `vfm`, `None` if it is disabled.
+    vfm_mic: Option<i64>,
+    /// The protection mask, applied to the output of every stage.
+    mask: Option<Node<'core>>,
+    mask_mode: MaskMode,
+}
+
+impl<'core> Mpeg2Stinx<'core> {
+    fn stage(&self, n: usize) -> &Stage<'core> {
+        match self.zones.iter().position(|zone| zone.contains(n)) {
+            Some(i) => &self.stages[i + 1],
+            None => &self.stages[0],
+        }
+    }
+
+    /// Frame `n` of the clip processed by `stage`, i.e. of the source or,
+    /// for interlaced frames, of its fields.
+    fn process_graph(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        stage: &GraphStage<'core>,
+        n: usize,
+        overrides: &FrameOverrides,
+    ) -> Result<FrameRef<'core>, Error> {
+        let nuked = stage
+            .nuked
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: "Mpeg2Stinx",
+                input: "the nuked clip",
+                frame: n,
+            })?;
+
+        if !stage.contra {
+            return Ok(nuked);
+        }
+
+        let src = stage
+            .bobbed
+            .as_ref()
+            .unwrap_or(&self.src)
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: "Mpeg2Stinx",
+                input: if stage.bobbed.is_some() {
+                    "the bobbed source"
+                } else {
+                    "the source"
+                },
+                frame: n,
+            })?;
+        let nuked_blurred = stage
+            .nuked_blurred
+            .as_ref()
+            .unwrap()
+            .get_frame_filter(context, n)
+            .ok_or(Error::FrameFetch {
+                filter: "Mpeg2Stinx",
+                input: "the nuked blurred clip",
+                frame: n,
+            })?;
+        let (sstr, scl) = overrides.sharpening(stage.sstr, stage.scl);
+        let chroma = (stage.sstr_c.unwrap_or(sstr), stage.scl_c.unwrap_or(scl));
+
+        contrasharpen(
+            core,
+            &src,
+            &nuked,
+            &nuked_blurred,
+            (sstr, scl),
+            chroma,
+            stage.planes,
+        )
+    }
+
+    /// The previous and next frame numbers, clamped to the clip.
+    fn neighbours(&self, n: usize) -> (usize, usize) {
+        let last = self.src.info().num_frames.saturating_sub(1);
+        (n.saturating_sub(1), (n + 1).min(last))
+    }
+}
+
+impl<'core> DeclaredRequests<'core> for Mpeg2Stinx<'core> {
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn inputs(&self, n: usize) -> Vec<(&Node<'core>, RequestPattern)> {
+        let mut inputs = match self.stage(n) {
+            Stage::Graph(stage) => {
+                // Interlaced frames are woven from the frames of their fields
+                let pattern = if stage.bobbed.is_some() {
+                    RequestPattern::DoubleRate
+                } else {
+                    RequestPattern::StrictSpatial
+                };
+                let mut inputs = vec![
+                    (&self.src, RequestPattern::StrictSpatial),
+                    (&stage.nuked, pattern),
+                ];
+                if let Some(ref nuked_blurred) = stage.nuked_blurred {
+                    inputs.push((nuked_blurred, pattern));
+                    if let Some(ref bobbed) = stage.bobbed {
+                        inputs.push((bobbed, pattern));
+                    }
+                }
+                inputs
+            }
+            // Temporal limiting compares each frame to its neighbours
+            Stage::Fused(params, _) if params.diffscl.is_some() => {
+                vec![(&self.src, RequestPattern::Temporal(1))]
+            }
+            Stage::Fused(..) => vec![(&self.src, RequestPattern::StrictSpatial)],
+        };
+        if let Some(ref mask) = self.mask {
+            inputs.push((mask, RequestPattern::StrictSpatial));
+        }
+        inputs
+    }
 }
 
 impl<'core> Filter<'core> for Mpeg2Stinx<'core> {
@@ -49,12 +189,8 @@ impl<'core> Filter<'core> for Mpeg2Stinx<'core> {
         _core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<Option<FrameRef<'core>>, Error> {
-        self.src.request_frame_filter(context, n);
-        self.nuked.request_frame_filter(context, n);
-        if let Some(ref nuked_blurred) = self.nuked_blurred {
-            nuked_blurred.request_frame_filter(context, n);
-        }
+    ) -> Result<Option<FrameRef<'core>>, failure::Error> {
+        self.request_inputs(context, n);
         Ok(None)
     }
 
@@ -64,42 +200,85 @@ impl<'core> Filter<'core> for Mpeg2Stinx<'core> {
         core: CoreRef<'core>,
         context: FrameContext,
         n: usize,
-    ) -> Result<FrameRef<'core>, Error> {
-        let nuked = self
-            .nuked
-            .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Mpeg2Stinx: Couldn't get the nuked frame"))?;
-
-        if !self.contra {
-            return Ok(nuked);
-        }
-
+    ) -> Result<FrameRef<'core>, failure::Error> {
         let src = self
             .src
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Mpeg2Stinx: Couldn't get the source frame"))?;
-        let nuked_blurred = self
-            .nuked_blurred
-            .as_ref()
-            .unwrap()
-            .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Mpeg2Stinx: Couldn't get the nuked blurred frame"))?;
-
-        let sharp = lutxy_sharp(core, &nuked, &nuked_blurred, self.sstr)
-            .map_err(|e| e.context("Mpeg2Stinx: "))?;
-
-        if self.scl == 0.0 {
-            return Ok(
-                median3(core, &nuked, &sharp, &src, true).map_err(|e| e.context("Mpeg2Stinx: "))?
-            );
+            .ok_or(Error::FrameFetch {
+                filter: "Mpeg2Stinx",
+                input: "the source",
+                frame: n,
+            })?;
+        let overrides = FrameOverrides::from_frame(&src, self.vfm_mic);
+        if overrides.pass_through() {
+            let field_based = self.stage(n).field_based();
+            return Ok(with_source_props(
+                core,
+                &src,
+                None,
+                Planes::ALL,
+                field_based,
+                overrides.vfm,
+            )?);
         }
 
-        let nukedd = make_diff(core, &src, &nuked).map_err(|e| e.context("Mpeg2Stinx: "))?;
-        let sharpd = lutxy_sharpd(core, &nuked, &nuked_blurred, self.sstr)
-            .map_err(|e| e.context("Mpeg2Stinx: "))?;
-        let limd =
-            lutxy_limd(core, &sharpd, &nukedd, self.scl).map_err(|e| e.context("Mpeg2Stinx: "))?;
-        Ok(add_diff(core, &nuked, &limd).map_err(|e| e.context("Mpeg2Stinx: "))?)
+        let (output, planes) = match self.stage(n) {
+            Stage::Graph(stage) => {
+                let output = if stage.bobbed.is_some() {
+                    let first = self.process_graph(core, context, stage, 2 * n, &overrides)?;
+                    let second = self.process_graph(core, context, stage, 2 * n + 1, &overrides)?;
+                    weave_frames(core, &first, &second, stage.tff)
+                } else {
+                    self.process_graph(core, context, stage, n, &overrides)?
+                };
+                let output = with_source_props(
+                    core,
+                    &src,
+                    Some(&output),
+                    stage.planes,
+                    stage.field_based(),
+                    overrides.vfm,
+                )?;
+                (output, stage.planes)
+            }
+            Stage::Fused(params, planes) => {
+                let (prev, next) = if params.diffscl.is_some() {
+                    let (prev, next) = self.neighbours(n);
+                    let get = |frame| {
+                        self.src
+                            .get_frame_filter(context, frame)
+                            .ok_or(Error::FrameFetch {
+                                filter: "Mpeg2Stinx",
+                                input: "the source",
+                                frame,
+                            })
+                    };
+                    (get(prev)?, get(next)?)
+                } else {
+                    (src.clone(), src.clone())
+                };
+                let output = process_fused(core, &prev, &src, &next, params, *planes, &overrides)?;
+                (output, *planes)
+            }
+        };
+
+        let mask = match self.mask {
+            Some(ref mask) => mask,
+            None => return Ok(output),
+        };
+        let mask = mask.get_frame_filter(context, n).ok_or(Error::FrameFetch {
+            filter: "Mpeg2Stinx",
+            input: "the mask",
+            frame: n,
+        })?;
+        Ok(apply_mask(
+            core,
+            &src,
+            &output,
+            &mask,
+            self.mask_mode,
+            planes,
+        )?)
     }
 }
 
@@ -121,113 +300,59 @@ make_filter_function! {
         dither: Option<i64>,
         order: Option<i64>,
         diffscl: Option<f64>,
-    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
-        let mode = match mode {
-            Some(mode) => FilterMode::try_from(mode)?,
-            None => FilterMode::default()
-        };
-        let sw = match sw {
-            Some(sw) => {
-                ensure!(
-                    sw >= 0,
-                    "Mpeg2Stinx: sw and sh must both be non-negative integers"
-                );
-                sw as u32
-            }
-            None => 1
-        };
-        let sh = match sh {
-            Some(sh) => {
-                ensure!(
-                    sh >= 0,
-                    "Mpeg2Stinx: sw and sh must both be non-negative integers"
-                );
-                sh as u32
-            }
-            None => 1
-        };
-        let contra = contra.map(|contra| contra != 0).unwrap_or(true);
-        let blurv = blurv.unwrap_or_else(|| if contra { 0.9 } else { 0.0 });
-        let sstr = sstr.unwrap_or(2.0);
-        let scl = scl.unwrap_or(0.25);
-        let dither = dither.map(|dither| dither != 0).unwrap_or(false);
-        let order = order.unwrap_or(-1);
-        ensure!(
-            order >= -1 && order <= 1,
-            "Mpeg2Stinx: order must be -1, 0 or 1"
-        );
-        if let Some(diffscl) = diffscl {
-            ensure!(
-                diffscl >= 0.0,
-                "Mpeg2Stinx: diffscl must be a non-negative number"
-            );
+        zones: Option<&[u8]>,
+        zones_file: Option<&[u8]>,
+        fused: Option<i64>,
+        planes: Option<ValueIter<'_, 'core, i64>>,
+        sw_c: Option<i64>,
+        sh_c: Option<i64>,
+        blurv_c: Option<f64>,
+        sstr_c: Option<f64>,
+        scl_c: Option<f64>,
+        shape: Option<i64>,
+        coordinates: Option<ValueIter<'_, 'core, i64>>,
+        passes: Option<i64>,
+        combine: Option<i64>,
+        tff: Option<i64>,
+        interlaced: Option<i64>,
+        vfm: Option<i64>,
+        vfm_mic: Option<i64>,
+        mask: Option<Node<'core>>,
+        mask_blend: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        let mut params = Params::from_args(
+            mode, sw, sh, contra, blurv, sstr, scl, dither, order, diffscl, sw_c, sh_c, blurv_c,
+            sstr_c, scl_c, passes, combine, tff, interlaced,
+        )?;
+        params.planes = Planes::from_arg(planes)?;
+        params.shape = shape_from_args(shape, coordinates.map(Iterator::collect))?;
+        let zones = read_zones(zones, zones_file, &params, clip.info().num_frames)?;
+        let vfm_mic = vfm_from_args(vfm, vfm_mic)?;
+        let mask_mode = mask_mode_from_arg(mask_blend);
+        if let Some(ref mask) = mask {
+            check_mask(&clip, mask, mask_mode)?;
         }
 
-        let a = cross_field_repair2(
-            core,
-            api,
-            &clip,
-            Some(
-                &deint(core, api, &clip, mode, order)
-                    .map_err(|e| e.context("Mpeg2Stinx: "))?,
-            ),
-            sw,
-            sh,
-            true,
-        )
-        .map_err(|e| e.context("Mpeg2Stinx: "))?;
-        let a = if let Some(diffscl) = diffscl {
-            temp_limit(core, api, &clip, &a, &clip, diffscl).map_err(|e| e.context("Mpeg2Stinx: "))?
-        } else {
-            a
-        };
-
-        let b = cross_field_repair2(
-            core,
-            api,
-            &a,
-            Some(
-                &deint(core, api, &a, mode, order)
-                    .map_err(|e| e.context("Mpeg2Stinx: "))?,
-            ),
-            sw,
-            sh,
-            true,
-        )
-        .map_err(|e| e.context("Mpeg2Stinx: "))?;
-        let b = if let Some(diffscl) = diffscl {
-            temp_limit(core, api, &a, &b, &clip, diffscl).map_err(|e| e.context("Mpeg2Stinx: "))?
-        } else {
-            b
-        };
+        let fused = fused.unwrap_or(0) != 0;
 
-        let average = average(core, api, &a, &b, dither).map_err(|e| e.context("Mpeg2Stinx: "))?;
+        if fused {
+            check_fused_format(&clip)?;
+        }
+        check_dependencies(core, &params, &zones, subsampling(&clip)?, fused)?;
 
-        let nuked = if blurv > 0.0 {
-            crate::util::blur_v(core, api, &average, blurv)
-                .map_err(|e| e.context("Mpeg2Stinx: "))?
-        } else {
-            average
-        };
-        let nuked_blurred = if contra {
-            Some(crate::util::blur_v(
-                core,
-                api,
-                &crate::util::blur_v(core, api, &nuked, 1.0).map_err(|e| e.context("Mpeg2Stinx: "))?,
-                1.0,
-            )
-            .map_err(|e| e.context("Mpeg2Stinx: "))?)
-        } else {
-            None
-        };
+        let mut stages = Vec::with_capacity(zones.len() + 1);
+        stages.push(build_stage(core, api, &clip, &params, fused)?);
+        for zone in &zones {
+            stages.push(build_stage(core, api, &clip, &zone.params, fused)?);
+        }
 
         Ok(Some(Box::new(Mpeg2Stinx {
             src: clip,
-            nuked,
-            nuked_blurred,
-            contra,
-            sstr: sstr as f32,
-            scl: scl as f32,
+            stages,
+            zones,
+            vfm_mic,
+            mask,
+            mask_mode,
         })))
     }
 }
@@ -240,10 +365,12 @@ make_filter_function! {
         core: CoreRef<'core>,
         clip1: Node<'core>,
         clip2: Node<'core>,
-    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
         Ok(Some(Box::new(Min {
             clip1,
             clip2,
+            planes: Planes::from_arg(planes)?,
         })))
     }
 }
@@ -256,10 +383,12 @@ make_filter_function! {
         core: CoreRef<'core>,
         clip1: Node<'core>,
         clip2: Node<'core>,
-    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
         Ok(Some(Box::new(Max {
             clip1,
             clip2,
+            planes: Planes::from_arg(planes)?,
         })))
     }
 }
@@ -274,12 +403,18 @@ make_filter_function! {
         clip2: Node<'core>,
         clip3: Node<'core>,
         process_chroma: Option<i64>,
-    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        // `planes` supersedes `process_chroma`, which is kept for compatibility
+        let planes = match planes {
+            Some(planes) => Planes::from_arg(Some(planes))?,
+            None => Planes::from_process_chroma(process_chroma.unwrap_or(0) > 0),
+        };
         Ok(Some(Box::new(Median3 {
             clip1,
             clip2,
             clip3,
-            process_chroma: process_chroma.unwrap_or(0) > 0
+            planes,
         })))
     }
 }
@@ -292,14 +427,114 @@ make_filter_function! {
         core: CoreRef<'core>,
         clip1: Node<'core>,
         clip2: Node<'core>,
-    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
         Ok(Some(Box::new(LutXYDiff {
             clip1,
             clip2,
+            planes: Planes::from_arg(planes)?,
         })))
     }
 }
 
+make_filter_function! {
+    AverageFramesFunction, "AverageFrames"
+
+    fn create_average_frames<'core>(
+        api: API,
+        core: CoreRef<'core>,
+        clips: ValueIter<'_, 'core, Node<'core>>,
+        weights: Option<ValueIter<'_, 'core, f64>>,
+        scale: Option<f64>,
+        rounding: Option<i64>,
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        Ok(Some(Box::new(AverageFrames::new(
+            clips.collect(),
+            weights.map(Iterator::collect),
+            scale,
+            rounding,
+            Planes::from_arg(planes)?,
+        )?)))
+    }
+}
+
+make_filter_function! {
+    CueRepairFunction, "CUERepair"
+
+    fn create_cue_repair<'core>(
+        api: API,
+        core: CoreRef<'core>,
+        clip: Node<'core>,
+        mode: Option<i64>,
+        threshold: Option<f64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        Ok(Some(Box::new(CueRepair::new(clip, mode, threshold)?)))
+    }
+}
+
+make_filter_function! {
+    ExpandFunction, "Expand"
+
+    fn create_expand<'core>(
+        api: API,
+        core: CoreRef<'core>,
+        clip: Node<'core>,
+        sw: Option<i64>,
+        sh: Option<i64>,
+        shape: Option<i64>,
+        planes: Option<ValueIter<'_, 'core, i64>>,
+        coordinates: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        Ok(Some(Box::new(Expand::new(
+            clip,
+            sw,
+            sh,
+            shape_from_args(shape, coordinates.map(Iterator::collect))?,
+            Planes::from_arg(planes)?,
+        )?)))
+    }
+}
+
+make_filter_function! {
+    InpandFunction, "Inpand"
+
+    fn create_inpand<'core>(
+        api: API,
+        core: CoreRef<'core>,
+        clip: Node<'core>,
+        sw: Option<i64>,
+        sh: Option<i64>,
+        shape: Option<i64>,
+        planes: Option<ValueIter<'_, 'core, i64>>,
+        coordinates: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        Ok(Some(Box::new(Inpand::new(
+            clip,
+            sw,
+            sh,
+            shape_from_args(shape, coordinates.map(Iterator::collect))?,
+            Planes::from_arg(planes)?,
+        )?)))
+    }
+}
+
+make_filter_function! {
+    MedianFramesFunction, "MedianFrames"
+
+    fn create_median_frames<'core>(
+        api: API,
+        core: CoreRef<'core>,
+        clips: ValueIter<'_, 'core, Node<'core>>,
+        planes: Option<ValueIter<'_, 'core, i64>>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, failure::Error> {
+        Ok(Some(Box::new(MedianFrames::new(
+            clips.collect(),
+            Planes::from_arg(planes)?,
+        )?)))
+    }
+}
+
 export_vapoursynth_plugin! {
     Metadata {
         identifier: MPEG2STINX_NAMESPACE,
@@ -312,6 +547,11 @@ export_vapoursynth_plugin! {
         MinFunction::new(),
         MaxFunction::new(),
         Median3Function::new(),
-        LutXYDiffFunction::new()
+        LutXYDiffFunction::new(),
+        AverageFramesFunction::new(),
+        MedianFramesFunction::new(),
+        CueRepairFunction::new(),
+        ExpandFunction::new(),
+        InpandFunction::new()
     ]
 }
@@ -0,0 +1,118 @@
+use crate::adapter::{plane_mut, plane_ref};
+use crate::error::Error;
+use crate::planes::Planes;
+use mpeg2stinx_core::{mask_merge, subsample_mask, MaskMode, Pixel};
+use vapoursynth::core::CoreRef;
+use vapoursynth::format::{ColorFamily, SampleType};
+use vapoursynth::frame::Frame;
+use vapoursynth::prelude::*;
+
+/// Parses the `mask_blend` argument.
+pub(crate) fn mask_mode_from_arg(mask_blend: Option<i64>) -> MaskMode {
+    if mask_blend.unwrap_or(0) != 0 {
+        MaskMode::Blend
+    } else {
+        MaskMode::Replace
+    }
+}
+
+/// Checks that `mask` is an 8 or 16-bit Gray clip of the same size and
+/// length as `clip`, and that `mode` can be used with the format of `clip`.
+pub(crate) fn check_mask(clip: &Node, mask: &Node, mode: MaskMode) -> Result<(), Error> {
+    let info = mask.info();
+    let is_gray = match info.format {
+        Constant(format) => {
+            format.color_family() == ColorFamily::Gray
+                && format.sample_type() == SampleType::Integer
+                && (format.bits_per_sample() == 8 || format.bits_per_sample() == 16)
+        }
+        _ => false,
+    };
+    if !is_gray {
+        return Err(Error::invalid_argument("mask", "an 8 or 16-bit Gray clip"));
+    }
+    match info.resolution {
+        Constant(_) if info.resolution == clip.info().resolution => (),
+        _ => return Err(Error::invalid_argument("mask", "of the same size as clip")),
+    }
+    if info.num_frames != clip.info().num_frames {
+        return Err(Error::invalid_argument(
+            "mask",
+            "as many frames long as clip",
+        ));
+    }
+    // `mask_merge` blends integers, which the bits of float samples aren't
+    let is_float = match clip.info().format {
+        Constant(format) => format.sample_type() == SampleType::Float,
+        _ => false,
+    };
+    if mode == MaskMode::Blend && is_float {
+        return Err(Error::invalid_argument("mask_blend", "0 with a float clip"));
+    }
+    Ok(())
+}
+
+fn merge_planes<T: Pixel, M: Pixel>(
+    src: &Frame,
+    processed: &Frame,
+    mask: &Frame,
+    mode: MaskMode,
+    planes: Planes,
+    dst: &mut Frame,
+) {
+    let format = src.format();
+    let mask_bits = mask.format().bits_per_sample() as u32;
+    let luma = plane_ref::<M>(mask, 0);
+    let (sub_w, sub_h) = (
+        u32::from(format.sub_sampling_w()),
+        u32::from(format.sub_sampling_h()),
+    );
+    let chroma = if (sub_w, sub_h) != (0, 0) {
+        Some(subsample_mask(luma, sub_w, sub_h))
+    } else {
+        None
+    };
+
+    for plane in planes.filter(0..format.plane_count()) {
+        let mask = match chroma {
+            Some(ref chroma) if plane > 0 => chroma.as_ref(),
+            _ => luma,
+        };
+        mask_merge(
+            plane_ref::<T>(processed, plane),
+            plane_ref::<T>(src, plane),
+            mask,
+            plane_mut::<T>(dst, plane),
+            mask_bits,
+            mode,
+        );
+    }
+}
+
+// Returns `src` again where the `mask` argument protects it, see `mask_merge`.
+// Only `planes` are merged, as the others are copies of `src` already
+pub(crate) fn apply_mask<'core>(
+    core: CoreRef<'core>,
+    src: &FrameRef<'core>,
+    processed: &FrameRef<'core>,
+    mask: &FrameRef<'core>,
+    mode: MaskMode,
+    planes: Planes,
+) -> Result<FrameRef<'core>, Error> {
+    let mut merged = FrameRefMut::copy_of(core, &*processed);
+
+    // The formats were checked on creation
+    match (
+        src.format().bytes_per_sample(),
+        mask.format().bytes_per_sample(),
+    ) {
+        (1, 1) => merge_planes::<u8, u8>(src, processed, mask, mode, planes, &mut merged),
+        (1, 2) => merge_planes::<u8, u16>(src, processed, mask, mode, planes, &mut merged),
+        (2, 1) => merge_planes::<u16, u8>(src, processed, mask, mode, planes, &mut merged),
+        (2, 2) => merge_planes::<u16, u16>(src, processed, mask, mode, planes, &mut merged),
+        (4, 1) => merge_planes::<u32, u8>(src, processed, mask, mode, planes, &mut merged),
+        (4, 2) => merge_planes::<u32, u16>(src, processed, mask, mode, planes, &mut merged),
+        _ => unreachable!(),
+    }
+    Ok(FrameRef::from(merged))
+}
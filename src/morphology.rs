@@ -0,0 +1,131 @@
+use crate::error::Error;
+use crate::planes::Planes;
+use mpeg2stinx_core::{Mask, Shape};
+use vapoursynth::core::CoreRef;
+use vapoursynth::prelude::*;
+
+/// Parses a `shape` of 0 to 4. Masks (5) need `coordinates`, see `shape_from_args`.
+pub(crate) fn shape_from_arg(shape: i64) -> Result<Shape, Error> {
+    match shape {
+        0 => Ok(Shape::Square),
+        1 => Ok(Shape::Diamond),
+        2 => Ok(Shape::Ellipse),
+        3 => Ok(Shape::Plus),
+        4 => Ok(Shape::Field),
+        _ => Err(Error::invalid_argument(
+            "shape",
+            "0 to 4, or 5 along with coordinates",
+        )),
+    }
+}
+
+/// Parses the `shape` and `coordinates` arguments, where `coordinates`
+/// holds the offsets of a mask as `dx, dy` pairs and implies `shape=5`.
+pub(crate) fn shape_from_args(
+    shape: Option<i64>,
+    coordinates: Option<Vec<i64>>,
+) -> Result<Shape, Error> {
+    let coordinates = match coordinates {
+        Some(coordinates) => coordinates,
+        None => return shape_from_arg(shape.unwrap_or(0)),
+    };
+    if matches!(shape, Some(shape) if shape != 5) {
+        return Err(Error::invalid_argument(
+            "shape",
+            "5 or unset when coordinates are given",
+        ));
+    }
+    let limit = i64::from(Mask::MAX_RADIUS);
+    if coordinates.len() % 2 != 0 || coordinates.iter().any(|c| c.abs() > limit) {
+        return Err(Error::invalid_argument(
+            "coordinates",
+            format!("dx, dy pairs from -{} to {}", limit, limit),
+        ));
+    }
+    let offsets: Vec<(i32, i32)> = coordinates
+        .chunks(2)
+        .map(|pair| (pair[0] as i32, pair[1] as i32))
+        .collect();
+    // The range was checked above
+    Ok(Shape::Mask(Mask::new(&offsets).unwrap()))
+}
+
+/// The `shape` and `coordinates` arguments that `shape_from_args` parses to `shape`.
+pub(crate) fn shape_to_args(shape: Shape) -> (i64, Option<Vec<i64>>) {
+    match shape {
+        Shape::Square => (0, None),
+        Shape::Diamond => (1, None),
+        Shape::Ellipse => (2, None),
+        Shape::Plus => (3, None),
+        Shape::Field => (4, None),
+        Shape::Mask(mask) => (
+            5,
+            Some(
+                mask.offsets()
+                    .into_iter()
+                    .flat_map(|(dx, dy)| vec![i64::from(dx), i64::from(dy)])
+                    .collect(),
+            ),
+        ),
+    }
+}
+
+/// Checks a radius argument of `Expand` and `Inpand`.
+pub(crate) fn radius_from_arg(name: &'static str, radius: i64) -> Result<u32, Error> {
+    if radius < 0 {
+        return Err(Error::invalid_argument(name, "a non-negative integer"));
+    }
+    Ok(radius as u32)
+}
+
+// Equivalent AVS:
+// `mt_expand(mode=mt_rectangle(sw, sh))` for the default shape,
+// `mt_expand(mode="0 0 dx dy ...")` for masks
+pub(crate) fn expand<'core>(
+    core: CoreRef<'core>,
+    clip: &FrameRef<'core>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+    planes: Planes,
+) -> Result<FrameRef<'core>, Error> {
+    let mut filtered = FrameRefMut::copy_of(core, &*clip);
+
+    for_each_plane!(
+        planes.filter(0..clip.format().plane_count()),
+        clip.format().bytesPerSample,
+        ref [x = clip],
+        mut [dst = filtered],
+        {
+            let mut dst = dst;
+            dst.copy_from(mpeg2stinx_core::expand_shape(x, sw, sh, shape).as_ref())
+        }
+    );
+    Ok(FrameRef::from(filtered))
+}
+
+// Equivalent AVS:
+// `mt_inpand(mode=mt_rectangle(sw, sh))` for the default shape,
+// `mt_inpand(mode="0 0 dx dy ...")` for masks
+pub(crate) fn inpand<'core>(
+    core: CoreRef<'core>,
+    clip: &FrameRef<'core>,
+    sw: u32,
+    sh: u32,
+    shape: Shape,
+    planes: Planes,
+) -> Result<FrameRef<'core>, Error> {
+    let mut filtered = FrameRefMut::copy_of(core, &*clip);
+
+    for_each_plane!(
+        planes.filter(0..clip.format().plane_count()),
+        clip.format().bytesPerSample,
+        ref [x = clip],
+        mut [dst = filtered],
+        {
+            let mut dst = dst;
+            dst.copy_from(mpeg2stinx_core::inpand_shape(x, sw, sh, shape).as_ref())
+        }
+    );
+    Ok(FrameRef::from(filtered))
+}
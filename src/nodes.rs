@@ -0,0 +1,40 @@
+use crate::error::Error;
+use crate::requests::DeclaredRequests;
+use crate::vsfunc::{get_plugin, invoke};
+use crate::MPEG2STINX_NAMESPACE;
+use vapoursynth::api::API;
+use vapoursynth::core::CoreRef;
+use vapoursynth::map::OwnedMap;
+use vapoursynth::node::Node;
+use vapoursynth::plugins::Filter;
+
+/// One of our own filters, used as a node of the internal graph.
+///
+/// This only makes creating the internal nodes typed and panic free: the
+/// function name and its arguments are tied to the filter struct instead of
+/// being spelled out at every call site, and a failed lookup of our plugin is
+/// an error. The nodes are still made by invoking our own filter functions,
+/// which serializes the arguments to a map that the function parses again.
+/// Registering the filter directly with `createFilter` would need the raw
+/// API 3.x core and API pointers, which vapoursynth-rs keeps private, and is
+/// left for when it exposes them.
+pub(crate) trait InternalFilter<'core>: Filter<'core> + DeclaredRequests<'core> {
+    /// The name the filter is registered under in our namespace.
+    const NAME: &'static str;
+
+    /// Sets the arguments of the filter function that create this filter.
+    fn set_args(&self, args: &mut OwnedMap<'core>) -> Result<(), Error>;
+}
+
+/// Creates a node running `filter`.
+pub(crate) fn create_node<'core, F: InternalFilter<'core>>(
+    core: CoreRef<'core>,
+    api: API,
+    filter: F,
+) -> Result<Node<'core>, Error> {
+    let plugin = get_plugin(core, MPEG2STINX_NAMESPACE, "mpeg2stinx")?;
+
+    let mut args = OwnedMap::new(api);
+    filter.set_args(&mut args)?;
+    invoke(&plugin, F::NAME, &args)
+}
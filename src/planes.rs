@@ -0,0 +1,75 @@
+use crate::error::Error;
+use std::ops::Range;
+
+/// The planes a filter processes. All other planes are copied from its
+/// (first) input, so they stay bit exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Planes([bool; 3]);
+
+impl Planes {
+    pub(crate) const ALL: Planes = Planes([true; 3]);
+    pub(crate) const LUMA: Planes = Planes([true, false, false]);
+    pub(crate) const CHROMA: Planes = Planes([false, true, true]);
+
+    /// Parses a `planes` argument, processing all planes if it is not given.
+    pub(crate) fn from_arg<I: IntoIterator<Item = i64>>(planes: Option<I>) -> Result<Self, Error> {
+        let planes = match planes {
+            Some(planes) => planes,
+            None => return Ok(Planes::ALL),
+        };
+        let mut selected = [false; 3];
+        for plane in planes {
+            match selected.get_mut(plane as usize) {
+                Some(selected) if plane >= 0 => *selected = true,
+                _ => return Err(Error::invalid_argument("planes", "a list of 0, 1 and 2")),
+            }
+        }
+        if selected.iter().any(|&selected| selected) {
+            Ok(Planes(selected))
+        } else {
+            Err(Error::invalid_argument(
+                "planes",
+                "a list of at least one plane",
+            ))
+        }
+    }
+
+    /// `Planes::ALL` or `Planes::LUMA`, for the `process_chroma` argument of `Median3`.
+    pub(crate) fn from_process_chroma(process_chroma: bool) -> Self {
+        if process_chroma {
+            Planes::ALL
+        } else {
+            Planes::LUMA
+        }
+    }
+
+    pub(crate) fn contains(self, plane: usize) -> bool {
+        self.0.get(plane).cloned().unwrap_or(false)
+    }
+
+    /// Whether any chroma plane is processed.
+    pub(crate) fn chroma(self) -> bool {
+        self.0[1] || self.0[2]
+    }
+
+    /// The processed luma plane and the processed chroma planes,
+    /// each `None` if there are none.
+    pub(crate) fn split(self) -> (Option<Planes>, Option<Planes>) {
+        let luma = Planes([self.0[0], false, false]);
+        let chroma = Planes([false, self.0[1], self.0[2]]);
+        (
+            Some(luma).filter(|_| self.0[0]),
+            Some(chroma).filter(|_| self.chroma()),
+        )
+    }
+
+    /// The processed planes out of `planes`, e.g. `0..format.plane_count()`.
+    pub(crate) fn filter(self, planes: Range<usize>) -> impl Iterator<Item = usize> {
+        planes.filter(move |&plane| self.contains(plane))
+    }
+
+    /// The processed planes, as passed to the `planes` argument of other filters.
+    pub(crate) fn to_arg(self) -> Vec<i64> {
+        self.filter(0..3).map(|plane| plane as i64).collect()
+    }
+}
@@ -0,0 +1,378 @@
+use crate::error::Error;
+use crate::planes::Planes;
+use vapoursynth::core::CoreRef;
+use vapoursynth::map::Map;
+use vapoursynth::prelude::*;
+
+pub(crate) const PROP_FIELD_BASED: &str = "_FieldBased";
+pub(crate) const PROP_APPLIED: &str = "Mpeg2StinxApplied";
+pub(crate) const PROP_SSTR: &str = "Mpeg2Stinx_sstr";
+pub(crate) const PROP_SCL: &str = "Mpeg2Stinx_scl";
+pub(crate) const PROP_SKIP: &str = "Mpeg2Stinx_skip";
+pub(crate) const PROP_VFM: &str = "Mpeg2StinxVFM";
+pub(crate) const PROP_COMBED: &str = "_Combed";
+pub(crate) const PROP_VFM_MATCH: &str = "VFMMatch";
+pub(crate) const PROP_VFM_MICS: &str = "VFMMics";
+
+/// What the field matching properties set by VFM say about a frame,
+/// reported as `PROP_VFM` when the `vfm` argument is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VfmPath {
+    /// Flagged as clean, so passed through untouched.
+    Clean = 0,
+    /// Flagged as combed.
+    Combed = 1,
+    /// Flagged as clean, but the mic of the chosen match reaches `vfm_mic`.
+    LowConfidence = 2,
+    /// Not flagged either way.
+    Unknown = 3,
+}
+
+impl VfmPath {
+    /// Classifies a frame by its `_Combed` flag and the mic of its match,
+    /// processing all but clean frames with a confident match.
+    pub(crate) fn classify(combed: Option<i64>, mic: Option<i64>, vfm_mic: i64) -> Self {
+        match (combed, mic) {
+            (None, _) => VfmPath::Unknown,
+            (Some(combed), _) if combed != 0 => VfmPath::Combed,
+            // Matches VFM didn't evaluate have a mic of -1, below any threshold
+            (Some(_), Some(mic)) if mic >= vfm_mic => VfmPath::LowConfidence,
+            _ => VfmPath::Clean,
+        }
+    }
+}
+
+/// Checks the `vfm` and `vfm_mic` arguments, returning the mic threshold
+/// if the field matching properties are to be consulted at all.
+pub(crate) fn vfm_from_args(vfm: Option<i64>, vfm_mic: Option<i64>) -> Result<Option<i64>, Error> {
+    let vfm_mic = vfm_mic.unwrap_or(40);
+    if vfm_mic < 0 {
+        return Err(Error::invalid_argument("vfm_mic", "a non-negative integer"));
+    }
+    Ok(if vfm.unwrap_or(0) != 0 {
+        Some(vfm_mic)
+    } else {
+        None
+    })
+}
+
+/// Contrasharpening parameters that upstream filters may override per frame
+/// by setting frame properties on the source clip.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FrameOverrides {
+    pub sstr: Option<f32>,
+    pub scl: Option<f32>,
+    pub skip: bool,
+    /// With `vfm`, whether the frame needs processing according to VFM.
+    pub vfm: Option<VfmPath>,
+}
+
+impl FrameOverrides {
+    /// Reads the overrides of `frame`, see `from_props`.
+    pub(crate) fn from_frame(frame: &FrameRef, vfm_mic: Option<i64>) -> Self {
+        Self::from_props(&*frame.props(), vfm_mic)
+    }
+
+    /// Reads the overrides set in `props`, and the field matching properties
+    /// when `vfm_mic` is set, see `vfm_from_args`.
+    ///
+    /// Overrides of the wrong type, and sharpening overrides that aren't
+    /// finite as an `f32`, are ignored.
+    pub(crate) fn from_props<P: FrameProps + ?Sized>(props: &P, vfm_mic: Option<i64>) -> Self {
+        let number = |key| {
+            props
+                .number(key)
+                .map(|value| value as f32)
+                .filter(|value| value.is_finite())
+        };
+        FrameOverrides {
+            sstr: number(PROP_SSTR),
+            scl: number(PROP_SCL),
+            skip: props.int(PROP_SKIP).map(|skip| skip != 0).unwrap_or(false),
+            vfm: vfm_mic.map(|vfm_mic| {
+                let mic = props.int(PROP_VFM_MATCH).and_then(|matched| {
+                    props
+                        .ints(PROP_VFM_MICS)
+                        .and_then(|mics| mics.get(matched as usize).copied())
+                });
+                VfmPath::classify(props.int(PROP_COMBED), mic, vfm_mic)
+            }),
+        }
+    }
+
+    /// The contrasharpening strength and scale of the frame, overriding
+    /// `sstr` and `scl`, those of the zone covering it or of the arguments.
+    pub(crate) fn sharpening(&self, sstr: f32, scl: f32) -> (f32, f32) {
+        (self.sstr.unwrap_or(sstr), self.scl.unwrap_or(scl))
+    }
+
+    /// Whether the source frame is to be returned untouched.
+    pub(crate) fn pass_through(&self) -> bool {
+        self.skip || self.vfm == Some(VfmPath::Clean)
+    }
+}
+
+/// The frame properties of either API, as far as `FrameOverrides` reads them.
+pub(crate) trait FrameProps {
+    fn int(&self, key: &str) -> Option<i64>;
+    fn ints(&self, key: &str) -> Option<Vec<i64>>;
+    fn float(&self, key: &str) -> Option<f64>;
+
+    // Python scripts will happily set `2` where `2.0` was meant, so accept either.
+    fn number(&self, key: &str) -> Option<f64> {
+        self.float(key)
+            .or_else(|| self.int(key).map(|value| value as f64))
+    }
+}
+
+impl FrameProps for Map<'_> {
+    fn int(&self, key: &str) -> Option<i64> {
+        self.get_int(key).ok()
+    }
+
+    fn ints(&self, key: &str) -> Option<Vec<i64>> {
+        self.get_int_iter(key).ok().map(Iterator::collect)
+    }
+
+    fn float(&self, key: &str) -> Option<f64> {
+        self.get_float(key).ok()
+    }
+}
+
+/// Builds the output frame for `src`, carrying over all of its frame properties.
+///
+/// The internal filter chain separates and weaves fields and averages frames,
+/// all of which may drop or rewrite properties, so only the pixel data of
+/// `processed` is used, and only for `planes`. If `processed` is `None` the
+/// source pixels are kept.
+pub(crate) fn with_source_props<'core>(
+    core: CoreRef<'core>,
+    src: &FrameRef<'core>,
+    processed: Option<&FrameRef<'core>>,
+    planes: Planes,
+    field_based: i64,
+    vfm: Option<VfmPath>,
+) -> Result<FrameRef<'core>, Error> {
+    let mut output = FrameRefMut::copy_of(core, src);
+    if let Some(processed) = processed {
+        for plane in planes.filter(0..src.format().plane_count()) {
+            for row in 0..src.height(plane) {
+                output
+                    .data_row_mut(plane, row)
+                    .copy_from_slice(processed.data_row(plane, row));
+            }
+        }
+    }
+
+    set_output_props(&mut output, processed.is_some(), field_based, vfm)?;
+    Ok(FrameRef::from(output))
+}
+
+/// The `_FieldBased` of our output frames. Whatever the source was flagged
+/// as, the output is progressive, unless interlaced frames were processed,
+/// which stay interlaced in the field order they were processed in.
+pub(crate) fn output_field_based(interlaced: bool, tff: bool) -> i64 {
+    match (interlaced, tff) {
+        (false, _) => 0,
+        (true, false) => 1,
+        (true, true) => 2,
+    }
+}
+
+/// The properties describing our processing, set on an output frame on top
+/// of those of its source, see `output_field_based` for `field_based`.
+pub(crate) fn output_props(
+    applied: bool,
+    field_based: i64,
+    vfm: Option<VfmPath>,
+) -> Vec<(&'static str, i64)> {
+    let mut props = vec![
+        (PROP_FIELD_BASED, field_based),
+        (PROP_APPLIED, applied as i64),
+    ];
+    if let Some(vfm) = vfm {
+        props.push((PROP_VFM, vfm as i64));
+    }
+    props
+}
+
+/// Sets the properties of `output_props` on an output frame.
+pub(crate) fn set_output_props(
+    output: &mut FrameRefMut,
+    applied: bool,
+    field_based: i64,
+    vfm: Option<VfmPath>,
+) -> Result<(), Error> {
+    let mut props = output.props_mut();
+    for (key, value) in output_props(applied, field_based, vfm) {
+        props.set_int(key, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stage::Params;
+    use crate::zones::parse_zones;
+    use std::collections::HashMap;
+
+    enum Prop {
+        Int(Vec<i64>),
+        Float(f64),
+        Data(&'static str),
+    }
+
+    impl FrameProps for HashMap<&str, Prop> {
+        fn int(&self, key: &str) -> Option<i64> {
+            self.ints(key).and_then(|values| values.first().copied())
+        }
+
+        fn ints(&self, key: &str) -> Option<Vec<i64>> {
+            match self.get(key) {
+                Some(Prop::Int(values)) => Some(values.clone()),
+                _ => None,
+            }
+        }
+
+        fn float(&self, key: &str) -> Option<f64> {
+            match self.get(key) {
+                Some(&Prop::Float(value)) => Some(value),
+                _ => None,
+            }
+        }
+    }
+
+    fn overrides(props: Vec<(&'static str, Prop)>) -> FrameOverrides {
+        FrameOverrides::from_props(&props.into_iter().collect::<HashMap<_, _>>(), None)
+    }
+
+    #[test]
+    fn reads_overrides_as_floats_or_ints() {
+        let read = overrides(vec![
+            (PROP_SSTR, Prop::Float(1.5)),
+            (PROP_SCL, Prop::Int(vec![2])),
+            (PROP_SKIP, Prop::Int(vec![0])),
+        ]);
+        assert_eq!(
+            (read.sstr, read.scl, read.skip),
+            (Some(1.5), Some(2.0), false)
+        );
+        assert!(!read.pass_through());
+
+        let read = overrides(vec![(PROP_SKIP, Prop::Int(vec![-1]))]);
+        assert_eq!((read.sstr, read.scl, read.skip), (None, None, true));
+        assert!(read.pass_through());
+    }
+
+    #[test]
+    fn ignores_overrides_of_the_wrong_type() {
+        let read = overrides(vec![
+            (PROP_SSTR, Prop::Data("2.0")),
+            (PROP_SCL, Prop::Data("")),
+            (PROP_SKIP, Prop::Float(1.0)),
+        ]);
+        assert_eq!((read.sstr, read.scl, read.skip), (None, None, false));
+    }
+
+    #[test]
+    fn ignores_sharpening_overrides_that_are_not_finite() {
+        let read = overrides(vec![
+            (PROP_SSTR, Prop::Float(f64::NAN)),
+            (PROP_SCL, Prop::Float(f64::INFINITY)),
+        ]);
+        assert_eq!((read.sstr, read.scl), (None, None));
+        // Finite, but out of the range of an `f32`
+        let read = overrides(vec![
+            (PROP_SSTR, Prop::Float(-1e300)),
+            (PROP_SCL, Prop::Int(vec![i64::MAX])),
+        ]);
+        assert_eq!((read.sstr, read.scl), (None, Some(i64::MAX as f32)));
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_zones() {
+        let base = Params::default();
+        let zones = parse_zones("0 9 sstr=3.0 scl=0.5", &base, 20).unwrap();
+        let zone = (zones[0].params.sstr as f32, zones[0].params.scl as f32);
+        let outside = (base.sstr as f32, base.scl as f32);
+
+        let read = overrides(vec![(PROP_SSTR, Prop::Float(1.0))]);
+        assert_eq!(read.sharpening(zone.0, zone.1), (1.0, 0.5));
+        assert_eq!(read.sharpening(outside.0, outside.1), (1.0, outside.1));
+        let read = overrides(vec![(PROP_SCL, Prop::Int(vec![4]))]);
+        assert_eq!(read.sharpening(zone.0, zone.1), (3.0, 4.0));
+        let read = overrides(vec![]);
+        assert_eq!(read.sharpening(zone.0, zone.1), zone);
+    }
+
+    #[test]
+    fn reads_the_mic_of_the_chosen_match() {
+        let props: HashMap<_, _> = vec![
+            (PROP_COMBED, Prop::Int(vec![0])),
+            (PROP_VFM_MATCH, Prop::Int(vec![1])),
+            (PROP_VFM_MICS, Prop::Int(vec![10, 50, 20])),
+        ]
+        .into_iter()
+        .collect();
+        let vfm = |vfm_mic| FrameOverrides::from_props(&props, vfm_mic).vfm;
+        assert_eq!(vfm(None), None);
+        assert_eq!(vfm(Some(40)), Some(VfmPath::LowConfidence));
+        assert_eq!(vfm(Some(60)), Some(VfmPath::Clean));
+        assert!(FrameOverrides::from_props(&props, Some(60)).pass_through());
+    }
+
+    #[test]
+    fn output_is_progressive_unless_processed_interlaced() {
+        assert_eq!(output_field_based(false, true), 0);
+        assert_eq!(output_field_based(false, false), 0);
+        assert_eq!(output_field_based(true, false), 1);
+        assert_eq!(output_field_based(true, true), 2);
+    }
+
+    #[test]
+    fn output_props_mark_processing() {
+        assert_eq!(
+            output_props(true, 0, None),
+            vec![(PROP_FIELD_BASED, 0), (PROP_APPLIED, 1)]
+        );
+        assert_eq!(
+            output_props(false, 2, Some(VfmPath::Clean)),
+            vec![(PROP_FIELD_BASED, 2), (PROP_APPLIED, 0), (PROP_VFM, 0)]
+        );
+        assert_eq!(
+            output_props(true, 1, Some(VfmPath::Unknown)).last(),
+            Some(&(PROP_VFM, 3))
+        );
+    }
+
+    #[test]
+    fn output_props_keep_the_combed_flag_of_the_source() {
+        for &vfm in &[None, Some(VfmPath::Combed)] {
+            let props = output_props(true, 0, vfm);
+            assert!(props.iter().all(|&(key, _)| key != PROP_COMBED));
+        }
+    }
+
+    #[test]
+    fn classifies_frames_by_combed_flag_and_mic() {
+        assert_eq!(VfmPath::classify(None, Some(80), 40), VfmPath::Unknown);
+        assert_eq!(VfmPath::classify(Some(1), Some(0), 40), VfmPath::Combed);
+        assert_eq!(VfmPath::classify(Some(2), None, 40), VfmPath::Combed);
+        assert_eq!(
+            VfmPath::classify(Some(0), Some(40), 40),
+            VfmPath::LowConfidence
+        );
+        assert_eq!(VfmPath::classify(Some(0), Some(39), 40), VfmPath::Clean);
+        assert_eq!(VfmPath::classify(Some(0), Some(-1), 0), VfmPath::Clean);
+        assert_eq!(VfmPath::classify(Some(0), None, 40), VfmPath::Clean);
+    }
+
+    #[test]
+    fn vfm_mic_is_only_used_with_vfm() {
+        assert_eq!(vfm_from_args(None, Some(20)).unwrap(), None);
+        assert_eq!(vfm_from_args(Some(1), None).unwrap(), Some(40));
+        assert_eq!(vfm_from_args(Some(1), Some(0)).unwrap(), Some(0));
+        assert!(vfm_from_args(Some(1), Some(-1)).is_err());
+    }
+}
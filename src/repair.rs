@@ -1,9 +1,11 @@
 use super::*;
-use failure::{bail, Error};
+use crate::error::Error;
+use mpeg2stinx_core::Shape;
 use vapoursynth::core::CoreRef;
 use vapoursynth::prelude::*;
 use vapoursynth::video_info::Property::Constant;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn cross_field_repair2<'core>(
     core: CoreRef<'core>,
     api: API,
@@ -11,12 +13,14 @@ pub(crate) fn cross_field_repair2<'core>(
     bobbed: Option<&Node<'core>>,
     sw: u32,
     sh: u32,
-    process_chroma: bool,
+    shape: Shape,
+    planes: Planes,
+    tff: bool,
 ) -> Result<Node<'core>, Error> {
     let format = if let Constant(format) = src.info().format {
         format.id()
     } else {
-        bail!("Format not constant");
+        return Err(Error::unsupported_format("the format is not constant"));
     };
 
     let bobbed = convert(
@@ -24,36 +28,42 @@ pub(crate) fn cross_field_repair2<'core>(
         api,
         &match bobbed {
             Some(bobbed) => bobbed.clone(),
-            None => spline36_bob(core, api, src, process_chroma)?,
+            None => spline36_bob(core, api, src, planes.chroma(), tff)?,
         },
         format.into(),
     )?;
-    let (re, ro) = if sw == 1 && sh == 1 {
+    let (re, ro) = if sw == 1 && sh == 1 && shape == Shape::Square {
+        // Mode 0 leaves a plane untouched
+        let modes: Vec<i64> = if planes == Planes::ALL {
+            vec![1]
+        } else {
+            (0..3).map(|plane| planes.contains(plane) as i64).collect()
+        };
         let re = repair(
             core,
             api,
             src,
             &convert(core, api, &select_even(core, api, &bobbed)?, format.into())?,
-            1,
+            &modes,
         )?;
         let ro = repair(
             core,
             api,
             src,
             &convert(core, api, &select_odd(core, api, &bobbed)?, format.into())?,
-            1,
+            &modes,
         )?;
         (re, ro)
     } else {
-        let bobbed_ex = expand_multi(core, api, &bobbed, sw, sh, process_chroma)?;
-        let bobbed_in = inpand_multi(core, api, &bobbed, sw, sh, process_chroma)?;
+        let bobbed_ex = expand_clip(core, api, &bobbed, sw, sh, shape, planes)?;
+        let bobbed_in = inpand_clip(core, api, &bobbed, sw, sh, shape, planes)?;
         let re = median3_clip(
             core,
             api,
             src,
             &select_even(core, api, &bobbed_ex)?,
             &select_even(core, api, &bobbed_in)?,
-            process_chroma,
+            planes,
         )?;
         let ro = median3_clip(
             core,
@@ -61,12 +71,14 @@ pub(crate) fn cross_field_repair2<'core>(
             src,
             &select_odd(core, api, &bobbed_ex)?,
             &select_odd(core, api, &bobbed_in)?,
-            process_chroma,
+            planes,
         )?;
         (re, ro)
     };
+    // The even frames of `bobbed` come from the first field, so the first
+    // field is taken from `ro` and the second one from `re`
     let clip = interleave(core, api, &[&re, &ro])?;
-    let clip = separate_rows(core, api, &clip)?;
+    let clip = separate_rows(core, api, &clip, tff)?;
     let clip = select_every(core, api, &clip, 4, &[2, 1])?;
-    weave_rows(core, api, &clip)
+    weave_rows(core, api, &clip, tff)
 }
@@ -0,0 +1,70 @@
+use std::ops::RangeInclusive;
+use vapoursynth::plugins::FrameContext;
+use vapoursynth::prelude::*;
+
+/// How VapourSynth may schedule the frame requests of a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Parallelism {
+    /// Any number of frames may be processed at once, from any thread.
+    Parallel,
+    /// Frames are requested in parallel, but processed one at a time.
+    // None of our filters keep state between frames, so this is unused so far
+    #[allow(dead_code)]
+    ParallelRequests,
+}
+
+/// Which frames of an input a filter reads to produce output frame n,
+/// ordered from the least to the most general.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum RequestPattern {
+    /// Only frame n.
+    StrictSpatial,
+    /// Frames n - radius to n + radius, clamped to the clip.
+    Temporal(usize),
+    /// Frames 2n and 2n + 1 of an input at twice the frame rate.
+    DoubleRate,
+}
+
+impl RequestPattern {
+    /// The frames of an input of `num_frames` frames read for output frame `n`.
+    pub(crate) fn frames(self, n: usize, num_frames: usize) -> RangeInclusive<usize> {
+        match self {
+            RequestPattern::StrictSpatial => n..=n,
+            RequestPattern::Temporal(radius) => {
+                let last = num_frames.saturating_sub(1);
+                n.saturating_sub(radius)..=(n + radius).min(last)
+            }
+            RequestPattern::DoubleRate => {
+                let last = num_frames.saturating_sub(1);
+                (2 * n).min(last)..=(2 * n + 1).min(last)
+            }
+        }
+    }
+}
+
+/// The threading model and the input frames of an API 3.x filter.
+///
+/// vapoursynth-rs creates every filter as `fmParallel`, and the API 3.x
+/// has no way to pass request patterns on to VapourSynth, so these are
+/// used to issue the requests in `get_frame_initial`. The API v4 filters
+/// declare the same to VapourSynth, see `v4::filter::Filter4`.
+pub(crate) trait DeclaredRequests<'core> {
+    const PARALLELISM: Parallelism;
+
+    /// The inputs read for output frame `n`, with the frames needed from each.
+    fn inputs(&self, n: usize) -> Vec<(&Node<'core>, RequestPattern)>;
+
+    /// Requests every frame declared by `inputs`.
+    fn request_inputs(&self, context: FrameContext, n: usize) {
+        debug_assert_eq!(
+            Self::PARALLELISM,
+            Parallelism::Parallel,
+            "vapoursynth-rs only creates fmParallel filters"
+        );
+        for (node, pattern) in self.inputs(n) {
+            for frame in pattern.frames(n, node.info().num_frames) {
+                node.request_frame_filter(context, frame);
+            }
+        }
+    }
+}
@@ -0,0 +1,581 @@
+use super::*;
+use crate::error::Error;
+use crate::morphology::shape_from_arg;
+use mpeg2stinx_core::{subsampled_radius, Combine, PlaneParams, Shape};
+use std::convert::TryFrom;
+use vapoursynth::core::CoreRef;
+use vapoursynth::prelude::*;
+use vapoursynth::video_info::Property::Constant;
+
+/// The user-facing parameters that shape a single Mpeg2Stinx pipeline.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Params {
+    pub mode: FilterMode,
+    pub sw: u32,
+    pub sh: u32,
+    /// Set through the `shape` and `coordinates` arguments, see `shape_from_args`.
+    pub shape: Shape,
+    pub contra: bool,
+    pub blurv: Option<f64>,
+    pub sstr: f64,
+    pub scl: f64,
+    pub dither: bool,
+    pub order: i64,
+    pub diffscl: Option<f64>,
+    pub passes: u32,
+    pub combine: Combine,
+    pub interlaced: bool,
+    /// Chroma overrides of the settings above, see `Params::chroma`.
+    pub sw_c: Option<u32>,
+    pub sh_c: Option<u32>,
+    pub blurv_c: Option<f64>,
+    pub sstr_c: Option<f64>,
+    pub scl_c: Option<f64>,
+    /// Only set through the `planes` argument, zones can't override it.
+    pub planes: Planes,
+    /// The field order of the source, only set through the `tff` argument.
+    pub tff: bool,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            mode: FilterMode::default(),
+            sw: 1,
+            sh: 1,
+            shape: Shape::Square,
+            contra: true,
+            blurv: None,
+            sstr: 2.0,
+            scl: 0.25,
+            dither: false,
+            order: -1,
+            diffscl: None,
+            passes: 2,
+            combine: Combine::Average,
+            interlaced: false,
+            sw_c: None,
+            sh_c: None,
+            blurv_c: None,
+            sstr_c: None,
+            scl_c: None,
+            planes: Planes::ALL,
+            tff: true,
+        }
+    }
+}
+
+impl Params {
+    /// Builds the parameters from the optional arguments of the filter function.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_args(
+        mode: Option<i64>,
+        sw: Option<i64>,
+        sh: Option<i64>,
+        contra: Option<i64>,
+        blurv: Option<f64>,
+        sstr: Option<f64>,
+        scl: Option<f64>,
+        dither: Option<i64>,
+        order: Option<i64>,
+        diffscl: Option<f64>,
+        sw_c: Option<i64>,
+        sh_c: Option<i64>,
+        blurv_c: Option<f64>,
+        sstr_c: Option<f64>,
+        scl_c: Option<f64>,
+        passes: Option<i64>,
+        combine: Option<i64>,
+        tff: Option<i64>,
+        interlaced: Option<i64>,
+    ) -> Result<Self, Error> {
+        let mut params = Params::default();
+        if let Some(mode) = mode {
+            params.set_mode(mode)?;
+        }
+        if let Some(sw) = sw {
+            params.set_sw(sw)?;
+        }
+        if let Some(sh) = sh {
+            params.set_sh(sh)?;
+        }
+        if let Some(contra) = contra {
+            params.contra = contra != 0;
+        }
+        params.blurv = blurv;
+        if let Some(sstr) = sstr {
+            params.sstr = sstr;
+        }
+        if let Some(scl) = scl {
+            params.scl = scl;
+        }
+        if let Some(dither) = dither {
+            params.dither = dither != 0;
+        }
+        if let Some(order) = order {
+            params.set_order(order)?;
+        }
+        if let Some(diffscl) = diffscl {
+            params.set_diffscl(diffscl)?;
+        }
+        if let Some(sw_c) = sw_c {
+            params.set_sw_c(sw_c)?;
+        }
+        if let Some(sh_c) = sh_c {
+            params.set_sh_c(sh_c)?;
+        }
+        params.blurv_c = blurv_c;
+        params.sstr_c = sstr_c;
+        params.scl_c = scl_c;
+        if let Some(passes) = passes {
+            params.set_passes(passes)?;
+        }
+        if let Some(combine) = combine {
+            params.set_combine(combine)?;
+        }
+        if let Some(tff) = tff {
+            params.tff = tff != 0;
+        }
+        if let Some(interlaced) = interlaced {
+            params.interlaced = interlaced != 0;
+        }
+        Ok(params)
+    }
+
+    pub(crate) fn blurv(&self) -> f64 {
+        self.blurv
+            .unwrap_or_else(|| if self.contra { 0.9 } else { 0.0 })
+    }
+
+    /// The settings for luma, see `mpeg2stinx_core::Params::luma`.
+    pub(crate) fn luma(&self) -> PlaneParams {
+        PlaneParams {
+            sw: self.sw,
+            sh: self.sh,
+            shape: self.shape,
+            blurv: self.blurv(),
+            sstr: self.sstr as f32,
+            scl: self.scl as f32,
+        }
+    }
+
+    /// The settings for chroma subsampled by `2^ss_w` and `2^ss_h`,
+    /// see `mpeg2stinx_core::Params::chroma`.
+    pub(crate) fn chroma(&self, ss_w: u32, ss_h: u32) -> PlaneParams {
+        let luma = self.luma();
+        PlaneParams {
+            sw: self
+                .sw_c
+                .unwrap_or_else(|| subsampled_radius(self.sw, ss_w)),
+            sh: self
+                .sh_c
+                .unwrap_or_else(|| subsampled_radius(self.sh, ss_h)),
+            shape: self.shape,
+            blurv: self.blurv_c.unwrap_or(luma.blurv),
+            sstr: self.sstr_c.map_or(luma.sstr, |sstr| sstr as f32),
+            scl: self.scl_c.map_or(luma.scl, |scl| scl as f32),
+        }
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: i64) -> Result<(), Error> {
+        self.mode = FilterMode::try_from(mode)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_sw(&mut self, sw: i64) -> Result<(), Error> {
+        if sw < 0 {
+            return Err(Error::invalid_argument("sw", "a non-negative integer"));
+        }
+        self.sw = sw as u32;
+        Ok(())
+    }
+
+    pub(crate) fn set_sh(&mut self, sh: i64) -> Result<(), Error> {
+        if sh < 0 {
+            return Err(Error::invalid_argument("sh", "a non-negative integer"));
+        }
+        self.sh = sh as u32;
+        Ok(())
+    }
+
+    /// Sets a `shape` of a zone, where a mask (5) can only be kept from the
+    /// `coordinates` argument.
+    pub(crate) fn set_shape(&mut self, shape: i64) -> Result<(), Error> {
+        match (shape, self.shape) {
+            (5, Shape::Mask(_)) => (),
+            _ => self.shape = shape_from_arg(shape)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_sw_c(&mut self, sw_c: i64) -> Result<(), Error> {
+        if sw_c < 0 {
+            return Err(Error::invalid_argument("sw_c", "a non-negative integer"));
+        }
+        self.sw_c = Some(sw_c as u32);
+        Ok(())
+    }
+
+    pub(crate) fn set_sh_c(&mut self, sh_c: i64) -> Result<(), Error> {
+        if sh_c < 0 {
+            return Err(Error::invalid_argument("sh_c", "a non-negative integer"));
+        }
+        self.sh_c = Some(sh_c as u32);
+        Ok(())
+    }
+
+    pub(crate) fn set_order(&mut self, order: i64) -> Result<(), Error> {
+        if !(-1..=1).contains(&order) {
+            return Err(Error::invalid_argument("order", "-1, 0 or 1"));
+        }
+        self.order = order;
+        Ok(())
+    }
+
+    pub(crate) fn set_diffscl(&mut self, diffscl: f64) -> Result<(), Error> {
+        if diffscl.is_nan() || diffscl < 0.0 {
+            return Err(Error::invalid_argument("diffscl", "a non-negative number"));
+        }
+        self.diffscl = Some(diffscl);
+        Ok(())
+    }
+
+    pub(crate) fn set_passes(&mut self, passes: i64) -> Result<(), Error> {
+        if !(1..=i64::from(u32::MAX)).contains(&passes) {
+            return Err(Error::invalid_argument("passes", "a positive integer"));
+        }
+        self.passes = passes as u32;
+        Ok(())
+    }
+
+    pub(crate) fn set_combine(&mut self, combine: i64) -> Result<(), Error> {
+        self.combine = match combine {
+            0 => Combine::Average,
+            1 => Combine::Weighted,
+            2 => Combine::Median,
+            3 => Combine::Last,
+            _ => return Err(Error::invalid_argument("combine", "0, 1, 2 or 3")),
+        };
+        Ok(())
+    }
+
+    /// Applies a single `key=value` override, as found in a zone definition.
+    pub(crate) fn apply_override(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "mode" => self.set_mode(parse_int(key, value)?),
+            "sw" => self.set_sw(parse_int(key, value)?),
+            "sh" => self.set_sh(parse_int(key, value)?),
+            "shape" => self.set_shape(parse_int(key, value)?),
+            "contra" => {
+                self.contra = parse_int(key, value)? != 0;
+                Ok(())
+            }
+            "blurv" => {
+                self.blurv = Some(parse_float(key, value)?);
+                Ok(())
+            }
+            "sstr" => {
+                self.sstr = parse_float(key, value)?;
+                Ok(())
+            }
+            "scl" => {
+                self.scl = parse_float(key, value)?;
+                Ok(())
+            }
+            "dither" => {
+                self.dither = parse_int(key, value)? != 0;
+                Ok(())
+            }
+            "order" => self.set_order(parse_int(key, value)?),
+            "diffscl" => self.set_diffscl(parse_float(key, value)?),
+            "passes" => self.set_passes(parse_int(key, value)?),
+            "combine" => self.set_combine(parse_int(key, value)?),
+            "interlaced" => {
+                self.interlaced = parse_int(key, value)? != 0;
+                Ok(())
+            }
+            "sw_c" => self.set_sw_c(parse_int(key, value)?),
+            "sh_c" => self.set_sh_c(parse_int(key, value)?),
+            "blurv_c" => {
+                self.blurv_c = Some(parse_float(key, value)?);
+                Ok(())
+            }
+            "sstr_c" => {
+                self.sstr_c = Some(parse_float(key, value)?);
+                Ok(())
+            }
+            "scl_c" => {
+                self.scl_c = Some(parse_float(key, value)?);
+                Ok(())
+            }
+            _ => Err(Error::InvalidZones(format!("unknown parameter '{}'", key))),
+        }
+    }
+}
+
+fn parse_int(key: &str, value: &str) -> Result<i64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::invalid_argument(format!("zone parameter {}", key), "an integer"))
+}
+
+fn parse_float(key: &str, value: &str) -> Result<f64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::invalid_argument(format!("zone parameter {}", key), "a number"))
+}
+
+/// How output is produced for one set of `Params`.
+pub(crate) enum Stage<'core> {
+    /// A graph of internal nodes, contrasharpened in `get_frame`.
+    Graph(GraphStage<'core>),
+    /// The whole pipeline run natively on each frame, see `process_fused`.
+    Fused(mpeg2stinx_core::Params, Planes),
+}
+
+/// The nodes and per-frame settings of a `Stage::Graph`.
+pub(crate) struct GraphStage<'core> {
+    pub nuked: Node<'core>,
+    pub nuked_blurred: Option<Node<'core>>,
+    pub contra: bool,
+    pub sstr: f32,
+    pub scl: f32,
+    /// `None` to follow `sstr` and `scl`, including their frame overrides.
+    pub sstr_c: Option<f32>,
+    pub scl_c: Option<f32>,
+    pub planes: Planes,
+    /// For interlaced frames, the source bobbed to double rate, which the
+    /// nodes above process in place of the source.
+    pub bobbed: Option<Node<'core>>,
+    pub tff: bool,
+}
+
+impl Stage<'_> {
+    /// The `_FieldBased` of the output frames of this stage.
+    pub(crate) fn field_based(&self) -> i64 {
+        match self {
+            Stage::Graph(stage) => output_field_based(stage.bobbed.is_some(), stage.tff),
+            Stage::Fused(params, _) => output_field_based(params.interlaced, params.tff),
+        }
+    }
+}
+
+/// The log2 horizontal and vertical chroma subsampling of `clip`.
+pub(crate) fn subsampling(clip: &Node) -> Result<(u32, u32), Error> {
+    match clip.info().format {
+        Constant(format) => Ok((
+            u32::from(format.sub_sampling_w()),
+            u32::from(format.sub_sampling_h()),
+        )),
+        _ => Err(Error::unsupported_format("the format is not constant")),
+    }
+}
+
+/// `cross_field_repair2` with the radii of `luma` and `chroma`, in separate
+/// passes if they differ.
+#[allow(clippy::too_many_arguments)]
+fn repair_planes<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    src: &Node<'core>,
+    bobbed: &Node<'core>,
+    luma: &PlaneParams,
+    chroma: &PlaneParams,
+    planes: Planes,
+    tff: bool,
+) -> Result<Node<'core>, Error> {
+    if (luma.sw, luma.sh) == (chroma.sw, chroma.sh) {
+        return cross_field_repair2(
+            core,
+            api,
+            src,
+            Some(bobbed),
+            luma.sw,
+            luma.sh,
+            luma.shape,
+            planes,
+            tff,
+        );
+    }
+    // Each pass copies the planes it doesn't process from its source
+    let (luma_planes, chroma_planes) = planes.split();
+    let mut clip = src.clone();
+    for (planes, settings) in [(luma_planes, luma), (chroma_planes, chroma)].iter() {
+        if let Some(planes) = *planes {
+            clip = cross_field_repair2(
+                core,
+                api,
+                &clip,
+                Some(bobbed),
+                settings.sw,
+                settings.sh,
+                settings.shape,
+                planes,
+                tff,
+            )?;
+        }
+    }
+    Ok(clip)
+}
+
+/// Blurs the planes of `clip` by the `blurv` of `luma` and `chroma`,
+/// leaving planes with a non-positive `blurv` untouched.
+fn blur_planes<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clip: &Node<'core>,
+    luma: &PlaneParams,
+    chroma: &PlaneParams,
+    planes: Planes,
+) -> Result<Node<'core>, Error> {
+    let (luma_planes, chroma_planes) = planes.split();
+    let passes: Vec<(Planes, f64)> = if luma.blurv == chroma.blurv {
+        vec![(planes, luma.blurv)]
+    } else {
+        vec![(luma_planes, luma.blurv), (chroma_planes, chroma.blurv)]
+            .into_iter()
+            .filter_map(|(planes, blurv)| planes.map(|planes| (planes, blurv)))
+            .collect()
+    };
+    let mut clip = clip.clone();
+    for (planes, blurv) in passes {
+        if blurv > 0.0 {
+            clip = crate::util::blur_v(core, api, &clip, blurv, planes)?;
+        }
+    }
+    Ok(clip)
+}
+
+pub(crate) fn build_stage<'core>(
+    core: CoreRef<'core>,
+    api: API,
+    clip: &Node<'core>,
+    params: &Params,
+    fused: bool,
+) -> Result<Stage<'core>, Error> {
+    if fused {
+        return Ok(Stage::Fused(core_params(params)?, params.planes));
+    }
+
+    let (ss_w, ss_h) = subsampling(clip)?;
+    let (luma, chroma) = (params.luma(), params.chroma(ss_w, ss_h));
+
+    // Interlaced frames are processed as the frames of their fields
+    let bobbed = if params.interlaced {
+        let format = match clip.info().format {
+            Constant(format) => format.id(),
+            _ => return Err(Error::unsupported_format("the format is not constant")),
+        };
+        Some(convert(
+            core,
+            api,
+            &params.mode.deint(core, api, clip, params.tff)?,
+            format.into(),
+        )?)
+    } else {
+        None
+    };
+    let clip = bobbed.as_ref().unwrap_or(clip);
+
+    // Each pass repairs the previous one, or the source for the first
+    let mut passes: Vec<Node<'core>> = Vec::with_capacity(params.passes as usize);
+    for _ in 0..params.passes {
+        let src = passes.last().unwrap_or(clip);
+        let repaired = repair_planes(
+            core,
+            api,
+            src,
+            &deint(core, api, src, params.mode, params.order, params.tff)?,
+            &luma,
+            &chroma,
+            params.planes,
+            params.tff,
+        )?;
+        let repaired = if let Some(diffscl) = params.diffscl {
+            temp_limit(
+                core,
+                api,
+                src,
+                &repaired,
+                clip,
+                diffscl,
+                params.planes,
+                params.tff,
+            )?
+        } else {
+            repaired
+        };
+        passes.push(repaired);
+    }
+
+    let passes: Vec<&Node<'core>> = passes.iter().collect();
+    let combined = match params.combine {
+        Combine::Average | Combine::Weighted => average(
+            core,
+            api,
+            &passes,
+            &params.combine.weights(passes.len()),
+            params.dither,
+            params.planes,
+        )?,
+        Combine::Median => median_frames_clip(core, api, &passes, params.planes)?,
+        Combine::Last => passes[passes.len() - 1].clone(),
+    };
+
+    let nuked = blur_planes(core, api, &combined, &luma, &chroma, params.planes)?;
+    let nuked_blurred = if params.contra {
+        Some(crate::util::blur_v(
+            core,
+            api,
+            &crate::util::blur_v(core, api, &nuked, 1.0, params.planes)?,
+            1.0,
+            params.planes,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(Stage::Graph(GraphStage {
+        nuked,
+        nuked_blurred,
+        contra: params.contra,
+        sstr: params.sstr as f32,
+        scl: params.scl as f32,
+        sstr_c: params.sstr_c.map(|sstr| sstr as f32),
+        scl_c: params.scl_c.map(|scl| scl as f32),
+        planes: params.planes,
+        bobbed,
+        tff: params.tff,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fused::core_params;
+    use crate::zones::parse_zones;
+
+    fn with_tff(tff: Option<i64>) -> Params {
+        Params::from_args(
+            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+            None, None, None, tff, None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn defaults_to_top_field_first() {
+        assert!(with_tff(None).tff);
+        assert!(with_tff(Some(1)).tff);
+        assert!(!with_tff(Some(0)).tff);
+    }
+
+    #[test]
+    fn zones_and_fused_processing_keep_the_field_order() {
+        let params = with_tff(Some(0));
+        let zones = parse_zones("0 9 sw=2 interlaced=1", &params, 20).unwrap();
+        assert!(!zones[0].params.tff);
+        assert!(!core_params(&zones[0].params).unwrap().tff);
+        assert!(parse_zones("0 9 tff=1", &params, 20).is_err());
+    }
+}
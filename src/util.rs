@@ -1,7 +1,9 @@
 use super::*;
-use failure::{bail, ensure, Error};
-use std::cmp;
+use crate::adapter::for_each_float_row;
+use crate::error::Error;
+use mpeg2stinx_core::{build_blurv_kernel, Isa, Shape, SimdPixel};
 use vapoursynth::core::CoreRef;
+use vapoursynth::format::SampleType;
 use vapoursynth::prelude::*;
 use vapoursynth::video_info::Property::Constant;
 
@@ -15,7 +17,7 @@ pub(crate) fn u_to_y8<'core>(
             return convert(core, api, src, PresetFormat::Gray8 as i32);
         }
     } else {
-        bail!("Format is not constant");
+        return Err(Error::unsupported_format("the format is not constant"));
     };
     convert(
         core,
@@ -35,7 +37,7 @@ pub(crate) fn v_to_y8<'core>(
             return convert(core, api, src, PresetFormat::Gray8 as i32);
         }
     } else {
-        bail!("Format is not constant");
+        return Err(Error::unsupported_format("the format is not constant"));
     };
     convert(
         core,
@@ -58,12 +60,16 @@ pub(crate) fn max_yuv<'core>(
     let y_res = if let Constant(res) = y.info().resolution {
         res
     } else {
-        bail!("Luma resolution is not constant");
+        return Err(Error::unsupported_format(
+            "the luma resolution is not constant",
+        ));
     };
     let u_res = if let Constant(res) = u.info().resolution {
         res
     } else {
-        bail!("Chroma resolution is not constant");
+        return Err(Error::unsupported_format(
+            "the chroma resolution is not constant",
+        ));
     };
     let w = y_res.width as i64;
     let h = y_res.height as i64;
@@ -74,10 +80,23 @@ pub(crate) fn max_yuv<'core>(
     let ls = max_clip(
         core,
         api,
-        &max_clip(core, api, &y, &bilinear_resize(core, api, &u, w, h)?)?,
+        &max_clip(
+            core,
+            api,
+            &y,
+            &bilinear_resize(core, api, &u, w, h)?,
+            Planes::ALL,
+        )?,
         &bilinear_resize(core, api, &v, w, h)?,
+        Planes::ALL,
+    )?;
+    let cs = max_clip(
+        core,
+        api,
+        &max_clip(core, api, &yc, &u, Planes::ALL)?,
+        &v,
+        Planes::ALL,
     )?;
-    let cs = max_clip(core, api, &max_clip(core, api, &yc, &u)?, &v)?;
     shuffle_planes(core, api, &[&ls, &cs, &cs], &[0, 0, 0], ColorFamily::YUV)
 }
 
@@ -86,101 +105,46 @@ pub(crate) fn median3<'core>(
     clip1: &FrameRef<'core>,
     clip2: &FrameRef<'core>,
     clip3: &FrameRef<'core>,
-    process_chroma: bool,
+    planes: Planes,
 ) -> Result<FrameRef<'core>, Error> {
     let mut filtered = FrameRefMut::copy_of(core, &*clip1);
 
-    ensure!(
-        clip1.format().plane_count() == clip2.format().plane_count(),
-        "Clip2 had {} planes, expected {}",
-        clip2.format().plane_count(),
-        clip1.format().plane_count()
-    );
-    ensure!(
-        clip1.format().plane_count() == clip3.format().plane_count(),
-        "Clip3 had {} planes, expected {}",
-        clip3.format().plane_count(),
-        clip1.format().plane_count()
-    );
-    ensure!(
-        clip1.format().bits_per_sample() == clip2.format().bits_per_sample(),
-        "Clip2 had bit depth of {}, expected {}",
-        clip2.format().bits_per_sample(),
-        clip1.format().bits_per_sample()
-    );
-    ensure!(
-        clip1.format().bits_per_sample() == clip3.format().bits_per_sample(),
-        "Clip3 had bit depth of {}, expected {}",
-        clip3.format().bits_per_sample(),
-        clip1.format().bits_per_sample()
-    );
-    let plane_count = cmp::min(
-        clip1.format().plane_count(),
-        if process_chroma { 3 } else { 1 },
-    );
-    let bytes_per_sample = clip1.format().bytesPerSample;
-    for plane in 0..plane_count {
-        match bytes_per_sample {
-            1 => median_loop_u8(clip1, clip2, clip3, &mut filtered, plane)?,
-            2 => median_loop_u16(clip1, clip2, clip3, &mut filtered, plane)?,
-            4 => median_loop_u32(clip1, clip2, clip3, &mut filtered, plane)?,
-            _ => unreachable!(),
+    for (name, clip) in [("clip2", clip2), ("clip3", clip3)].iter() {
+        if clip.format().plane_count() != clip1.format().plane_count()
+            || clip.format().bits_per_sample() != clip1.format().bits_per_sample()
+        {
+            return Err(Error::unsupported_format(format!(
+                "Median3 {} has {} planes of {} bits, expected {} planes of {} bits",
+                name,
+                clip.format().plane_count(),
+                clip.format().bits_per_sample(),
+                clip1.format().plane_count(),
+                clip1.format().bits_per_sample()
+            )));
         }
     }
+    let plane_indices = planes.filter(0..clip1.format().plane_count());
+    if clip1.format().sample_type() == SampleType::Float {
+        let isa = Isa::detect();
+        for_each_float_row(
+            plane_indices,
+            [&**clip1, &**clip2, &**clip3],
+            &mut filtered,
+            |[x, y, z], dst| f32::median3_row(isa, x, y, z, dst),
+        );
+    } else {
+        for_each_plane!(
+            plane_indices,
+            clip1.format().bytesPerSample,
+            ref [x = clip1, y = clip2, z = clip3],
+            mut [dst = filtered],
+            mpeg2stinx_core::median3(x, y, z, dst)
+        );
+    }
     Ok(FrameRef::from(filtered))
 }
 
-macro_rules! median_fn {
-    ($pix_ty:ty) => {
-        paste::item! {
-            fn [<median_loop_ $pix_ty>]<'core>(
-                clip1: &FrameRef<'core>,
-                clip2: &FrameRef<'core>,
-                clip3: &FrameRef<'core>,
-                filtered: &mut FrameRefMut<'core>,
-                plane: usize,
-            ) -> Result<(), Error> {
-                for (((&x, &y), &z), target) in clip1
-                    .plane::<$pix_ty>(plane)
-                    .map_err(Error::from)?
-                    .into_iter()
-                    .zip(
-                        clip2
-                            .plane::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .into_iter(),
-                    ).zip(
-                        clip3
-                            .plane::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .into_iter(),
-                    )
-                    .zip(
-                        filtered
-                            .plane_mut::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .iter_mut(),
-                    )
-                {
-                    *target = if x > y {
-                        if y > z { y }
-                        else if x > z { z }
-                        else { x }
-                    } else {
-                        if x > z { x }
-                        else if y > z { z }
-                        else { y }
-                    };
-                }
-                Ok(())
-            }
-        }
-    };
-}
-median_fn!(u8);
-median_fn!(u16);
-median_fn!(u32);
-
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn temp_limit<'core>(
     core: CoreRef<'core>,
     api: API,
@@ -188,6 +152,8 @@ pub(crate) fn temp_limit<'core>(
     flt: &Node<'core>,
     reff: &Node<'core>,
     diffscl: f64,
+    planes: Planes,
+    tff: bool,
 ) -> Result<Node<'core>, Error> {
     let adj = select_every(core, api, reff, 1, &[-1, 1])?;
     let diff = max_yuv(
@@ -196,13 +162,20 @@ pub(crate) fn temp_limit<'core>(
         &separate_rows(
             core,
             api,
-            &lutxy_diff_clip(core, api, &select_every(core, api, clip, 1, &[0, 0])?, &adj)?,
+            &lutxy_diff_clip(
+                core,
+                api,
+                &select_every(core, api, clip, 1, &[0, 0])?,
+                &adj,
+                Planes::ALL,
+            )?,
+            tff,
         )?,
     )?;
     let diff2 = weave_rows(
         core,
         api,
-        &expand_multi(
+        &expand_clip(
             core,
             api,
             &min_clip(
@@ -210,240 +183,114 @@ pub(crate) fn temp_limit<'core>(
                 api,
                 &select_every(core, api, &diff, 4, &[0, 1])?,
                 &select_every(core, api, &diff, 4, &[2, 3])?,
+                Planes::ALL,
             )?,
             2,
             1,
-            true,
+            Shape::Square,
+            Planes::ALL,
         )?,
+        tff,
     )?;
-    let a = average_frames(core, api, &[clip, &diff2], Some(&[1.0, -diffscl]))?;
-    let b = average_frames(core, api, &[clip, &diff2], Some(&[1.0, diffscl]))?;
-    median3_clip(core, api, &a, &b, flt, true)
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ExpandMode {
-    Square,
-    Horizontal,
-    Vertical,
-    None,
-}
-
-impl ExpandMode {
-    pub(crate) fn to_coords(self) -> [i64; 8] {
-        match self {
-            ExpandMode::Square => [1, 1, 1, 1, 1, 1, 1, 1],
-            ExpandMode::Horizontal => [0, 0, 0, 1, 1, 0, 0, 0],
-            ExpandMode::Vertical => [0, 1, 0, 0, 0, 0, 1, 0],
-            ExpandMode::None => [0; 8],
-        }
-    }
-}
-
-pub(crate) fn expand_multi<'core>(
-    core: CoreRef<'core>,
-    api: API,
-    clip: &Node<'core>,
-    sw: u32,
-    sh: u32,
-    process_chroma: bool,
-) -> Result<Node<'core>, Error> {
-    let mode = match (sw, sh) {
-        (0, 0) => ExpandMode::None,
-        (0, _) => ExpandMode::Vertical,
-        (_, 0) => ExpandMode::Horizontal,
-        _ => ExpandMode::Square,
-    };
-
-    if mode == ExpandMode::None {
-        return Ok(clip.clone());
-    }
-
-    let expanded = expand(core, api, clip, mode, process_chroma)?;
-    expand_multi(
+    // clip -/+ diffscl * diff2, with the weights taken as they are
+    let a = average_frames_clip(
         core,
         api,
-        &expanded,
-        sw.saturating_sub(1),
-        sh.saturating_sub(1),
-        process_chroma,
-    )
+        &[clip, &diff2],
+        Some(&[1.0, -diffscl]),
+        Some(1.0),
+        planes,
+    )?;
+    let b = average_frames_clip(
+        core,
+        api,
+        &[clip, &diff2],
+        Some(&[1.0, diffscl]),
+        Some(1.0),
+        planes,
+    )?;
+    median3_clip(core, api, &a, &b, flt, planes)
 }
 
-pub(crate) fn inpand_multi<'core>(
+/// The interlaced frame made of the first field of `first` and the second
+/// field of `second`, in the field order `tff`.
+pub(crate) fn weave_frames<'core>(
     core: CoreRef<'core>,
-    api: API,
-    clip: &Node<'core>,
-    sw: u32,
-    sh: u32,
-    process_chroma: bool,
-) -> Result<Node<'core>, Error> {
-    let mode = match (sw, sh) {
-        (0, 0) => ExpandMode::None,
-        (0, _) => ExpandMode::Vertical,
-        (_, 0) => ExpandMode::Horizontal,
-        _ => ExpandMode::Square,
-    };
-
-    if mode == ExpandMode::None {
-        return Ok(clip.clone());
+    first: &FrameRef<'core>,
+    second: &FrameRef<'core>,
+    tff: bool,
+) -> FrameRef<'core> {
+    let mut output = FrameRefMut::copy_of(core, first);
+    // The second field is on the odd rows for top field first
+    let parity = tff as usize;
+    for plane in 0..first.format().plane_count() {
+        for row in (parity..first.height(plane)).step_by(2) {
+            output
+                .data_row_mut(plane, row)
+                .copy_from_slice(second.data_row(plane, row));
+        }
     }
-
-    let inpanded = inpand(core, api, clip, mode, process_chroma)?;
-    inpand_multi(
-        core,
-        api,
-        &inpanded,
-        sw.saturating_sub(1),
-        sh.saturating_sub(1),
-        process_chroma,
-    )
+    FrameRef::from(output)
 }
 
 pub(crate) fn min<'core>(
     core: CoreRef<'core>,
     clip1: &FrameRef<'core>,
     clip2: &FrameRef<'core>,
+    planes: Planes,
 ) -> Result<FrameRef<'core>, Error> {
     let mut filtered = FrameRefMut::copy_of(core, &*clip1);
 
     // Assume formats are equivalent, because this is an internal function
-    let plane_count = clip1.format().plane_count();
-    let bytes_per_sample = clip1.format().bytesPerSample;
-    for plane in 0..plane_count {
-        match bytes_per_sample {
-            1 => min_loop_u8(clip1, clip2, &mut filtered, plane)?,
-            2 => min_loop_u16(clip1, clip2, &mut filtered, plane)?,
-            4 => min_loop_u32(clip1, clip2, &mut filtered, plane)?,
-            _ => unreachable!(),
-        }
+    let plane_indices = planes.filter(0..clip1.format().plane_count());
+    if clip1.format().sample_type() == SampleType::Float {
+        let isa = Isa::detect();
+        for_each_float_row(
+            plane_indices,
+            [&**clip1, &**clip2],
+            &mut filtered,
+            |[x, y], dst| f32::min_row(isa, x, y, dst),
+        );
+    } else {
+        for_each_plane!(
+            plane_indices,
+            clip1.format().bytesPerSample,
+            ref [x = clip1, y = clip2],
+            mut [dst = filtered],
+            mpeg2stinx_core::min(x, y, dst)
+        );
     }
     Ok(FrameRef::from(filtered))
 }
 
-macro_rules! min_fn {
-    ($pix_ty:ty) => {
-        paste::item! {
-            fn [<min_loop_ $pix_ty>]<'core>(
-                clip1: &FrameRef<'core>,
-                clip2: &FrameRef<'core>,
-                filtered: &mut FrameRefMut<'core>,
-                plane: usize,
-            ) -> Result<(), Error> {
-                for ((&x, &y), target) in clip1
-                    .plane::<$pix_ty>(plane)
-                    .map_err(Error::from)?
-                    .into_iter()
-                    .zip(
-                        clip2
-                            .plane::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .into_iter(),
-                    )
-                    .zip(
-                        filtered
-                            .plane_mut::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .iter_mut(),
-                    )
-                {
-                    *target = ::std::cmp::min(x, y);
-                }
-                Ok(())
-            }
-        }
-    };
-}
-min_fn!(u8);
-min_fn!(u16);
-min_fn!(u32);
-
 pub(crate) fn max<'core>(
     core: CoreRef<'core>,
     clip1: &FrameRef<'core>,
     clip2: &FrameRef<'core>,
+    planes: Planes,
 ) -> Result<FrameRef<'core>, Error> {
     let mut filtered = FrameRefMut::copy_of(core, &*clip1);
 
     // Assume formats are equivalent, because this is an internal function
-    let plane_count = clip1.format().plane_count();
-    let bytes_per_sample = clip1.format().bytesPerSample;
-    for plane in 0..plane_count {
-        match bytes_per_sample {
-            1 => max_loop_u8(clip1, clip2, &mut filtered, plane)?,
-            2 => max_loop_u16(clip1, clip2, &mut filtered, plane)?,
-            4 => max_loop_u32(clip1, clip2, &mut filtered, plane)?,
-            _ => unreachable!(),
-        }
-    }
-    Ok(FrameRef::from(filtered))
-}
-
-macro_rules! max_fn {
-    ($pix_ty:ty) => {
-        paste::item! {
-            fn [<max_loop_ $pix_ty>]<'core>(
-                clip1: &FrameRef<'core>,
-                clip2: &FrameRef<'core>,
-                filtered: &mut FrameRefMut<'core>,
-                plane: usize,
-            ) -> Result<(), Error> {
-                for ((&x, &y), target) in clip1
-                    .plane::<$pix_ty>(plane)
-                    .map_err(Error::from)?
-                    .into_iter()
-                    .zip(
-                        clip2
-                            .plane::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .into_iter(),
-                    )
-                    .zip(
-                        filtered
-                            .plane_mut::<$pix_ty>(plane)
-                            .map_err(Error::from)?
-                            .iter_mut(),
-                    )
-                {
-                    *target = ::std::cmp::max(x, y);
-                }
-                Ok(())
-            }
-        }
-    };
-}
-max_fn!(u8);
-max_fn!(u16);
-max_fn!(u32);
-
-#[inline(always)]
-pub(crate) fn clamp<T: PartialOrd>(input: T, min: T, max: T) -> T {
-    if input < min {
-        min
-    } else if input > max {
-        max
+    let plane_indices = planes.filter(0..clip1.format().plane_count());
+    if clip1.format().sample_type() == SampleType::Float {
+        let isa = Isa::detect();
+        for_each_float_row(
+            plane_indices,
+            [&**clip1, &**clip2],
+            &mut filtered,
+            |[x, y], dst| f32::max_row(isa, x, y, dst),
+        );
     } else {
-        input
+        for_each_plane!(
+            plane_indices,
+            clip1.format().bytesPerSample,
+            ref [x = clip1, y = clip2],
+            mut [dst = filtered],
+            mpeg2stinx_core::max(x, y, dst)
+        );
     }
-}
-
-pub(crate) fn build_blurv_kernel(strength: f64) -> [f64; 3] {
-    // Vapoursynth's Convolution kernel will round our numbers to integers,
-    // so scale up as far as possible for the most accuracy.
-    const MAX: f64 = 1023.0;
-    let inner_factor = 1.0 / 2f64.powf(strength);
-    let outer_factor = (1.0 - 1.0 / 2f64.powf(strength)) / 2.0;
-    let inner = if strength > 0.0 {
-        inner_factor * MAX
-    } else {
-        MAX / inner_factor
-    };
-    let outer = if strength > 0.0 {
-        outer_factor * MAX
-    } else {
-        MAX / outer_factor
-    };
-    [outer, inner, outer]
+    Ok(FrameRef::from(filtered))
 }
 
 pub(crate) fn blur_v<'core>(
@@ -451,13 +298,10 @@ pub(crate) fn blur_v<'core>(
     api: API,
     src: &Node<'core>,
     strength: f64,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
-    let kernel = if (strength - 1.0).abs() < std::f64::EPSILON {
-        build_blurv_kernel(1.0)
-    } else {
-        build_blurv_kernel(strength)
-    };
-    crate::vsfunc::blur_v(core, api, src, &kernel)
+    let kernel = build_blurv_kernel(strength);
+    crate::vsfunc::blur_v(core, api, src, &kernel, planes)
 }
 
 pub(crate) fn deint<'core>(
@@ -466,8 +310,9 @@ pub(crate) fn deint<'core>(
     src: &Node<'core>,
     mode: FilterMode,
     order: i64,
+    tff: bool,
 ) -> Result<Node<'core>, Error> {
-    let bobbed = mode.deint(core, api, src)?;
+    let bobbed = mode.deint(core, api, src, tff)?;
     Ok(match order {
         -1 => bobbed,
         0 => select_every(
@@ -492,17 +337,22 @@ pub(crate) fn deint<'core>(
     })
 }
 
+/// The mean of `clips` weighted by `weights`.
 pub(crate) fn average<'core>(
     core: CoreRef<'core>,
     api: API,
-    a: &Node<'core>,
-    b: &Node<'core>,
+    clips: &[&Node<'core>],
+    weights: &[f64],
     dither: bool,
+    planes: Planes,
 ) -> Result<Node<'core>, Error> {
     if dither {
         // DitherPost(RAverageW(a, 0.5, b, 0.5, lsb_out=true)?, mode=7)
-        bail!("dither=true is not yet implemented");
+        Err(Error::invalid_argument(
+            "dither",
+            "0, dithering is not implemented yet",
+        ))
     } else {
-        average_frames(core, api, &[a, b], None)
+        average_frames_clip(core, api, clips, Some(weights), None, planes)
     }
 }
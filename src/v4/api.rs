@@ -0,0 +1,363 @@
+//! Thin owning wrappers around the raw API v4 handles.
+
+use super::ffi::*;
+use crate::props::FrameProps;
+use mpeg2stinx_core::{Pixel, PlaneMut, PlaneRef};
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+
+/// The function table VapourSynth passes to every callback.
+#[derive(Clone, Copy)]
+pub(crate) struct Api(&'static VSAPI);
+
+// SAFETY: the function table is immutable and its functions are thread-safe
+unsafe impl Send for Api {}
+unsafe impl Sync for Api {}
+
+impl Api {
+    /// # Safety
+    /// `api` must be the pointer VapourSynth passed to a callback.
+    pub(crate) unsafe fn from_raw(api: *const VSAPI) -> Self {
+        Api(&*api)
+    }
+
+    pub(crate) fn raw(self) -> &'static VSAPI {
+        self.0
+    }
+}
+
+fn c_key(key: &str) -> CString {
+    CString::new(key).expect("map keys contain no NUL bytes")
+}
+
+/// A borrowed, read-only map: function arguments or frame properties.
+pub(crate) struct MapRef<'a> {
+    api: Api,
+    ptr: *const VSMap,
+    _marker: PhantomData<&'a VSMap>,
+}
+
+impl<'a> MapRef<'a> {
+    /// # Safety
+    /// `ptr` must be a valid map that outlives `'a`.
+    pub(crate) unsafe fn from_raw(api: Api, ptr: *const VSMap) -> Self {
+        MapRef {
+            api,
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn get_int(&self, key: &str) -> Option<i64> {
+        let key = c_key(key);
+        let mut error = 0;
+        let value = unsafe { (self.api.raw().mapGetInt)(self.ptr, key.as_ptr(), 0, &mut error) };
+        if error == PE_SUCCESS {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn get_float(&self, key: &str) -> Option<f64> {
+        let key = c_key(key);
+        let mut error = 0;
+        let value = unsafe { (self.api.raw().mapGetFloat)(self.ptr, key.as_ptr(), 0, &mut error) };
+        if error == PE_SUCCESS {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// All ints stored under `key`, or `None` if it is not set.
+    pub(crate) fn get_ints(&self, key: &str) -> Option<Vec<i64>> {
+        let count = self.num_elements(key);
+        if count == 0 {
+            return None;
+        }
+        let key = c_key(key);
+        (0..count)
+            .map(|index| {
+                let mut error = 0;
+                let value = unsafe {
+                    (self.api.raw().mapGetInt)(self.ptr, key.as_ptr(), index as c_int, &mut error)
+                };
+                if error == PE_SUCCESS {
+                    Some(value)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// All floats stored under `key`, or `None` if it is not set.
+    pub(crate) fn get_floats(&self, key: &str) -> Option<Vec<f64>> {
+        let count = self.num_elements(key);
+        if count == 0 {
+            return None;
+        }
+        let key = c_key(key);
+        (0..count)
+            .map(|index| {
+                let mut error = 0;
+                let value = unsafe {
+                    (self.api.raw().mapGetFloat)(self.ptr, key.as_ptr(), index as c_int, &mut error)
+                };
+                if error == PE_SUCCESS {
+                    Some(value)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The number of values stored under `key`, 0 if it is not set.
+    pub(crate) fn num_elements(&self, key: &str) -> usize {
+        let key = c_key(key);
+        let count = unsafe { (self.api.raw().mapNumElements)(self.ptr, key.as_ptr()) };
+        count.max(0) as usize
+    }
+
+    pub(crate) fn get_data(&self, key: &str) -> Option<&'a [u8]> {
+        let key = c_key(key);
+        let mut error = 0;
+        unsafe {
+            let data = (self.api.raw().mapGetData)(self.ptr, key.as_ptr(), 0, &mut error);
+            if error != PE_SUCCESS {
+                return None;
+            }
+            let size = (self.api.raw().mapGetDataSize)(self.ptr, key.as_ptr(), 0, &mut error);
+            Some(slice::from_raw_parts(
+                data as *const u8,
+                size.max(0) as usize,
+            ))
+        }
+    }
+
+    pub(crate) fn get_node(&self, key: &str, index: usize) -> Option<Node> {
+        let key = c_key(key);
+        let mut error = 0;
+        let node = unsafe {
+            (self.api.raw().mapGetNode)(self.ptr, key.as_ptr(), index as c_int, &mut error)
+        };
+        if error == PE_SUCCESS {
+            Some(Node {
+                api: self.api,
+                ptr: node,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl FrameProps for MapRef<'_> {
+    fn int(&self, key: &str) -> Option<i64> {
+        self.get_int(key)
+    }
+
+    fn ints(&self, key: &str) -> Option<Vec<i64>> {
+        self.get_ints(key)
+    }
+
+    fn float(&self, key: &str) -> Option<f64> {
+        self.get_float(key)
+    }
+}
+
+/// A borrowed, writable map.
+pub(crate) struct MapMut<'a> {
+    api: Api,
+    ptr: *mut VSMap,
+    _marker: PhantomData<&'a mut VSMap>,
+}
+
+impl<'a> MapMut<'a> {
+    /// # Safety
+    /// `ptr` must be a valid map that outlives `'a` and is not aliased.
+    pub(crate) unsafe fn from_raw(api: Api, ptr: *mut VSMap) -> Self {
+        MapMut {
+            api,
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn as_raw(&mut self) -> *mut VSMap {
+        self.ptr
+    }
+
+    pub(crate) fn set_int(&mut self, key: &str, value: i64) {
+        let key = c_key(key);
+        unsafe { (self.api.raw().mapSetInt)(self.ptr, key.as_ptr(), value, MA_REPLACE) };
+    }
+
+    pub(crate) fn set_error(&mut self, message: &str) {
+        let message = CString::new(message.replace('\0', "")).unwrap();
+        unsafe { (self.api.raw().mapSetError)(self.ptr, message.as_ptr()) };
+    }
+}
+
+/// An owned reference to a node.
+pub(crate) struct Node {
+    api: Api,
+    ptr: *mut VSNode,
+}
+
+// SAFETY: node references may be used and freed from any thread
+unsafe impl Send for Node {}
+unsafe impl Sync for Node {}
+
+impl Node {
+    pub(crate) fn as_raw(&self) -> *mut VSNode {
+        self.ptr
+    }
+
+    pub(crate) fn info(&self) -> &VSVideoInfo {
+        unsafe { &*(self.api.raw().getVideoInfo)(self.ptr) }
+    }
+
+    pub(crate) fn request_frame_filter(&self, n: usize, ctx: *mut VSFrameContext) {
+        unsafe { (self.api.raw().requestFrameFilter)(n as c_int, self.ptr, ctx) };
+    }
+
+    pub(crate) fn get_frame_filter(&self, n: usize, ctx: *mut VSFrameContext) -> Option<Frame> {
+        let frame = unsafe { (self.api.raw().getFrameFilter)(n as c_int, self.ptr, ctx) };
+        if frame.is_null() {
+            None
+        } else {
+            Some(Frame {
+                api: self.api,
+                ptr: frame,
+            })
+        }
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        unsafe { (self.api.raw().freeNode)(self.ptr) };
+    }
+}
+
+/// Plane accessors shared by `Frame` and `FrameMut`.
+macro_rules! impl_frame_info {
+    ($ty:ty) => {
+        // Not every accessor is needed for both types
+        #[allow(dead_code)]
+        impl $ty {
+            pub(crate) fn format(&self) -> &VSVideoFormat {
+                unsafe { &*(self.api.raw().getVideoFrameFormat)(self.ptr) }
+            }
+
+            pub(crate) fn width(&self, plane: usize) -> usize {
+                unsafe { (self.api.raw().getFrameWidth)(self.ptr, plane as c_int) as usize }
+            }
+
+            pub(crate) fn height(&self, plane: usize) -> usize {
+                unsafe { (self.api.raw().getFrameHeight)(self.ptr, plane as c_int) as usize }
+            }
+
+            fn plane_len<T>(&self, plane: usize) -> (usize, usize) {
+                let stride = unsafe { (self.api.raw().getStride)(self.ptr, plane as c_int) }
+                    as usize
+                    / mem::size_of::<T>();
+                let height = self.height(plane);
+                let len = if height == 0 {
+                    0
+                } else {
+                    stride * (height - 1) + self.width(plane)
+                };
+                (stride, len)
+            }
+
+            pub(crate) fn plane_ref<T: Pixel>(&self, plane: usize) -> PlaneRef<'_, T> {
+                let (stride, len) = self.plane_len::<T>(plane);
+                // SAFETY: see `adapter::plane_ref`
+                let data = unsafe {
+                    let ptr = (self.api.raw().getReadPtr)(self.ptr, plane as c_int);
+                    slice::from_raw_parts(ptr as *const T, len)
+                };
+                PlaneRef::new(data, self.width(plane), self.height(plane), stride)
+            }
+
+            pub(crate) fn props(&self) -> MapRef<'_> {
+                unsafe {
+                    MapRef::from_raw(self.api, (self.api.raw().getFramePropertiesRO)(self.ptr))
+                }
+            }
+        }
+    };
+}
+
+/// An owned reference to a frame returned by a node.
+pub(crate) struct Frame {
+    api: Api,
+    ptr: *const VSFrame,
+}
+
+impl_frame_info!(Frame);
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        unsafe { (self.api.raw().freeFrame)(self.ptr) };
+    }
+}
+
+/// A writable frame, to be returned from a filter.
+pub(crate) struct FrameMut {
+    api: Api,
+    ptr: *mut VSFrame,
+}
+
+impl_frame_info!(FrameMut);
+
+impl FrameMut {
+    /// A writable copy of `src`, sharing its planes until they are written.
+    pub(crate) fn copy_of(src: &Frame, core: *mut VSCore) -> Self {
+        FrameMut {
+            api: src.api,
+            ptr: unsafe { (src.api.raw().copyFrame)(src.ptr, core) },
+        }
+    }
+
+    pub(crate) fn plane_mut<T: Pixel>(&mut self, plane: usize) -> PlaneMut<'_, T> {
+        let (stride, len) = self.plane_len::<T>(plane);
+        let (width, height) = (self.width(plane), self.height(plane));
+        // SAFETY: see `adapter::plane_mut`
+        let data = unsafe {
+            let ptr = (self.api.raw().getWritePtr)(self.ptr, plane as c_int);
+            slice::from_raw_parts_mut(ptr as *mut T, len)
+        };
+        PlaneMut::new(data, width, height, stride)
+    }
+
+    pub(crate) fn props_mut(&mut self) -> MapMut<'_> {
+        unsafe { MapMut::from_raw(self.api, (self.api.raw().getFramePropertiesRW)(self.ptr)) }
+    }
+
+    /// Hands the frame over to VapourSynth.
+    pub(crate) fn into_raw(self) -> *const VSFrame {
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for FrameMut {
+    fn drop(&mut self) {
+        unsafe { (self.api.raw().freeFrame)(self.ptr) };
+    }
+}
+
+/// A null frame, returned from `getFrame` when no frame is produced.
+pub(crate) fn no_frame() -> *const VSFrame {
+    ptr::null()
+}
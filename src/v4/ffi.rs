@@ -0,0 +1,263 @@
+//! The subset of `VapourSynth4.h` used by the plugin.
+//!
+//! `VSAPI` is only ever accessed through a pointer handed to us by
+//! VapourSynth, so only the part of it up to the last function we call is
+//! declared. Its fields must stay in the order of the header.
+
+#![allow(
+    non_camel_case_types,
+    non_snake_case,
+    dead_code,
+    clippy::upper_case_acronyms
+)]
+
+use std::os::raw::{c_char, c_double, c_int, c_void};
+
+pub const VAPOURSYNTH_API_MAJOR: c_int = 4;
+pub const VAPOURSYNTH_API_MINOR: c_int = 0;
+pub const VAPOURSYNTH_API_VERSION: c_int = (VAPOURSYNTH_API_MAJOR << 16) | VAPOURSYNTH_API_MINOR;
+
+pub enum VSFrame {}
+pub enum VSNode {}
+pub enum VSCore {}
+pub enum VSPlugin {}
+pub enum VSMap {}
+pub enum VSFrameContext {}
+
+pub const CF_UNDEFINED: c_int = 0;
+pub const CF_GRAY: c_int = 1;
+pub const CF_YUV: c_int = 3;
+pub const ST_INTEGER: c_int = 0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VSVideoFormat {
+    pub colorFamily: c_int,
+    pub sampleType: c_int,
+    pub bitsPerSample: c_int,
+    pub bytesPerSample: c_int,
+    pub subSamplingW: c_int,
+    pub subSamplingH: c_int,
+    pub numPlanes: c_int,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VSVideoInfo {
+    pub format: VSVideoFormat,
+    pub fpsNum: i64,
+    pub fpsDen: i64,
+    pub width: c_int,
+    pub height: c_int,
+    pub numFrames: c_int,
+}
+
+pub const PE_SUCCESS: c_int = 0;
+
+pub const MA_REPLACE: c_int = 0;
+pub const MA_APPEND: c_int = 1;
+
+pub const AR_INITIAL: c_int = 0;
+pub const AR_ALL_FRAMES_READY: c_int = 1;
+pub const AR_ERROR: c_int = -1;
+
+pub const FM_PARALLEL: c_int = 0;
+pub const FM_PARALLEL_REQUESTS: c_int = 1;
+pub const FM_UNORDERED: c_int = 2;
+pub const FM_FRAME_STATE: c_int = 3;
+
+pub const RP_GENERAL: c_int = 0;
+pub const RP_NO_FRAME_REUSE: c_int = 1;
+pub const RP_STRICT_SPATIAL: c_int = 2;
+
+pub const PC_MODIFIABLE: c_int = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VSFilterDependency {
+    pub source: *mut VSNode,
+    pub requestPattern: c_int,
+}
+
+pub type VSPublicFunction = unsafe extern "system" fn(
+    in_: *const VSMap,
+    out: *mut VSMap,
+    userData: *mut c_void,
+    core: *mut VSCore,
+    vsapi: *const VSAPI,
+);
+pub type VSFilterGetFrame = unsafe extern "system" fn(
+    n: c_int,
+    activationReason: c_int,
+    instanceData: *mut c_void,
+    frameData: *mut *mut c_void,
+    frameCtx: *mut VSFrameContext,
+    core: *mut VSCore,
+    vsapi: *const VSAPI,
+) -> *const VSFrame;
+pub type VSFilterFree =
+    unsafe extern "system" fn(instanceData: *mut c_void, core: *mut VSCore, vsapi: *const VSAPI);
+
+#[repr(C)]
+pub struct VSPLUGINAPI {
+    pub getAPIVersion: unsafe extern "system" fn() -> c_int,
+    pub configPlugin: unsafe extern "system" fn(
+        identifier: *const c_char,
+        pluginNamespace: *const c_char,
+        name: *const c_char,
+        pluginVersion: c_int,
+        apiVersion: c_int,
+        flags: c_int,
+        plugin: *mut VSPlugin,
+    ) -> c_int,
+    pub registerFunction: unsafe extern "system" fn(
+        name: *const c_char,
+        args: *const c_char,
+        returnType: *const c_char,
+        argsFunc: VSPublicFunction,
+        functionData: *mut c_void,
+        plugin: *mut VSPlugin,
+    ) -> c_int,
+}
+
+/// A function of `VSAPI` that the plugin does not call.
+type Unused = *const c_void;
+
+#[repr(C)]
+pub struct VSAPI {
+    pub createVideoFilter: unsafe extern "system" fn(
+        out: *mut VSMap,
+        name: *const c_char,
+        vi: *const VSVideoInfo,
+        getFrame: VSFilterGetFrame,
+        free: VSFilterFree,
+        filterMode: c_int,
+        dependencies: *const VSFilterDependency,
+        numDeps: c_int,
+        instanceData: *mut c_void,
+        core: *mut VSCore,
+    ),
+    createVideoFilter2: Unused,
+    createAudioFilter: Unused,
+    createAudioFilter2: Unused,
+    setLinearFilter: Unused,
+    setCacheMode: Unused,
+    setCacheOptions: Unused,
+
+    pub freeNode: unsafe extern "system" fn(node: *mut VSNode),
+    addNodeRef: Unused,
+    getNodeType: Unused,
+    pub getVideoInfo: unsafe extern "system" fn(node: *mut VSNode) -> *const VSVideoInfo,
+    getAudioInfo: Unused,
+
+    newVideoFrame: Unused,
+    newVideoFrame2: Unused,
+    newAudioFrame: Unused,
+    newAudioFrame2: Unused,
+    pub freeFrame: unsafe extern "system" fn(f: *const VSFrame),
+    addFrameRef: Unused,
+    pub copyFrame: unsafe extern "system" fn(f: *const VSFrame, core: *mut VSCore) -> *mut VSFrame,
+    pub getFramePropertiesRO: unsafe extern "system" fn(f: *const VSFrame) -> *const VSMap,
+    pub getFramePropertiesRW: unsafe extern "system" fn(f: *mut VSFrame) -> *mut VSMap,
+
+    pub getStride: unsafe extern "system" fn(f: *const VSFrame, plane: c_int) -> isize,
+    pub getReadPtr: unsafe extern "system" fn(f: *const VSFrame, plane: c_int) -> *const u8,
+    pub getWritePtr: unsafe extern "system" fn(f: *mut VSFrame, plane: c_int) -> *mut u8,
+
+    pub getVideoFrameFormat: unsafe extern "system" fn(f: *const VSFrame) -> *const VSVideoFormat,
+    getAudioFrameFormat: Unused,
+    getFrameType: Unused,
+    pub getFrameWidth: unsafe extern "system" fn(f: *const VSFrame, plane: c_int) -> c_int,
+    pub getFrameHeight: unsafe extern "system" fn(f: *const VSFrame, plane: c_int) -> c_int,
+    getFrameLength: Unused,
+
+    getVideoFormatName: Unused,
+    getAudioFormatName: Unused,
+    queryVideoFormat: Unused,
+    queryAudioFormat: Unused,
+    queryVideoFormatID: Unused,
+    getVideoFormatByID: Unused,
+
+    getFrame: Unused,
+    getFrameAsync: Unused,
+    pub getFrameFilter: unsafe extern "system" fn(
+        n: c_int,
+        node: *mut VSNode,
+        frameCtx: *mut VSFrameContext,
+    ) -> *const VSFrame,
+    pub requestFrameFilter:
+        unsafe extern "system" fn(n: c_int, node: *mut VSNode, frameCtx: *mut VSFrameContext),
+    releaseFrameEarly: Unused,
+    cacheFrame: Unused,
+    pub setFilterError:
+        unsafe extern "system" fn(errorMessage: *const c_char, frameCtx: *mut VSFrameContext),
+
+    createFunction: Unused,
+    freeFunction: Unused,
+    addFunctionRef: Unused,
+    callFunction: Unused,
+
+    createMap: Unused,
+    freeMap: Unused,
+    clearMap: Unused,
+    copyMap: Unused,
+
+    pub mapSetError: unsafe extern "system" fn(map: *mut VSMap, errorMessage: *const c_char),
+    mapGetError: Unused,
+
+    mapNumKeys: Unused,
+    mapGetKey: Unused,
+    mapDeleteKey: Unused,
+    pub mapNumElements: unsafe extern "system" fn(map: *const VSMap, key: *const c_char) -> c_int,
+    mapGetType: Unused,
+    mapSetEmpty: Unused,
+
+    pub mapGetInt: unsafe extern "system" fn(
+        map: *const VSMap,
+        key: *const c_char,
+        index: c_int,
+        error: *mut c_int,
+    ) -> i64,
+    mapGetIntSaturated: Unused,
+    mapGetIntArray: Unused,
+    pub mapSetInt: unsafe extern "system" fn(
+        map: *mut VSMap,
+        key: *const c_char,
+        i: i64,
+        append: c_int,
+    ) -> c_int,
+    mapSetIntArray: Unused,
+
+    pub mapGetFloat: unsafe extern "system" fn(
+        map: *const VSMap,
+        key: *const c_char,
+        index: c_int,
+        error: *mut c_int,
+    ) -> c_double,
+    mapGetFloatSaturated: Unused,
+    mapGetFloatArray: Unused,
+    mapSetFloat: Unused,
+    mapSetFloatArray: Unused,
+
+    pub mapGetData: unsafe extern "system" fn(
+        map: *const VSMap,
+        key: *const c_char,
+        index: c_int,
+        error: *mut c_int,
+    ) -> *const c_char,
+    pub mapGetDataSize: unsafe extern "system" fn(
+        map: *const VSMap,
+        key: *const c_char,
+        index: c_int,
+        error: *mut c_int,
+    ) -> c_int,
+    mapGetDataTypeHint: Unused,
+    mapSetData: Unused,
+
+    pub mapGetNode: unsafe extern "system" fn(
+        map: *const VSMap,
+        key: *const c_char,
+        index: c_int,
+        error: *mut c_int,
+    ) -> *mut VSNode,
+}
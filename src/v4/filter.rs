@@ -0,0 +1,129 @@
+use super::api::*;
+use super::ffi::*;
+use crate::error::Error;
+use crate::requests::{Parallelism, RequestPattern};
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+
+/// A filter instance created through the API v4.
+///
+/// Unlike with API 3.x, the threading model and the frames requested from
+/// each input are declared when the filter is created, which lets
+/// VapourSynth size its caches and schedule requests accordingly.
+pub(crate) trait Filter4: Send + Sync + Sized + 'static {
+    const NAME: &'static str;
+    const PARALLELISM: Parallelism;
+
+    fn video_info(&self) -> VSVideoInfo;
+
+    /// The input nodes, paired with the request pattern each is used with.
+    fn dependencies(&self) -> Vec<(&Node, RequestPattern)>;
+
+    /// Requests the input frames needed for output frame `n`.
+    fn request_frames(&self, n: usize, ctx: *mut VSFrameContext);
+
+    /// Produces output frame `n` once all requested frames are available.
+    fn get_frame(
+        &self,
+        n: usize,
+        ctx: *mut VSFrameContext,
+        core: *mut VSCore,
+    ) -> Result<FrameMut, Error>;
+}
+
+/// Creates a node running `filter` and stores it in `out` as `clip`.
+pub(crate) fn create_filter<F: Filter4>(api: Api, out: &mut MapMut, core: *mut VSCore, filter: F) {
+    let info = filter.video_info();
+    let deps: Vec<_> = filter
+        .dependencies()
+        .into_iter()
+        .map(|(node, pattern)| VSFilterDependency {
+            source: node.as_raw(),
+            requestPattern: match pattern {
+                RequestPattern::StrictSpatial => RP_STRICT_SPATIAL,
+                RequestPattern::Temporal(_) | RequestPattern::DoubleRate => RP_GENERAL,
+            },
+        })
+        .collect();
+    let mode = match F::PARALLELISM {
+        Parallelism::Parallel => FM_PARALLEL,
+        Parallelism::ParallelRequests => FM_PARALLEL_REQUESTS,
+    };
+    let name = CString::new(F::NAME).unwrap();
+    let instance = Box::into_raw(Box::new(filter));
+    unsafe {
+        (api.raw().createVideoFilter)(
+            out.as_raw(),
+            name.as_ptr(),
+            &info,
+            get_frame::<F>,
+            free::<F>,
+            mode,
+            deps.as_ptr(),
+            deps.len() as c_int,
+            instance as *mut c_void,
+            core,
+        );
+    }
+}
+
+/// Runs `f`, turning a panic into an error.
+///
+/// Every callback VapourSynth makes into us goes through this, as unwinding
+/// out of an `extern` function aborts the whole process.
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> Result<R, Error>) -> Result<R, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => (*message).to_owned(),
+                Err(_) => "unknown panic".to_owned(),
+            },
+        };
+        Err(Error::Panic(message))
+    })
+}
+
+unsafe extern "system" fn get_frame<F: Filter4>(
+    n: c_int,
+    activation_reason: c_int,
+    instance_data: *mut c_void,
+    _frame_data: *mut *mut c_void,
+    frame_ctx: *mut VSFrameContext,
+    core: *mut VSCore,
+    vsapi: *const VSAPI,
+) -> *const VSFrame {
+    let filter = &*(instance_data as *const F);
+    let result = catch_panic(|| match activation_reason {
+        AR_INITIAL => {
+            filter.request_frames(n as usize, frame_ctx);
+            Ok(None)
+        }
+        AR_ALL_FRAMES_READY => filter.get_frame(n as usize, frame_ctx, core).map(Some),
+        _ => Ok(None),
+    });
+    match result {
+        Ok(Some(frame)) => return frame.into_raw(),
+        Ok(None) => (),
+        Err(e) => {
+            let message = CString::new(e.to_string().replace('\0', "")).unwrap();
+            ((*vsapi).setFilterError)(message.as_ptr(), frame_ctx);
+        }
+    }
+    no_frame()
+}
+
+unsafe extern "system" fn free<F: Filter4>(
+    instance_data: *mut c_void,
+    _core: *mut VSCore,
+    _vsapi: *const VSAPI,
+) {
+    let filter = Box::from_raw(instance_data as *mut F);
+    // There is no frame or map left to report an error to, so a panic while
+    // dropping the filter is only kept from unwinding into VapourSynth
+    let _ = catch_panic(|| {
+        drop(filter);
+        Ok(())
+    });
+}
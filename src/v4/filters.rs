@@ -0,0 +1,473 @@
+use super::api::*;
+use super::ffi::*;
+use super::filter::Filter4;
+use crate::error::Error;
+use crate::planes::Planes;
+use crate::props::{output_field_based, output_props, FrameOverrides};
+use crate::requests::{Parallelism, RequestPattern};
+use crate::zones::Zone;
+use mpeg2stinx_core::{
+    mask_merge, subsample_mask, BobMode, Frame as CoreFrame, MaskMode, Pixel, Plane, PlaneMut,
+    PlaneRef, Rounding, Shape,
+};
+
+/// Checks that all `clips` share one constant integer format and size.
+pub(crate) fn check_clips(clips: &[&Node]) -> Result<(), Error> {
+    let first = clips[0].info();
+    if first.format.colorFamily == CF_UNDEFINED || first.width == 0 || first.height == 0 {
+        return Err(Error::unsupported_format(
+            "clips must have a constant format and size",
+        ));
+    }
+    if first.format.sampleType != ST_INTEGER {
+        return Err(Error::unsupported_format(
+            "only integer formats are supported",
+        ));
+    }
+    for (i, clip) in clips.iter().enumerate().skip(1) {
+        let info = clip.info();
+        let matches = info.format.colorFamily == first.format.colorFamily
+            && info.format.sampleType == first.format.sampleType
+            && info.format.bitsPerSample == first.format.bitsPerSample
+            && info.format.subSamplingW == first.format.subSamplingW
+            && info.format.subSamplingH == first.format.subSamplingH
+            && info.width == first.width
+            && info.height == first.height;
+        if !matches {
+            return Err(Error::invalid_argument(
+                format!("clip{}", i + 1),
+                "of the same format and size as clip1",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `mask` is an 8 or 16-bit Gray clip of the same size and
+/// length as `clip`.
+pub(crate) fn check_mask(clip: &Node, mask: &Node) -> Result<(), Error> {
+    let (info, format) = (mask.info(), mask.info().format);
+    if format.colorFamily != CF_GRAY
+        || format.sampleType != ST_INTEGER
+        || (format.bitsPerSample != 8 && format.bitsPerSample != 16)
+    {
+        return Err(Error::invalid_argument("mask", "an 8 or 16-bit Gray clip"));
+    }
+    if info.width != clip.info().width || info.height != clip.info().height {
+        return Err(Error::invalid_argument("mask", "of the same size as clip"));
+    }
+    if info.numFrames != clip.info().numFrames {
+        return Err(Error::invalid_argument(
+            "mask",
+            "as many frames long as clip",
+        ));
+    }
+    Ok(())
+}
+
+fn get_frame(
+    filter: &'static str,
+    input: &'static str,
+    clip: &Node,
+    n: usize,
+    ctx: *mut VSFrameContext,
+) -> Result<Frame, Error> {
+    clip.get_frame_filter(n, ctx).ok_or(Error::FrameFetch {
+        filter,
+        input,
+        frame: n,
+    })
+}
+
+fn to_core_frame<T: Pixel>(frame: &Frame) -> CoreFrame<T> {
+    let planes: Vec<_> = (0..frame.format().numPlanes as usize)
+        .map(|plane| frame.plane_ref::<T>(plane))
+        .collect();
+    CoreFrame::from_refs(&planes, frame.format().bitsPerSample as u32)
+}
+
+pub(crate) struct Mpeg2Stinx {
+    pub src: Node,
+    // `params[i]` is used for `zones[i - 1]`, `params[0]` everywhere else
+    pub params: Vec<mpeg2stinx_core::Params>,
+    pub zones: Vec<Zone>,
+    /// The planes taken from the output of `mpeg2stinx_core`,
+    /// the others keep the source.
+    pub planes: Planes,
+    /// The mic threshold if VFM's properties are consulted, see `vfm_from_args`.
+    pub vfm_mic: Option<i64>,
+    /// The protection mask, see `check_mask`.
+    pub mask: Option<Node>,
+    pub mask_mode: MaskMode,
+}
+
+impl Mpeg2Stinx {
+    fn params(&self, n: usize) -> &mpeg2stinx_core::Params {
+        match self.zones.iter().position(|zone| zone.contains(n)) {
+            Some(i) => &self.params[i + 1],
+            None => &self.params[0],
+        }
+    }
+
+    fn neighbours(&self, n: usize) -> (usize, usize) {
+        let last = (self.src.info().numFrames as usize).saturating_sub(1);
+        (n.saturating_sub(1), (n + 1).min(last))
+    }
+
+    fn src_pattern(params: &mpeg2stinx_core::Params) -> RequestPattern {
+        // Temporal limiting compares each frame to its neighbours
+        if params.diffscl.is_some() {
+            RequestPattern::Temporal(1)
+        } else {
+            RequestPattern::StrictSpatial
+        }
+    }
+}
+
+fn run<T: Pixel>(
+    prev: &Frame,
+    src: &Frame,
+    next: &Frame,
+    params: &mpeg2stinx_core::Params,
+    planes: Planes,
+    dst: &mut FrameMut,
+) {
+    let cur = to_core_frame::<T>(src);
+    let output = if params.diffscl.is_some() {
+        mpeg2stinx_core::mpeg2stinx(&to_core_frame(prev), &cur, &to_core_frame(next), params)
+    } else {
+        mpeg2stinx_core::mpeg2stinx(&cur, &cur, &cur, params)
+    };
+    for plane in planes.filter(0..output.planes.len()) {
+        dst.plane_mut::<T>(plane)
+            .copy_from(output.planes[plane].as_ref());
+    }
+}
+
+// Returns `src` again where `mask` protects it, see `mask_merge`
+fn merge_mask<T: Pixel, M: Pixel>(
+    src: &Frame,
+    mask: &Frame,
+    mode: MaskMode,
+    planes: Planes,
+    dst: &mut FrameMut,
+) {
+    let format = src.format();
+    let mask_bits = mask.format().bitsPerSample as u32;
+    let luma = mask.plane_ref::<M>(0);
+    let (sub_w, sub_h) = (format.subSamplingW as u32, format.subSamplingH as u32);
+    let chroma = if (sub_w, sub_h) != (0, 0) {
+        Some(subsample_mask(luma, sub_w, sub_h))
+    } else {
+        None
+    };
+
+    for plane in planes.filter(0..format.numPlanes as usize) {
+        let mask = match chroma {
+            Some(ref chroma) if plane > 0 => chroma.as_ref(),
+            _ => luma,
+        };
+        // `mask_merge` can't work in place
+        let processed = Plane::from_ref(dst.plane_ref::<T>(plane));
+        mask_merge(
+            processed.as_ref(),
+            src.plane_ref::<T>(plane),
+            mask,
+            dst.plane_mut::<T>(plane),
+            mask_bits,
+            mode,
+        );
+    }
+}
+
+impl Filter4 for Mpeg2Stinx {
+    const NAME: &'static str = "Mpeg2Stinx";
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn video_info(&self) -> VSVideoInfo {
+        *self.src.info()
+    }
+
+    fn dependencies(&self) -> Vec<(&Node, RequestPattern)> {
+        // The most general pattern used by any zone
+        let pattern = self.params.iter().map(Self::src_pattern).max().unwrap();
+        let mut dependencies = vec![(&self.src, pattern)];
+        if let Some(ref mask) = self.mask {
+            dependencies.push((mask, RequestPattern::StrictSpatial));
+        }
+        dependencies
+    }
+
+    fn request_frames(&self, n: usize, ctx: *mut VSFrameContext) {
+        let num_frames = self.src.info().numFrames as usize;
+        for frame in Self::src_pattern(self.params(n)).frames(n, num_frames) {
+            self.src.request_frame_filter(frame, ctx);
+        }
+        if let Some(ref mask) = self.mask {
+            mask.request_frame_filter(n, ctx);
+        }
+    }
+
+    fn get_frame(
+        &self,
+        n: usize,
+        ctx: *mut VSFrameContext,
+        core: *mut VSCore,
+    ) -> Result<FrameMut, Error> {
+        let src = get_frame(Self::NAME, "clip", &self.src, n, ctx)?;
+        let mut output = FrameMut::copy_of(&src, core);
+
+        let overrides = FrameOverrides::from_props(&src.props(), self.vfm_mic);
+        let applied = !overrides.pass_through();
+        if applied {
+            let mut params = *self.params(n);
+            let (sstr, scl) = overrides.sharpening(params.sstr, params.scl);
+            params.sstr = sstr;
+            params.scl = scl;
+            let (prev, next) = if params.diffscl.is_some() {
+                let (prev, next) = self.neighbours(n);
+                (
+                    Some(get_frame(Self::NAME, "clip", &self.src, prev, ctx)?),
+                    Some(get_frame(Self::NAME, "clip", &self.src, next, ctx)?),
+                )
+            } else {
+                (None, None)
+            };
+            let prev = prev.as_ref().unwrap_or(&src);
+            let next = next.as_ref().unwrap_or(&src);
+            match src.format().bytesPerSample {
+                1 => run::<u8>(prev, &src, next, &params, self.planes, &mut output),
+                2 => run::<u16>(prev, &src, next, &params, self.planes, &mut output),
+                4 => run::<u32>(prev, &src, next, &params, self.planes, &mut output),
+                _ => unreachable!(),
+            }
+
+            if let Some(ref mask) = self.mask {
+                let mask = get_frame(Self::NAME, "mask", mask, n, ctx)?;
+                let (mode, planes) = (self.mask_mode, self.planes);
+                match (src.format().bytesPerSample, mask.format().bytesPerSample) {
+                    (1, 1) => merge_mask::<u8, u8>(&src, &mask, mode, planes, &mut output),
+                    (1, 2) => merge_mask::<u8, u16>(&src, &mask, mode, planes, &mut output),
+                    (2, 1) => merge_mask::<u16, u8>(&src, &mask, mode, planes, &mut output),
+                    (2, 2) => merge_mask::<u16, u16>(&src, &mask, mode, planes, &mut output),
+                    (4, 1) => merge_mask::<u32, u8>(&src, &mask, mode, planes, &mut output),
+                    (4, 2) => merge_mask::<u32, u16>(&src, &mask, mode, planes, &mut output),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        let field_based = output_field_based(self.params(n).interlaced, self.params(n).tff);
+        let mut props = output.props_mut();
+        for (key, value) in output_props(applied, field_based, overrides.vfm) {
+            props.set_int(key, value);
+        }
+        Ok(output)
+    }
+}
+
+/// A per-pixel kernel of `mpeg2stinx_core` applied to same-sized clips.
+pub(crate) trait PlaneOp: Send + Sync + 'static {
+    const NAME: &'static str;
+
+    /// The name of the argument that input `index` was passed as.
+    fn input(index: usize) -> &'static str {
+        ["clip1", "clip2", "clip3"][index]
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, bit_depth: u32);
+}
+
+pub(crate) struct MinOp;
+pub(crate) struct MaxOp;
+pub(crate) struct Median3Op;
+pub(crate) struct DiffOp;
+pub(crate) struct AverageOp {
+    pub weights: Vec<f64>,
+    pub scale: f64,
+    pub rounding: Rounding,
+}
+
+pub(crate) struct MedianOp;
+
+pub(crate) struct CueOp {
+    pub mode: BobMode,
+    pub threshold: f64,
+}
+
+pub(crate) struct ExpandOp {
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+}
+
+pub(crate) struct InpandOp {
+    pub sw: u32,
+    pub sh: u32,
+    pub shape: Shape,
+}
+
+impl PlaneOp for MinOp {
+    const NAME: &'static str = "Min";
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, _bit_depth: u32) {
+        mpeg2stinx_core::min(src[0], src[1], dst)
+    }
+}
+
+impl PlaneOp for MaxOp {
+    const NAME: &'static str = "Max";
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, _bit_depth: u32) {
+        mpeg2stinx_core::max(src[0], src[1], dst)
+    }
+}
+
+impl PlaneOp for Median3Op {
+    const NAME: &'static str = "Median3";
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, _bit_depth: u32) {
+        mpeg2stinx_core::median3(src[0], src[1], src[2], dst)
+    }
+}
+
+impl PlaneOp for DiffOp {
+    const NAME: &'static str = "LutXYDiff";
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, bit_depth: u32) {
+        mpeg2stinx_core::diff(src[0], src[1], dst, bit_depth)
+    }
+}
+
+impl PlaneOp for AverageOp {
+    const NAME: &'static str = "AverageFrames";
+
+    fn input(_index: usize) -> &'static str {
+        "clips"
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, bit_depth: u32) {
+        mpeg2stinx_core::weighted_average(
+            src,
+            &self.weights,
+            self.scale,
+            self.rounding,
+            bit_depth,
+            dst,
+        )
+    }
+}
+
+impl PlaneOp for MedianOp {
+    const NAME: &'static str = "MedianFrames";
+
+    fn input(_index: usize) -> &'static str {
+        "clips"
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], dst: PlaneMut<T>, _bit_depth: u32) {
+        mpeg2stinx_core::median(src, dst)
+    }
+}
+
+impl PlaneOp for CueOp {
+    const NAME: &'static str = "CUERepair";
+
+    fn input(_index: usize) -> &'static str {
+        "clip"
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], mut dst: PlaneMut<T>, bit_depth: u32) {
+        let repaired = mpeg2stinx_core::cue_repair(src[0], self.mode, self.threshold, bit_depth);
+        dst.copy_from(repaired.as_ref())
+    }
+}
+
+impl PlaneOp for ExpandOp {
+    const NAME: &'static str = "Expand";
+
+    fn input(_index: usize) -> &'static str {
+        "clip"
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], mut dst: PlaneMut<T>, _bit_depth: u32) {
+        let expanded = mpeg2stinx_core::expand_shape(src[0], self.sw, self.sh, self.shape);
+        dst.copy_from(expanded.as_ref())
+    }
+}
+
+impl PlaneOp for InpandOp {
+    const NAME: &'static str = "Inpand";
+
+    fn input(_index: usize) -> &'static str {
+        "clip"
+    }
+
+    fn apply<T: Pixel>(&self, src: &[PlaneRef<T>], mut dst: PlaneMut<T>, _bit_depth: u32) {
+        let inpanded = mpeg2stinx_core::inpand_shape(src[0], self.sw, self.sh, self.shape);
+        dst.copy_from(inpanded.as_ref())
+    }
+}
+
+/// Runs `op` on frame n of each of `clips`.
+pub(crate) struct PlaneFilter<O> {
+    pub clips: Vec<Node>,
+    pub op: O,
+    /// The rest of the planes are copied from the first clip.
+    pub planes: Planes,
+}
+
+impl<O: PlaneOp> PlaneFilter<O> {
+    fn apply<T: Pixel>(&self, frames: &[Frame], dst: &mut FrameMut) {
+        let format = frames[0].format();
+        let bit_depth = format.bitsPerSample as u32;
+        for plane in self.planes.filter(0..format.numPlanes as usize) {
+            let src: Vec<_> = frames.iter().map(|f| f.plane_ref::<T>(plane)).collect();
+            self.op.apply(&src, dst.plane_mut::<T>(plane), bit_depth);
+        }
+    }
+}
+
+impl<O: PlaneOp> Filter4 for PlaneFilter<O> {
+    const NAME: &'static str = O::NAME;
+    const PARALLELISM: Parallelism = Parallelism::Parallel;
+
+    fn video_info(&self) -> VSVideoInfo {
+        *self.clips[0].info()
+    }
+
+    fn dependencies(&self) -> Vec<(&Node, RequestPattern)> {
+        self.clips
+            .iter()
+            .map(|clip| (clip, RequestPattern::StrictSpatial))
+            .collect()
+    }
+
+    fn request_frames(&self, n: usize, ctx: *mut VSFrameContext) {
+        for clip in &self.clips {
+            clip.request_frame_filter(n, ctx);
+        }
+    }
+
+    fn get_frame(
+        &self,
+        n: usize,
+        ctx: *mut VSFrameContext,
+        core: *mut VSCore,
+    ) -> Result<FrameMut, Error> {
+        let frames = self
+            .clips
+            .iter()
+            .enumerate()
+            .map(|(i, clip)| get_frame(Self::NAME, O::input(i), clip, n, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut output = FrameMut::copy_of(&frames[0], core);
+        match frames[0].format().bytesPerSample {
+            1 => self.apply::<u8>(&frames, &mut output),
+            2 => self.apply::<u16>(&frames, &mut output),
+            4 => self.apply::<u32>(&frames, &mut output),
+            _ => unreachable!(),
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,401 @@
+//! Support for the VapourSynth API v4 used by R55 and later.
+//!
+//! vapoursynth-rs only speaks API 3.x, so this talks to VapourSynth
+//! directly through `ffi`. VapourSynth prefers the `VapourSynthPluginInit2`
+//! entry point exported here whenever it supports it, and falls back to the
+//! API 3.x entry point of `lib.rs` otherwise.
+//!
+//! The filter chain of `fused=0` is built from vapoursynth-rs nodes, and
+//! hasn't been ported to this API yet, although `getPluginByID` and `invoke`
+//! would reach nnedi3, yadifmod and std just as well. Until it is,
+//! `Mpeg2Stinx` always runs the native pipeline of `mpeg2stinx_core`, as with
+//! `fused=1` under API 3.x, and rejects the arguments only the chain
+//! implements instead of ignoring them.
+
+mod api;
+mod ffi;
+mod filter;
+mod filters;
+
+use self::api::*;
+use self::ffi::*;
+use self::filter::{catch_panic, create_filter, Filter4};
+use self::filters::*;
+use crate::average::average_params;
+use crate::cue::cue_params;
+use crate::deint::FilterMode;
+use crate::error::Error;
+use crate::fused::core_params;
+use crate::mask::mask_mode_from_arg;
+use crate::morphology::{radius_from_arg, shape_from_args};
+use crate::planes::Planes;
+use crate::props::vfm_from_args;
+use crate::stage::Params;
+use crate::zones::read_zones;
+use crate::MPEG2STINX_NAMESPACE;
+use mpeg2stinx_core::Shape;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+
+const MPEG2STINX_ARGS: &str = "clip:vnode;mode:int:opt;sw:int:opt;sh:int:opt;contra:int:opt;\
+     blurv:float:opt;sstr:float:opt;scl:float:opt;dither:int:opt;order:int:opt;\
+     diffscl:float:opt;zones:data:opt;zones_file:data:opt;fused:int:opt;planes:int[]:opt;\
+     sw_c:int:opt;sh_c:int:opt;blurv_c:float:opt;sstr_c:float:opt;scl_c:float:opt;\
+     shape:int:opt;coordinates:int[]:opt;passes:int:opt;combine:int:opt;tff:int:opt;\
+     interlaced:int:opt;vfm:int:opt;vfm_mic:int:opt;\
+     mask:vnode:opt;mask_blend:int:opt;";
+const RETURN_CLIP: &str = "clip:vnode;";
+
+fn plugin_version() -> i32 {
+    let major: i32 = env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap();
+    let minor: i32 = env!("CARGO_PKG_VERSION_MINOR").parse().unwrap();
+    (major << 16) | minor
+}
+
+fn get_clip(args: &MapRef, key: &str, index: usize) -> Result<Node, Error> {
+    args.get_node(key, index)
+        .ok_or_else(|| Error::invalid_argument(key, "a clip"))
+}
+
+/// The `planes` argument, or `default` if it is not given.
+fn get_planes(args: &MapRef, default: Planes) -> Result<Planes, Error> {
+    match args.get_ints("planes") {
+        Some(planes) => Planes::from_arg(Some(planes)),
+        None => Ok(default),
+    }
+}
+
+/// Checks that `params` only asks for what the native pipeline does,
+/// the only one ported to this API, then converts them.
+///
+/// `name` gives the argument, or the zone parameter, each error is about.
+fn v4_params(
+    params: &Params,
+    name: impl Fn(&str) -> String,
+) -> Result<mpeg2stinx_core::Params, Error> {
+    if let FilterMode::Nnedi3 | FilterMode::Nnedi3CL = params.mode {
+        return Err(Error::invalid_argument(
+            name("mode"),
+            "0 or 1 through the API v4",
+        ));
+    }
+    if params.order != -1 {
+        return Err(Error::invalid_argument(
+            name("order"),
+            "-1 through the API v4",
+        ));
+    }
+    if params.dither {
+        return Err(Error::invalid_argument(
+            name("dither"),
+            "0 through the API v4",
+        ));
+    }
+    core_params(params)
+}
+
+fn create_mpeg2stinx(args: &MapRef) -> Result<Mpeg2Stinx, Error> {
+    let src = get_clip(args, "clip", 0)?;
+    check_clips(&[&src])?;
+
+    if args.get_int("fused") == Some(0) {
+        return Err(Error::invalid_argument("fused", "1 through the API v4"));
+    }
+    let mut params = Params::from_args(
+        args.get_int("mode"),
+        args.get_int("sw"),
+        args.get_int("sh"),
+        args.get_int("contra"),
+        args.get_float("blurv"),
+        args.get_float("sstr"),
+        args.get_float("scl"),
+        args.get_int("dither"),
+        args.get_int("order"),
+        args.get_float("diffscl"),
+        args.get_int("sw_c"),
+        args.get_int("sh_c"),
+        args.get_float("blurv_c"),
+        args.get_float("sstr_c"),
+        args.get_float("scl_c"),
+        args.get_int("passes"),
+        args.get_int("combine"),
+        args.get_int("tff"),
+        args.get_int("interlaced"),
+    )?;
+    params.planes = Planes::from_arg(args.get_ints("planes"))?;
+    params.shape = shape_from_args(args.get_int("shape"), args.get_ints("coordinates"))?;
+    let zones = read_zones(
+        args.get_data("zones"),
+        args.get_data("zones_file"),
+        &params,
+        src.info().numFrames as usize,
+    )?;
+    let vfm_mic = vfm_from_args(args.get_int("vfm"), args.get_int("vfm_mic"))?;
+    let mask = args.get_node("mask", 0);
+    if let Some(ref mask) = mask {
+        check_mask(&src, mask)?;
+    }
+
+    let mut stages = Vec::with_capacity(zones.len() + 1);
+    stages.push(v4_params(&params, str::to_owned)?);
+    for zone in &zones {
+        stages.push(v4_params(&zone.params, |key| {
+            format!("{} in zone {}-{}", key, zone.start, zone.end)
+        })?);
+    }
+    Ok(Mpeg2Stinx {
+        src,
+        params: stages,
+        zones,
+        planes: params.planes,
+        vfm_mic,
+        mask,
+        mask_mode: mask_mode_from_arg(args.get_int("mask_blend")),
+    })
+}
+
+fn create_plane_filter<O: PlaneOp>(
+    args: &MapRef,
+    clips: usize,
+    op: O,
+    default_planes: Planes,
+) -> Result<PlaneFilter<O>, Error> {
+    let clips = (1..=clips)
+        .map(|i| get_clip(args, &format!("clip{}", i), 0))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_clips(&clips.iter().collect::<Vec<_>>())?;
+    Ok(PlaneFilter {
+        clips,
+        op,
+        planes: get_planes(args, default_planes)?,
+    })
+}
+
+fn create_average_frames(args: &MapRef) -> Result<PlaneFilter<AverageOp>, Error> {
+    let clips = (0..args.num_elements("clips"))
+        .map(|i| get_clip(args, "clips", i))
+        .collect::<Result<Vec<_>, _>>()?;
+    if clips.is_empty() {
+        return Err(Error::invalid_argument("clips", "at least one clip"));
+    }
+    check_clips(&clips.iter().collect::<Vec<_>>())?;
+    let (weights, scale, rounding) = average_params(
+        clips.len(),
+        args.get_floats("weights"),
+        args.get_float("scale"),
+        args.get_int("rounding"),
+    )?;
+    Ok(PlaneFilter {
+        clips,
+        op: AverageOp {
+            weights,
+            scale,
+            rounding,
+        },
+        planes: get_planes(args, Planes::ALL)?,
+    })
+}
+
+fn create_median_frames(args: &MapRef) -> Result<PlaneFilter<MedianOp>, Error> {
+    let clips = (0..args.num_elements("clips"))
+        .map(|i| get_clip(args, "clips", i))
+        .collect::<Result<Vec<_>, _>>()?;
+    if clips.is_empty() {
+        return Err(Error::invalid_argument("clips", "at least one clip"));
+    }
+    check_clips(&clips.iter().collect::<Vec<_>>())?;
+    Ok(PlaneFilter {
+        clips,
+        op: MedianOp,
+        planes: get_planes(args, Planes::ALL)?,
+    })
+}
+
+fn create_cue_repair(args: &MapRef) -> Result<PlaneFilter<CueOp>, Error> {
+    let clip = get_clip(args, "clip", 0)?;
+    check_clips(&[&clip])?;
+    let info = clip.info();
+    if info.format.colorFamily != CF_YUV || info.format.subSamplingH != 1 {
+        return Err(Error::unsupported_format(
+            "CUERepair requires YUV with vertically subsampled chroma, like 4:2:0",
+        ));
+    }
+    if info.height % 4 != 0 {
+        return Err(Error::unsupported_format(
+            "CUERepair requires a constant height divisible by 4",
+        ));
+    }
+    let (mode, threshold) = cue_params(args.get_int("mode"), args.get_float("threshold"))?;
+    Ok(PlaneFilter {
+        clips: vec![clip],
+        op: CueOp { mode, threshold },
+        planes: Planes::CHROMA,
+    })
+}
+
+fn create_morphology<O: PlaneOp>(
+    args: &MapRef,
+    op: impl FnOnce(u32, u32, Shape) -> O,
+) -> Result<PlaneFilter<O>, Error> {
+    let clip = get_clip(args, "clip", 0)?;
+    check_clips(&[&clip])?;
+    let sw = radius_from_arg("sw", args.get_int("sw").unwrap_or(1))?;
+    let sh = radius_from_arg("sh", args.get_int("sh").unwrap_or(1))?;
+    let shape = shape_from_args(args.get_int("shape"), args.get_ints("coordinates"))?;
+    Ok(PlaneFilter {
+        clips: vec![clip],
+        op: op(sw, sh, shape),
+        planes: get_planes(args, Planes::ALL)?,
+    })
+}
+
+/// Defines a `VSPublicFunction` that creates the filter returned by `$create`,
+/// or reports its error, or a panic, to the caller.
+macro_rules! public_function {
+    ($name:ident, |$args:ident| $create:expr) => {
+        unsafe extern "system" fn $name(
+            in_: *const VSMap,
+            out: *mut VSMap,
+            _user_data: *mut c_void,
+            core: *mut VSCore,
+            vsapi: *const VSAPI,
+        ) {
+            let api = Api::from_raw(vsapi);
+            let $args = MapRef::from_raw(api, in_);
+            let mut out = MapMut::from_raw(api, out);
+            let result = catch_panic(|| {
+                let filter = $create?;
+                create_filter(api, &mut out, core, filter);
+                Ok(())
+            });
+            if let Err(e) = result {
+                out.set_error(&e.to_string());
+            }
+        }
+    };
+}
+
+public_function!(mpeg2stinx_function, |args| create_mpeg2stinx(&args));
+public_function!(min_function, |args| create_plane_filter(
+    &args,
+    2,
+    MinOp,
+    Planes::ALL
+));
+public_function!(max_function, |args| create_plane_filter(
+    &args,
+    2,
+    MaxOp,
+    Planes::ALL
+));
+public_function!(median3_function, |args| {
+    let process_chroma = args.get_int("process_chroma").unwrap_or(0) > 0;
+    create_plane_filter(
+        &args,
+        3,
+        Median3Op,
+        Planes::from_process_chroma(process_chroma),
+    )
+});
+public_function!(average_frames_function, |args| create_average_frames(&args));
+public_function!(median_frames_function, |args| create_median_frames(&args));
+public_function!(cue_repair_function, |args| create_cue_repair(&args));
+public_function!(expand_function, |args| create_morphology(
+    &args,
+    |sw, sh, shape| ExpandOp { sw, sh, shape }
+));
+public_function!(inpand_function, |args| create_morphology(
+    &args,
+    |sw, sh, shape| InpandOp { sw, sh, shape }
+));
+public_function!(lutxy_diff_function, |args| create_plane_filter(
+    &args,
+    2,
+    DiffOp,
+    Planes::ALL
+));
+
+/// The API v4 entry point.
+///
+/// # Safety
+/// Only to be called by VapourSynth, with a valid plugin and plugin API.
+#[no_mangle]
+pub unsafe extern "system" fn VapourSynthPluginInit2(
+    plugin: *mut VSPlugin,
+    vspapi: *const VSPLUGINAPI,
+) {
+    let vspapi = &*vspapi;
+    let identifier = CString::new(MPEG2STINX_NAMESPACE).unwrap();
+    (vspapi.configPlugin)(
+        identifier.as_ptr(),
+        b"mpeg2stinx\0".as_ptr() as *const _,
+        b"Mpeg2Stinx\0".as_ptr() as *const _,
+        plugin_version(),
+        VAPOURSYNTH_API_VERSION,
+        0,
+        plugin,
+    );
+
+    let functions: [(&str, &str, VSPublicFunction); 10] = [
+        (Mpeg2Stinx::NAME, MPEG2STINX_ARGS, mpeg2stinx_function),
+        (
+            MinOp::NAME,
+            "clip1:vnode;clip2:vnode;planes:int[]:opt;",
+            min_function,
+        ),
+        (
+            MaxOp::NAME,
+            "clip1:vnode;clip2:vnode;planes:int[]:opt;",
+            max_function,
+        ),
+        (
+            Median3Op::NAME,
+            "clip1:vnode;clip2:vnode;clip3:vnode;process_chroma:int:opt;planes:int[]:opt;",
+            median3_function,
+        ),
+        (
+            DiffOp::NAME,
+            "clip1:vnode;clip2:vnode;planes:int[]:opt;",
+            lutxy_diff_function,
+        ),
+        (
+            AverageOp::NAME,
+            "clips:vnode[];weights:float[]:opt;scale:float:opt;rounding:int:opt;planes:int[]:opt;",
+            average_frames_function,
+        ),
+        (
+            MedianOp::NAME,
+            "clips:vnode[];planes:int[]:opt;",
+            median_frames_function,
+        ),
+        (
+            CueOp::NAME,
+            "clip:vnode;mode:int:opt;threshold:float:opt;",
+            cue_repair_function,
+        ),
+        (
+            ExpandOp::NAME,
+            "clip:vnode;sw:int:opt;sh:int:opt;shape:int:opt;planes:int[]:opt;coordinates:int[]:opt;",
+            expand_function,
+        ),
+        (
+            InpandOp::NAME,
+            "clip:vnode;sw:int:opt;sh:int:opt;shape:int:opt;planes:int[]:opt;coordinates:int[]:opt;",
+            inpand_function,
+        ),
+    ];
+    let return_type = CString::new(RETURN_CLIP).unwrap();
+    for (name, args, function) in functions.iter() {
+        let name = CString::new(*name).unwrap();
+        let args = CString::new(*args).unwrap();
+        (vspapi.registerFunction)(
+            name.as_ptr(),
+            args.as_ptr(),
+            return_type.as_ptr(),
+            *function,
+            ptr::null_mut(),
+            plugin,
+        );
+    }
+}
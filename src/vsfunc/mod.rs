@@ -1,9 +1,39 @@
-mod misc;
 mod resize;
 mod rgvs;
 mod std;
 
-pub(crate) use self::misc::*;
 pub(crate) use self::resize::*;
 pub(crate) use self::rgvs::*;
 pub(crate) use self::std::*;
+
+use crate::error::Error;
+use vapoursynth::core::CoreRef;
+use vapoursynth::map::OwnedMap;
+use vapoursynth::node::Node;
+use vapoursynth::plugin::Plugin;
+
+/// Looks up a plugin by its identifier, reporting it as missing by `namespace`.
+pub(crate) fn get_plugin<'core>(
+    core: CoreRef<'core>,
+    identifier: &'static str,
+    namespace: &'static str,
+) -> Result<Plugin<'core>, Error> {
+    core.get_plugin_by_id(identifier)?
+        .ok_or(Error::MissingPlugin {
+            identifier,
+            namespace,
+        })
+}
+
+/// Invokes `function` of `plugin` and returns the clip it produced.
+pub(crate) fn invoke<'core>(
+    plugin: &Plugin<'core>,
+    function: &str,
+    args: &OwnedMap<'core>,
+) -> Result<Node<'core>, Error> {
+    let result = plugin.invoke(function, args)?;
+    if let Some(message) = result.error() {
+        return Err(Error::invoke(function, message));
+    }
+    Ok(result.get_node("clip")?)
+}
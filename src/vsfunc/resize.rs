@@ -1,5 +1,5 @@
-use failure::Error;
-use failure::{bail, format_err};
+use crate::error::Error;
+use crate::vsfunc::{get_plugin, invoke};
 use vapoursynth::core::CoreRef;
 use vapoursynth::prelude::*;
 
@@ -12,20 +12,13 @@ pub(crate) fn point_resize<'core>(
     width: i64,
     height: i64,
 ) -> Result<Node<'core>, Error> {
-    let resize = core
-        .get_plugin_by_id(RESIZE_NAMESPACE)
-        .map_err(Error::from)?
-        .ok_or_else(|| format_err!("resize namespace not found"))?;
+    let resize = get_plugin(core, RESIZE_NAMESPACE, "resize")?;
 
     let mut args = OwnedMap::new(api);
     args.set_node("clip", &*clip)?;
     args.set_int("width", width)?;
     args.set_int("height", height)?;
-    let result = resize.invoke("Point", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    invoke(&resize, "Point", &args)
 }
 
 pub(crate) fn bilinear_resize<'core>(
@@ -35,20 +28,13 @@ pub(crate) fn bilinear_resize<'core>(
     width: i64,
     height: i64,
 ) -> Result<Node<'core>, Error> {
-    let resize = core
-        .get_plugin_by_id(RESIZE_NAMESPACE)
-        .map_err(Error::from)?
-        .ok_or_else(|| format_err!("resize namespace not found"))?;
+    let resize = get_plugin(core, RESIZE_NAMESPACE, "resize")?;
 
     let mut args = OwnedMap::new(api);
     args.set_node("clip", &*clip)?;
     args.set_int("width", width)?;
     args.set_int("height", height)?;
-    let result = resize.invoke("Bilinear", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    invoke(&resize, "Bilinear", &args)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -63,10 +49,7 @@ pub(crate) fn spline36_resize_crop<'core>(
     src_width: f64,
     src_height: f64,
 ) -> Result<Node<'core>, Error> {
-    let resize = core
-        .get_plugin_by_id(RESIZE_NAMESPACE)
-        .map_err(Error::from)?
-        .ok_or_else(|| format_err!("resize namespace not found"))?;
+    let resize = get_plugin(core, RESIZE_NAMESPACE, "resize")?;
 
     let mut args = OwnedMap::new(api);
     args.set_node("clip", &*clip)?;
@@ -76,11 +59,7 @@ pub(crate) fn spline36_resize_crop<'core>(
     args.set_float("src_top", src_top)?;
     args.set_float("src_width", src_width)?;
     args.set_float("src_height", src_height)?;
-    let result = resize.invoke("Spline36", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    invoke(&resize, "Spline36", &args)
 }
 
 pub(crate) fn convert<'core>(
@@ -89,17 +68,10 @@ pub(crate) fn convert<'core>(
     clip: &Node<'core>,
     format: i32,
 ) -> Result<Node<'core>, Error> {
-    let resize = core
-        .get_plugin_by_id(RESIZE_NAMESPACE)
-        .map_err(Error::from)?
-        .ok_or_else(|| format_err!("resize namespace not found"))?;
+    let resize = get_plugin(core, RESIZE_NAMESPACE, "resize")?;
 
     let mut args = OwnedMap::new(api);
     args.set_node("clip", &*clip)?;
     args.set_int("format", format as i64)?;
-    let result = resize.invoke("Spline36", &args).map_err(Error::from)?;
-    if let Some(e) = result.error() {
-        bail!("{}", e);
-    }
-    result.get_node("clip").map_err(Error::from)
+    invoke(&resize, "Spline36", &args)
 }
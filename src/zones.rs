@@ -0,0 +1,151 @@
+use crate::error::Error;
+use crate::stage::Params;
+use std::fs;
+
+/// A frame range, inclusive on both ends, using its own set of parameters.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Zone {
+    pub start: usize,
+    pub end: usize,
+    pub params: Params,
+}
+
+impl Zone {
+    pub(crate) fn contains(&self, n: usize) -> bool {
+        n >= self.start && n <= self.end
+    }
+}
+
+/// Reads the zones given inline through `zones` and from the file named by `zones_file`,
+/// for a clip of `num_frames` frames.
+pub(crate) fn read_zones(
+    zones: Option<&[u8]>,
+    zones_file: Option<&[u8]>,
+    base: &Params,
+    num_frames: usize,
+) -> Result<Vec<Zone>, Error> {
+    let mut zone_defs = String::new();
+    if let Some(zones) = zones {
+        zone_defs.push_str(&String::from_utf8_lossy(zones));
+        zone_defs.push('\n');
+    }
+    if let Some(zones_file) = zones_file {
+        let path = String::from_utf8_lossy(zones_file);
+        let contents = fs::read_to_string(&*path).map_err(|source| Error::ZonesFile {
+            path: path.to_string(),
+            source,
+        })?;
+        zone_defs.push_str(&contents);
+    }
+    parse_zones(&zone_defs, base, num_frames)
+}
+
+/// Parses a zones definition on top of the base `params`.
+///
+/// Zones are separated by semicolons or newlines, and each zone takes the form
+/// `start end key=value [key=value ...]`, e.g. `0 499 sw=2 sh=2; 500 650 contra=0`.
+/// Any parameter not overridden by a zone is inherited from the base parameters.
+/// Everything after a `#` on a line is ignored. Zones must lie within the
+/// `num_frames` frames of the clip.
+pub(crate) fn parse_zones(
+    input: &str,
+    base: &Params,
+    num_frames: usize,
+) -> Result<Vec<Zone>, Error> {
+    let mut zones: Vec<Zone> = Vec::new();
+    for line in input.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        for def in line.split(';') {
+            let mut tokens = def.split_whitespace();
+            let start = match tokens.next() {
+                Some(start) => parse_frame(start)?,
+                None => continue,
+            };
+            let end = match tokens.next() {
+                Some(end) => parse_frame(end)?,
+                None => {
+                    return Err(Error::InvalidZones(format!(
+                        "zone starting at {} has no end frame",
+                        start
+                    )))
+                }
+            };
+            if start > end {
+                return Err(Error::InvalidZones(format!(
+                    "zone {}-{} ends before it starts",
+                    start, end
+                )));
+            }
+            if end >= num_frames {
+                return Err(Error::InvalidZones(format!(
+                    "zone {}-{} ends past the last frame, {}",
+                    start,
+                    end,
+                    num_frames as i64 - 1
+                )));
+            }
+
+            let mut params = *base;
+            for token in tokens {
+                let mut kv = token.splitn(2, '=');
+                let key = kv.next().unwrap_or("");
+                let value = kv.next().ok_or_else(|| {
+                    Error::InvalidZones(format!(
+                        "expected key=value in zone {}-{}, found '{}'",
+                        start, end, token
+                    ))
+                })?;
+                params.apply_override(key, value)?;
+            }
+
+            if let Some(other) = zones.iter().find(|z| z.start <= end && start <= z.end) {
+                return Err(Error::InvalidZones(format!(
+                    "zone {}-{} overlaps zone {}-{}",
+                    start, end, other.start, other.end
+                )));
+            }
+            zones.push(Zone { start, end, params });
+        }
+    }
+    Ok(zones)
+}
+
+fn parse_frame(token: &str) -> Result<usize, Error> {
+    token
+        .parse()
+        .map_err(|_| Error::InvalidZones(format!("'{}' is not a valid frame number", token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str, num_frames: usize) -> Result<Vec<(usize, usize)>, String> {
+        parse_zones(input, &Params::default(), num_frames)
+            .map(|zones| zones.iter().map(|zone| (zone.start, zone.end)).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn accepts_zones_within_the_clip() {
+        assert_eq!(
+            parse("0 9 sw=2; 10 19 # the rest\n", 20),
+            Ok(vec![(0, 9), (10, 19)])
+        );
+    }
+
+    #[test]
+    fn rejects_zones_past_the_end() {
+        assert_eq!(
+            parse("20 25 sw=2", 20),
+            Err("Mpeg2Stinx: invalid zones: zone 20-25 ends past the last frame, 19".to_owned())
+        );
+        assert!(parse("10 20", 20).is_err());
+        assert!(parse("0 0", 0).is_err());
+    }
+
+    #[test]
+    fn rejects_overlapping_zones() {
+        assert!(parse("0 9; 9 12", 20).is_err());
+    }
+}